@@ -0,0 +1,325 @@
+// Read-only JSON API: current devices, recent events and session statistics
+
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use chrono::{DateTime, Local, SecondsFormat};
+use serde::Serialize;
+
+use crate::device::MacAddr;
+use crate::http::{Request, Response};
+use crate::output::{format_event_json, get_vendor_from_mac};
+use crate::sink::EventSink;
+use crate::tracker::{Event, TrackedDevice, Tracker};
+
+/// The most recent events, oldest first, bounded to a fixed capacity
+#[derive(Debug)]
+pub struct EventBuffer {
+    events: Mutex<VecDeque<Event>>,
+    capacity: usize,
+}
+
+impl EventBuffer {
+    pub fn new(capacity: usize) -> Self {
+        EventBuffer { events: Mutex::new(VecDeque::with_capacity(capacity)), capacity }
+    }
+
+    pub fn push(&self, event: Event) {
+        let mut events = self.events.lock().unwrap();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        if self.capacity > 0 {
+            events.push_back(event);
+        }
+    }
+
+    /// Buffered events at or after `since`, or all of them
+    pub fn since(&self, since: Option<DateTime<Local>>) -> Vec<Event> {
+        let events = self.events.lock().unwrap();
+        events
+            .iter()
+            .filter(|event| since.is_none_or(|since| event.timestamp >= since))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Keeps delivered events in a shared buffer
+pub struct EventBufferSink {
+    buffer: Arc<EventBuffer>,
+}
+
+impl EventBufferSink {
+    pub fn new(buffer: Arc<EventBuffer>) -> Self {
+        EventBufferSink { buffer }
+    }
+}
+
+impl EventSink for EventBufferSink {
+    fn emit(&mut self, event: &Event) {
+        self.buffer.push(event.clone());
+    }
+}
+
+#[derive(Serialize)]
+struct DeviceJson<'a> {
+    ip: &'a IpAddr,
+    addresses: Vec<&'a IpAddr>,
+    mac: &'a MacAddr,
+    vendor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    services: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    interface: &'a str,
+    state: &'static str,
+    sources: &'a [String],
+    first_seen: String,
+    last_seen: String,
+    last_seen_seconds_ago: u64,
+}
+
+#[derive(Serialize)]
+struct StatsJson {
+    total_devices_seen: usize,
+    peak_concurrent_devices: usize,
+    current_devices: usize,
+    start_time: String,
+    uptime_seconds: u64,
+}
+
+/// Answer `/devices`, `/events?since=` and `/stats`
+pub fn handle(request: &Request, tracker: &Mutex<Tracker>, events: &EventBuffer) -> Response {
+    match request.path.as_str() {
+        "/devices" => devices(tracker),
+        "/events" => {
+            let since = match request.query.get("since") {
+                Some(value) => match DateTime::parse_from_rfc3339(value) {
+                    Ok(since) => Some(since.with_timezone(&Local)),
+                    Err(_) => return Response::error(400, "since must be an RFC 3339 timestamp"),
+                },
+                None => None,
+            };
+            let records: Vec<String> = events.since(since).iter().map(format_event_json).collect();
+            Response::json(format!("[{}]", records.join(",")))
+        }
+        "/stats" => stats(&tracker.lock().unwrap()),
+        _ => Response::not_found(),
+    }
+}
+
+// A tracked device with what it needs from the tracker, copied so the
+// hostname lookups run without holding the tracker lock
+struct DeviceSnapshot {
+    tracked: TrackedDevice,
+    name: Option<String>,
+    first_seen: DateTime<Local>,
+    last_seen: DateTime<Local>,
+}
+
+fn devices(tracker: &Mutex<Tracker>) -> Response {
+    let (snapshots, hostnames) = {
+        let tracker = tracker.lock().unwrap();
+        let snapshots: Vec<DeviceSnapshot> = tracker
+            .devices()
+            .map(|tracked| DeviceSnapshot {
+                name: tracker.inventory().get(&tracked.device.mac_address).and_then(|entry| entry.name.clone()),
+                first_seen: tracker.wall_time(tracked.first_seen),
+                last_seen: tracker.wall_time(tracked.last_seen),
+                tracked: tracked.clone(),
+            })
+            .collect();
+        (snapshots, Arc::clone(tracker.hostnames()))
+    };
+
+    let now = Instant::now();
+    let mut devices: Vec<DeviceJson> = snapshots
+        .iter()
+        .map(|snapshot| {
+            let tracked = &snapshot.tracked;
+            let device = &tracked.device;
+            DeviceJson {
+                ip: &device.ip_address,
+                addresses: tracked.addresses.keys().collect(),
+                mac: &device.mac_address,
+                vendor: get_vendor_from_mac(&device.mac_address),
+                hostname: hostnames.lookup(device),
+                client_id: device.client_id.as_deref(),
+                services: &device.services,
+                name: snapshot.name.as_deref(),
+                interface: &device.interface,
+                state: device.state.as_str(),
+                sources: &device.sources,
+                first_seen: format_time(snapshot.first_seen),
+                last_seen: format_time(snapshot.last_seen),
+                last_seen_seconds_ago: now.saturating_duration_since(tracked.last_seen).as_secs(),
+            }
+        })
+        .collect();
+    devices.sort_by(|a, b| (a.interface, a.ip).cmp(&(b.interface, b.ip)));
+
+    Response::json(serde_json::to_string(&devices).expect("device list is always serializable"))
+}
+
+fn stats(tracker: &Tracker) -> Response {
+    let stats = tracker.stats();
+    let json = StatsJson {
+        total_devices_seen: stats.total_devices_seen,
+        peak_concurrent_devices: stats.peak_concurrent_devices,
+        current_devices: tracker.devices().count(),
+        start_time: format_time(tracker.wall_time(stats.start_time)),
+        uptime_seconds: stats.start_time.elapsed().as_secs(),
+    };
+    Response::json(serde_json::to_string(&json).expect("stats are always serializable"))
+}
+
+fn format_time(time: DateTime<Local>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::time::Duration;
+
+    use serde_json::Value;
+
+    use crate::device::{Device, NeighState};
+    use crate::hostname::{HostnameConfig, Hostnames};
+    use crate::inventory::Inventory;
+    use crate::tracker::{EventKind, TrackerConfig};
+
+    fn device(ip: &str, mac: &str, interface: &str) -> Device {
+        Device::new(ip.parse().unwrap(), mac.parse().unwrap(), interface.to_string())
+            .with_state(NeighState::Reachable)
+            .seen_by("netlink")
+    }
+
+    fn get(path: &str, query: &[(&str, &str)]) -> Request {
+        let query: HashMap<String, String> =
+            query.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+        Request { method: "GET".to_string(), path: path.to_string(), query }
+    }
+
+    fn body(response: &Response) -> Value {
+        assert_eq!(response.content_type, "application/json");
+        serde_json::from_str(&response.body).unwrap()
+    }
+
+    fn event(kind: EventKind, ip: &str, timestamp: &str) -> Event {
+        let timestamp = DateTime::parse_from_rfc3339(timestamp).unwrap().with_timezone(&Local);
+        Event::new(kind, device(ip, "02:00:00:00:00:01", "eth0"), timestamp)
+    }
+
+    #[test]
+    fn devices_are_listed_by_interface_then_address() {
+        let hosts = std::env::temp_dir().join(format!("netneighbor-{}-api-hosts", std::process::id()));
+        fs::write(&hosts, "192.168.1.10 laptop\n").unwrap();
+
+        let start = Instant::now();
+        let mut tracker = Tracker::with_origin(TrackerConfig::default(), start, Local::now());
+        tracker.set_inventory(Inventory::parse("[[device]]\nmac = \"02:00:00:00:00:01\"\nname = \"Work laptop\"\n").unwrap());
+        tracker.set_hostnames(Hostnames::new(HostnameConfig { hosts_file: Some(hosts.clone()), ..HostnameConfig::default() }));
+        tracker.update(
+            vec![
+                device("192.168.1.20", "02:00:00:00:00:02", "wlan0"),
+                device("192.168.1.10", "02:00:00:00:00:01", "eth0"),
+                device("192.168.1.9", "02:00:00:00:00:03", "eth0"),
+            ],
+            start,
+        );
+        let tracker = Mutex::new(tracker);
+
+        let response = handle(&get("/devices", &[]), &tracker, &EventBuffer::new(10));
+        fs::remove_file(&hosts).unwrap();
+        assert_eq!(response.status, 200);
+        let devices = body(&response);
+        let ips: Vec<&str> = devices.as_array().unwrap().iter().map(|device| device["ip"].as_str().unwrap()).collect();
+        assert_eq!(ips, ["192.168.1.9", "192.168.1.10", "192.168.1.20"]);
+
+        let laptop = &devices[1];
+        assert_eq!(laptop["mac"], "02:00:00:00:00:01");
+        assert_eq!(laptop["addresses"], serde_json::json!(["192.168.1.10"]));
+        assert_eq!(laptop["hostname"], "laptop");
+        assert_eq!(laptop["name"], "Work laptop");
+        assert_eq!(laptop["interface"], "eth0");
+        assert_eq!(laptop["state"], "REACHABLE");
+        assert_eq!(laptop["sources"], serde_json::json!(["netlink"]));
+        assert!(laptop["first_seen"].is_string());
+        assert_eq!(laptop["first_seen"], laptop["last_seen"]);
+        assert!(laptop["last_seen_seconds_ago"].is_u64());
+
+        // Optional fields are left out rather than null
+        let other = devices[0].as_object().unwrap();
+        for field in ["hostname", "name", "client_id", "services"] {
+            assert!(!other.contains_key(field), "{field} should be omitted");
+        }
+    }
+
+    #[test]
+    fn events_are_filtered_by_since() {
+        let tracker = Mutex::new(Tracker::new(TrackerConfig::default()));
+        let events = EventBuffer::new(10);
+        events.push(event(EventKind::Connected, "192.168.1.10", "2024-05-01T10:00:00Z"));
+        events.push(event(EventKind::Disconnected, "192.168.1.10", "2024-05-01T11:00:00Z"));
+
+        let all = body(&handle(&get("/events", &[]), &tracker, &events));
+        assert_eq!(all.as_array().unwrap().len(), 2);
+        assert_eq!(all[0]["event"], "CONNECTED");
+        assert_eq!(all[0]["ip"], "192.168.1.10");
+
+        let recent = body(&handle(&get("/events", &[("since", "2024-05-01T11:00:00Z")]), &tracker, &events));
+        assert_eq!(recent.as_array().unwrap().len(), 1);
+        assert_eq!(recent[0]["event"], "DISCONNECTED");
+
+        let response = handle(&get("/events", &[("since", "yesterday")]), &tracker, &events);
+        assert_eq!(response.status, 400);
+        assert!(body(&response)["error"].as_str().unwrap().contains("RFC 3339"));
+    }
+
+    #[test]
+    fn stats_count_current_devices() {
+        let start = Instant::now();
+        let mut tracker = Tracker::with_origin(TrackerConfig::default(), start, Local::now());
+        tracker.update(vec![device("192.168.1.10", "02:00:00:00:00:01", "eth0")], start);
+        tracker.update(Vec::new(), start + Duration::from_secs(11));
+        tracker.update(vec![device("192.168.1.11", "02:00:00:00:00:02", "eth0")], start + Duration::from_secs(12));
+
+        let stats = body(&handle(&get("/stats", &[]), &Mutex::new(tracker), &EventBuffer::new(10)));
+        assert_eq!(stats["total_devices_seen"], 2);
+        assert_eq!(stats["peak_concurrent_devices"], 1);
+        assert_eq!(stats["current_devices"], 1);
+        assert!(stats["start_time"].is_string());
+        assert!(stats["uptime_seconds"].is_u64());
+    }
+
+    #[test]
+    fn unknown_paths_are_not_found() {
+        let tracker = Mutex::new(Tracker::new(TrackerConfig::default()));
+        let response = handle(&get("/device", &[]), &tracker, &EventBuffer::new(10));
+        assert_eq!(response.status, 404);
+    }
+
+    #[test]
+    fn event_buffer_keeps_the_most_recent() {
+        let events = EventBuffer::new(2);
+        events.push(event(EventKind::Connected, "192.168.1.10", "2024-05-01T10:00:00Z"));
+        events.push(event(EventKind::Connected, "192.168.1.11", "2024-05-01T10:01:00Z"));
+        events.push(event(EventKind::Connected, "192.168.1.12", "2024-05-01T10:02:00Z"));
+        let ips: Vec<String> = events.since(None).iter().map(|event| event.device.ip_address.to_string()).collect();
+        assert_eq!(ips, ["192.168.1.11", "192.168.1.12"]);
+
+        let nothing = EventBuffer::new(0);
+        nothing.push(event(EventKind::Connected, "192.168.1.10", "2024-05-01T10:00:00Z"));
+        assert!(nothing.since(None).is_empty());
+    }
+}
@@ -0,0 +1,532 @@
+// Passive discovery from captured traffic: ARP (including gratuitous ARP),
+// IPv6 neighbor discovery and DHCP reveal the MAC/IP bindings of devices that
+// never talk to this host, and so never show up in its neighbor table
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::device::{self, Device, MacAddr, NeighState};
+use crate::diag;
+use crate::netlink::interface_name;
+use crate::packet::{
+    self, ArpPacket, PacketSocket, ETH_P_ALL, ETH_P_ARP, ETH_P_IP, ETH_P_IPV6, ICMPV6_NEIGHBOR_ADVERTISEMENT,
+    ICMPV6_NEIGHBOR_SOLICITATION, ICMPV6_ROUTER_SOLICITATION, IPPROTO_ICMPV6, IPPROTO_UDP,
+};
+use crate::source::NeighborSource;
+
+/// Source name of sightings from captured traffic
+pub const SOURCE: &str = "capture";
+
+const DHCP_SERVER_PORT: u16 = 67;
+const DHCP_CLIENT_PORT: u16 = 68;
+const DHCP_MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+const DHCP_OPTIONS_OFFSET: usize = 240;
+const DHCP_MESSAGE_TYPE: u8 = 53;
+const DHCP_DECLINE: u8 = 4;
+const DHCP_ACK: u8 = 5;
+const DHCP_RELEASE: u8 = 7;
+
+// NDP option types carrying a link-layer address
+const NDP_SOURCE_LINK_ADDRESS: u8 = 1;
+const NDP_TARGET_LINK_ADDRESS: u8 = 2;
+
+// How often this host's own MAC addresses are re-read
+const LOCAL_REFRESH: Duration = Duration::from_secs(30);
+
+/// Devices revealed by one Ethernet frame, as REACHABLE sightings on `interface`
+pub fn sightings(frame: &[u8], interface: &str) -> Vec<Device> {
+    let Some((ethertype, payload)) = packet::parse_ethernet(frame) else {
+        return Vec::new();
+    };
+    let frame_source: [u8; 6] = frame[6..12].try_into().unwrap();
+
+    let mut bindings: Vec<(IpAddr, [u8; 6])> = Vec::new();
+    match ethertype {
+        // Requests, replies and gratuitous announcements all carry the sender's binding
+        ETH_P_ARP => {
+            if let Some(arp) = ArpPacket::parse(payload) {
+                bindings.push((IpAddr::V4(arp.sender_ip), arp.sender_mac));
+            }
+        }
+        ETH_P_IPV6 => {
+            if let Some((header, icmp)) = packet::parse_ipv6(payload)
+                && header.protocol == IPPROTO_ICMPV6
+                && header.hop_limit == 255
+            {
+                bindings.extend(ndp_bindings(header.src, icmp, frame_source));
+            }
+        }
+        ETH_P_IP => {
+            if let Some((header, segment)) = packet::parse_ipv4(payload)
+                && header.protocol == IPPROTO_UDP
+                && let Some((src_port, dst_port, message)) = packet::parse_udp(segment)
+                && [src_port, dst_port].iter().all(|port| [DHCP_SERVER_PORT, DHCP_CLIENT_PORT].contains(port))
+            {
+                bindings.extend(dhcp_binding(message));
+            }
+        }
+        _ => {}
+    }
+
+    let mut seen = HashSet::new();
+    bindings
+        .into_iter()
+        .filter(|(ip, mac)| device::is_neighbor_address(ip) && MacAddr(*mac).is_unicast() && seen.insert((*ip, *mac)))
+        .map(|(ip, mac)| Device::new(ip, MacAddr(mac), interface.to_string()).with_state(NeighState::Reachable).seen_by(SOURCE))
+        .collect()
+}
+
+// Router/neighbor solicitations and advertisements bind their source address
+// to the source link-layer option (or the frame's source); unsolicited
+// advertisements also announce their target address
+fn ndp_bindings(src: Ipv6Addr, icmp: &[u8], frame_source: [u8; 6]) -> Vec<(IpAddr, [u8; 6])> {
+    let Some(&kind) = icmp.first() else {
+        return Vec::new();
+    };
+    if !(ICMPV6_ROUTER_SOLICITATION..=ICMPV6_NEIGHBOR_ADVERTISEMENT).contains(&kind) {
+        return Vec::new();
+    }
+
+    // Fixed part before the options: RS 8, RA 16, NS/NA 24 bytes
+    let options_offset = match kind {
+        ICMPV6_ROUTER_SOLICITATION => 8,
+        ICMPV6_NEIGHBOR_SOLICITATION | ICMPV6_NEIGHBOR_ADVERTISEMENT => 24,
+        _ => 16,
+    };
+    let Some(options) = icmp.get(options_offset..) else {
+        return Vec::new();
+    };
+
+    let mut bindings = vec![(
+        IpAddr::V6(src),
+        ndp_link_address(options, NDP_SOURCE_LINK_ADDRESS).unwrap_or(frame_source),
+    )];
+    if kind == ICMPV6_NEIGHBOR_ADVERTISEMENT {
+        let target: [u8; 16] = icmp[8..24].try_into().unwrap();
+        let mac = ndp_link_address(options, NDP_TARGET_LINK_ADDRESS).unwrap_or(frame_source);
+        bindings.push((IpAddr::V6(target.into()), mac));
+    }
+    bindings
+}
+
+fn ndp_link_address(mut options: &[u8], wanted: u8) -> Option<[u8; 6]> {
+    while options.len() >= 8 {
+        let len = options[1] as usize * 8;
+        if len == 0 || len > options.len() {
+            return None;
+        }
+        if options[0] == wanted {
+            return options[2..8].try_into().ok();
+        }
+        options = &options[len..];
+    }
+    None
+}
+
+// The address a DHCP server acknowledged, or one a client already holds
+// (renewals and informs); releases and declines give the address up
+fn dhcp_binding(message: &[u8]) -> Option<(IpAddr, [u8; 6])> {
+    // Ethernet hardware addresses only
+    if message.len() < DHCP_OPTIONS_OFFSET || message[1] != 1 || message[2] != 6 || message[236..240] != DHCP_MAGIC_COOKIE {
+        return None;
+    }
+    let client_ip = Ipv4Addr::new(message[12], message[13], message[14], message[15]);
+    let your_ip = Ipv4Addr::new(message[16], message[17], message[18], message[19]);
+    let client_mac: [u8; 6] = message[28..34].try_into().ok()?;
+    let message_type = dhcp_message_type(&message[DHCP_OPTIONS_OFFSET..])?;
+
+    match message[0] {
+        1 if ![DHCP_DECLINE, DHCP_RELEASE].contains(&message_type) => Some((IpAddr::V4(client_ip), client_mac)),
+        2 if message_type == DHCP_ACK => Some((IpAddr::V4(your_ip), client_mac)),
+        _ => None,
+    }
+}
+
+fn dhcp_message_type(mut options: &[u8]) -> Option<u8> {
+    while let Some(&code) = options.first() {
+        match code {
+            0 => options = &options[1..],
+            255 => return None,
+            _ => {
+                let len = *options.get(1)? as usize;
+                let value = options.get(2..2 + len)?;
+                if code == DHCP_MESSAGE_TYPE {
+                    return value.first().copied();
+                }
+                options = &options[2 + len..];
+            }
+        }
+    }
+    None
+}
+
+/// BPF program passing ARP, ICMPv6 router/neighbor discovery and DHCP frames
+fn discovery_filter() -> [libc::sock_filter; 22] {
+    const LOAD_HALF: u32 = libc::BPF_LD | libc::BPF_H | libc::BPF_ABS;
+    const LOAD_BYTE: u32 = libc::BPF_LD | libc::BPF_B | libc::BPF_ABS;
+    const LOAD_IP_HEADER_LEN: u32 = libc::BPF_LDX | libc::BPF_B | libc::BPF_MSH;
+    const LOAD_HALF_AFTER_IP: u32 = libc::BPF_LD | libc::BPF_H | libc::BPF_IND;
+    const JUMP_EQ: u32 = libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K;
+    const JUMP_GE: u32 = libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K;
+    const JUMP_GT: u32 = libc::BPF_JMP | libc::BPF_JGT | libc::BPF_K;
+    const JUMP_SET: u32 = libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K;
+    const RETURN: u32 = libc::BPF_RET | libc::BPF_K;
+    let op = packet::bpf_op;
+
+    // Jump offsets count instructions to skip: 20 is "accept", 21 is "drop"
+    [
+        /* 0 */ op(LOAD_HALF, 12, 0, 0),
+        /* 1 */ op(JUMP_EQ, ETH_P_ARP as u32, 18, 0),
+        /* 2 */ op(JUMP_EQ, ETH_P_IPV6 as u32, 0, 5),
+        /* 3 */ op(LOAD_BYTE, 20, 0, 0),
+        /* 4 */ op(JUMP_EQ, IPPROTO_ICMPV6 as u32, 0, 16),
+        /* 5 */ op(LOAD_BYTE, 54, 0, 0),
+        /* 6 */ op(JUMP_GE, ICMPV6_ROUTER_SOLICITATION as u32, 0, 14),
+        /* 7 */ op(JUMP_GT, ICMPV6_NEIGHBOR_ADVERTISEMENT as u32, 13, 12),
+        /* 8 */ op(JUMP_EQ, ETH_P_IP as u32, 0, 12),
+        /* 9 */ op(LOAD_BYTE, 23, 0, 0),
+        /* 10 */ op(JUMP_EQ, IPPROTO_UDP as u32, 0, 10),
+        /* 11 */ op(LOAD_HALF, 20, 0, 0),
+        /* 12 */ op(JUMP_SET, 0x1fff, 8, 0),
+        /* 13 */ op(LOAD_IP_HEADER_LEN, 14, 0, 0),
+        /* 14 */ op(LOAD_HALF_AFTER_IP, 14, 0, 0),
+        /* 15 */ op(JUMP_EQ, DHCP_SERVER_PORT as u32, 4, 0),
+        /* 16 */ op(JUMP_EQ, DHCP_CLIENT_PORT as u32, 3, 0),
+        /* 17 */ op(LOAD_HALF_AFTER_IP, 16, 0, 0),
+        /* 18 */ op(JUMP_EQ, DHCP_SERVER_PORT as u32, 1, 0),
+        /* 19 */ op(JUMP_EQ, DHCP_CLIENT_PORT as u32, 0, 1),
+        /* 20 */ op(RETURN, u32::MAX, 0, 0),
+        /* 21 */ op(RETURN, 0, 0, 0),
+    ]
+}
+
+type SightingKey = (IpAddr, MacAddr, String);
+
+// Sightings keyed by (ip, mac, interface): each with when it was last heard,
+// and those heard since they were last taken
+#[derive(Default)]
+struct Sightings {
+    heard: HashMap<SightingKey, (Device, Instant)>,
+    new: HashMap<SightingKey, Device>,
+}
+
+// MAC addresses of this host's interfaces, whose own traffic isn't a device
+#[derive(Default)]
+struct LocalMacs {
+    macs: HashSet<MacAddr>,
+    checked: Option<Instant>,
+}
+
+impl LocalMacs {
+    fn refresh(&mut self, now: Instant) {
+        if self.checked.is_some_and(|checked| now.duration_since(checked) < LOCAL_REFRESH) {
+            return;
+        }
+        self.checked = Some(now);
+        // Keep the addresses last read if the interfaces can't be listed
+        if let Ok(interfaces) = packet::local_interfaces() {
+            self.macs = interfaces.iter().filter_map(|interface| interface.mac.map(MacAddr)).collect();
+        }
+    }
+
+    fn without_local(&mut self, devices: impl Iterator<Item = Device>, now: Instant) -> Vec<Device> {
+        self.refresh(now);
+        devices.filter(|device| !self.macs.contains(&device.mac_address)).collect()
+    }
+}
+
+/// Listens for discovery traffic on a background thread and keeps the devices
+/// it reveals, with when each was last heard
+pub struct PacketCapture {
+    sightings: Arc<Mutex<Sightings>>,
+    local: Mutex<LocalMacs>,
+}
+
+impl PacketCapture {
+    /// Open a packet socket and start capturing on every interface, or only on `interface`
+    pub fn start(interface: Option<String>) -> io::Result<Self> {
+        let socket = PacketSocket::open(ETH_P_ALL)?;
+        socket.attach_filter(&discovery_filter())?;
+        let sightings = Arc::new(Mutex::new(Sightings::default()));
+
+        let capture_sightings = Arc::clone(&sightings);
+        thread::spawn(move || capture(&socket, &capture_sightings, interface.as_deref()));
+
+        Ok(PacketCapture { sightings, local: Mutex::default() })
+    }
+
+    /// Devices heard within the last `ttl`, except this host's own interfaces.
+    /// They include the ones `take_new` would return.
+    pub fn current(&self, ttl: Duration) -> Vec<Device> {
+        let now = Instant::now();
+        let mut sightings = self.sightings.lock().unwrap();
+        sightings.new.clear();
+        sightings.heard.retain(|_, (_, heard)| now.duration_since(*heard) <= ttl);
+        let heard = sightings.heard.values().map(|(device, _)| device.clone());
+        self.local.lock().unwrap().without_local(heard, now)
+    }
+
+    /// Devices heard since the last call or `current`, except this host's own interfaces
+    pub fn take_new(&self) -> Vec<Device> {
+        let new = std::mem::take(&mut self.sightings.lock().unwrap().new);
+        self.local.lock().unwrap().without_local(new.into_values(), Instant::now())
+    }
+}
+
+fn capture(
+    socket: &PacketSocket,
+    collected: &Mutex<Sightings>,
+    interface_filter: Option<&str>,
+) {
+    let mut buf = [0u8; 2048];
+    let mut interface_names = HashMap::new();
+
+    loop {
+        let (len, received_on) = match socket.recv(&mut buf) {
+            Ok(received) => received,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                diag::warn(&format!("Packet capture: receive failed: {}", e));
+                return;
+            }
+        };
+        if received_on.outgoing {
+            continue;
+        }
+        let Some(interface) = interface_name(received_on.ifindex as u32, &mut interface_names) else {
+            continue;
+        };
+        if interface_filter.is_some_and(|filter| filter != interface) {
+            continue;
+        }
+
+        let found = sightings(&buf[..len], &interface);
+        if found.is_empty() {
+            continue;
+        }
+        let now = Instant::now();
+        let mut collected = collected.lock().unwrap();
+        for device in found {
+            let key = (device.ip_address, device.mac_address, device.interface.clone());
+            collected.new.insert(key.clone(), device.clone());
+            collected.heard.insert(key, (device, now));
+        }
+    }
+}
+
+/// Devices seen in captured traffic. Each stays in the snapshot until `ttl`
+/// has passed since it was last heard, since quiet devices may only broadcast
+/// once every few minutes.
+pub struct CaptureSource {
+    capture: PacketCapture,
+    ttl: Duration,
+}
+
+impl CaptureSource {
+    pub fn new(capture: PacketCapture, ttl: Duration) -> Self {
+        CaptureSource { capture, ttl }
+    }
+}
+
+impl NeighborSource for CaptureSource {
+    fn name(&self) -> &str {
+        SOURCE
+    }
+
+    fn scan(&mut self) -> Result<Vec<Device>, Box<dyn Error>> {
+        Ok(self.capture.current(self.ttl))
+    }
+
+    fn take_heard(&mut self) -> Vec<Device> {
+        self.capture.take_new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOST: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+    const CLIENT: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x10];
+    const OTHER: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x20];
+
+    fn found(frame: &[u8]) -> Vec<(String, String)> {
+        sightings(frame, "eth0").iter().map(|device| (device.ip_address.to_string(), device.mac_address.to_string())).collect()
+    }
+
+    fn binding(ip: &str, mac: [u8; 6]) -> (String, String) {
+        (ip.to_string(), MacAddr(mac).to_string())
+    }
+
+    fn ipv6_frame(src: &str, icmp: &[u8], hop_limit: u8) -> Vec<u8> {
+        let src: Ipv6Addr = src.parse().unwrap();
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend_from_slice(&(icmp.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[IPPROTO_ICMPV6, hop_limit]);
+        packet.extend_from_slice(&src.octets());
+        packet.extend_from_slice(&"ff02::1".parse::<Ipv6Addr>().unwrap().octets());
+        packet.extend_from_slice(icmp);
+        packet::ethernet_frame([0x33, 0x33, 0, 0, 0, 1], CLIENT, ETH_P_IPV6, &packet)
+    }
+
+    // Unsolicited Neighbor Advertisement for `target` with a target link-layer option
+    fn advertisement(target: &str, mac: [u8; 6]) -> Vec<u8> {
+        let mut icmp = vec![ICMPV6_NEIGHBOR_ADVERTISEMENT, 0, 0, 0, 0x20, 0, 0, 0];
+        icmp.extend_from_slice(&target.parse::<Ipv6Addr>().unwrap().octets());
+        icmp.extend_from_slice(&[NDP_TARGET_LINK_ADDRESS, 1]);
+        icmp.extend_from_slice(&mac);
+        icmp
+    }
+
+    fn dhcp(op: u8, message_type: u8, client_ip: [u8; 4], your_ip: [u8; 4]) -> Vec<u8> {
+        let mut message = vec![0; DHCP_OPTIONS_OFFSET];
+        message[0] = op;
+        message[1] = 1;
+        message[2] = 6;
+        message[12..16].copy_from_slice(&client_ip);
+        message[16..20].copy_from_slice(&your_ip);
+        message[28..34].copy_from_slice(&CLIENT);
+        message[236..240].copy_from_slice(&DHCP_MAGIC_COOKIE);
+        message.extend_from_slice(&[DHCP_MESSAGE_TYPE, 1, message_type, 255]);
+        message
+    }
+
+    fn udp_frame(src_port: u16, dst_port: u16, message: &[u8]) -> Vec<u8> {
+        let mut segment = Vec::new();
+        segment.extend_from_slice(&src_port.to_be_bytes());
+        segment.extend_from_slice(&dst_port.to_be_bytes());
+        segment.extend_from_slice(&((8 + message.len()) as u16).to_be_bytes());
+        segment.extend_from_slice(&[0, 0]);
+        segment.extend_from_slice(message);
+
+        let mut packet = vec![0x45, 0];
+        packet.extend_from_slice(&((20 + segment.len()) as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 0, 64, IPPROTO_UDP, 0, 0, 192, 168, 1, 1, 255, 255, 255, 255]);
+        packet.extend_from_slice(&segment);
+        packet::ethernet_frame(packet::BROADCAST_MAC, HOST, ETH_P_IP, &packet)
+    }
+
+    #[test]
+    fn arp_reveals_the_sender() {
+        let frame = ArpPacket::request_frame(CLIENT, Ipv4Addr::new(192, 168, 1, 10), Ipv4Addr::new(192, 168, 1, 1), packet::BROADCAST_MAC);
+        let devices = sightings(&frame, "eth0");
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].ip_address.to_string(), "192.168.1.10");
+        assert_eq!(devices[0].mac_address, MacAddr(CLIENT));
+        assert_eq!(devices[0].interface, "eth0");
+        assert_eq!(devices[0].state, NeighState::Reachable);
+        assert_eq!(devices[0].sources, [SOURCE]);
+
+        // Address conflict probes have no sender address yet
+        let probe = ArpPacket::request_frame(CLIENT, Ipv4Addr::UNSPECIFIED, Ipv4Addr::new(192, 168, 1, 10), packet::BROADCAST_MAC);
+        assert!(found(&probe).is_empty());
+        let multicast = ArpPacket::request_frame([0x01, 0x00, 0x5e, 0, 0, 1], Ipv4Addr::new(192, 168, 1, 10), Ipv4Addr::new(192, 168, 1, 1), packet::BROADCAST_MAC);
+        assert!(found(&multicast).is_empty());
+    }
+
+    #[test]
+    fn neighbor_discovery_reveals_sources_and_targets() {
+        let solicitation = packet::neighbor_solicitation_frame(CLIENT, "fe80::10".parse().unwrap(), "fe80::1".parse().unwrap(), HOST);
+        assert_eq!(found(&solicitation), [binding("fe80::10", CLIENT)]);
+
+        // The source has no option here, so the frame's source MAC is used
+        let frame = ipv6_frame("fe80::10", &advertisement("2001:db8::20", OTHER), 255);
+        assert_eq!(found(&frame), [binding("fe80::10", CLIENT), binding("2001:db8::20", OTHER)]);
+
+        // Neighbor discovery is never forwarded, so anything else is spoofed or broken
+        assert!(found(&ipv6_frame("fe80::10", &advertisement("2001:db8::20", OTHER), 64)).is_empty());
+        // Duplicate address detection solicits from the unspecified address
+        assert!(found(&ipv6_frame("::", &[ICMPV6_NEIGHBOR_SOLICITATION, 0, 0, 0, 0, 0, 0, 0], 255)).is_empty());
+    }
+
+    #[test]
+    fn ndp_bindings_by_message_type() {
+        let src: Ipv6Addr = "fe80::10".parse().unwrap();
+        let v6 = |ip: &str| IpAddr::V6(ip.parse().unwrap());
+
+        let solicitation = [ICMPV6_ROUTER_SOLICITATION, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(ndp_bindings(src, &solicitation, CLIENT), [(v6("fe80::10"), CLIENT)]);
+        let mut with_option = solicitation.to_vec();
+        with_option.extend_from_slice(&[NDP_SOURCE_LINK_ADDRESS, 1]);
+        with_option.extend_from_slice(&OTHER);
+        assert_eq!(ndp_bindings(src, &with_option, CLIENT), [(v6("fe80::10"), OTHER)]);
+
+        // Router advertisements carry options after 16 bytes
+        let mut advertisement = vec![134, 0, 0, 0, 64, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        advertisement.extend_from_slice(&[NDP_SOURCE_LINK_ADDRESS, 1]);
+        advertisement.extend_from_slice(&OTHER);
+        assert_eq!(ndp_bindings(src, &advertisement, CLIENT), [(v6("fe80::10"), OTHER)]);
+
+        // Redirects, echo requests and truncated messages reveal nothing
+        assert!(ndp_bindings(src, &[137; 40], CLIENT).is_empty());
+        assert!(ndp_bindings(src, &[128, 0, 0, 0], CLIENT).is_empty());
+        assert!(ndp_bindings(src, &[ICMPV6_NEIGHBOR_SOLICITATION, 0, 0, 0], CLIENT).is_empty());
+        assert!(ndp_bindings(src, &[], CLIENT).is_empty());
+    }
+
+    #[test]
+    fn ndp_options_with_zero_length_stop_the_search() {
+        let mut options = vec![NDP_SOURCE_LINK_ADDRESS, 0, 0, 0, 0, 0, 0, 0];
+        options.extend_from_slice(&[NDP_TARGET_LINK_ADDRESS, 1]);
+        options.extend_from_slice(&OTHER);
+        assert_eq!(ndp_link_address(&options, NDP_TARGET_LINK_ADDRESS), None);
+        assert_eq!(ndp_link_address(&options[8..], NDP_TARGET_LINK_ADDRESS), Some(OTHER));
+    }
+
+    #[test]
+    fn dhcp_ack_reveals_the_granted_address() {
+        let ack = dhcp(2, DHCP_ACK, [0; 4], [192, 168, 1, 50]);
+        assert_eq!(found(&udp_frame(DHCP_SERVER_PORT, DHCP_CLIENT_PORT, &ack)), [binding("192.168.1.50", CLIENT)]);
+        // Only DHCP ports are looked at
+        assert!(found(&udp_frame(5353, DHCP_CLIENT_PORT, &ack)).is_empty());
+    }
+
+    #[test]
+    fn dhcp_binding_by_message_type() {
+        let v4 = |a, b, c, d| IpAddr::V4(Ipv4Addr::new(a, b, c, d));
+        // Renewal from a client holding its address
+        assert_eq!(dhcp_binding(&dhcp(1, 3, [192, 168, 1, 50], [0; 4])), Some((v4(192, 168, 1, 50), CLIENT)));
+        assert_eq!(dhcp_binding(&dhcp(2, DHCP_ACK, [0; 4], [192, 168, 1, 51])), Some((v4(192, 168, 1, 51), CLIENT)));
+
+        // Offers aren't accepted yet; releases and declines give the address up
+        assert_eq!(dhcp_binding(&dhcp(2, 2, [0; 4], [192, 168, 1, 51])), None);
+        assert_eq!(dhcp_binding(&dhcp(1, DHCP_RELEASE, [192, 168, 1, 50], [0; 4])), None);
+        assert_eq!(dhcp_binding(&dhcp(1, DHCP_DECLINE, [192, 168, 1, 50], [0; 4])), None);
+
+        let mut token_ring = dhcp(2, DHCP_ACK, [0; 4], [192, 168, 1, 51]);
+        token_ring[1] = 6;
+        assert_eq!(dhcp_binding(&token_ring), None);
+        let mut bootp = dhcp(2, DHCP_ACK, [0; 4], [192, 168, 1, 51]);
+        bootp[236] = 0;
+        assert_eq!(dhcp_binding(&bootp), None);
+        assert_eq!(dhcp_binding(&bootp[..DHCP_OPTIONS_OFFSET - 1]), None);
+    }
+
+    #[test]
+    fn dhcp_message_type_skips_padding() {
+        assert_eq!(dhcp_message_type(&[0, 0, 12, 2, b'p', b'c', DHCP_MESSAGE_TYPE, 1, DHCP_ACK, 255]), Some(DHCP_ACK));
+        assert_eq!(dhcp_message_type(&[12, 2, b'p', b'c', 255, DHCP_MESSAGE_TYPE, 1, DHCP_ACK]), None);
+        assert_eq!(dhcp_message_type(&[DHCP_MESSAGE_TYPE, 2, DHCP_ACK]), None);
+        assert_eq!(dhcp_message_type(&[]), None);
+    }
+
+    #[test]
+    fn local_macs_are_reread_after_the_refresh_interval() {
+        let start = Instant::now();
+        let mut local = LocalMacs { macs: HashSet::from([MacAddr(HOST)]), checked: Some(start) };
+        let devices = || {
+            [HOST, CLIENT].into_iter().map(|mac| Device::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10)), MacAddr(mac), "eth0".to_string()))
+        };
+        let macs = |devices: Vec<Device>| devices.iter().map(|device| device.mac_address).collect::<Vec<_>>();
+
+        assert_eq!(macs(local.without_local(devices(), start + Duration::from_secs(1))), [MacAddr(CLIENT)]);
+        // HOST isn't really one of this machine's interfaces
+        assert_eq!(macs(local.without_local(devices(), start + LOCAL_REFRESH)), [MacAddr(HOST), MacAddr(CLIENT)]);
+        assert_eq!(local.checked, Some(start + LOCAL_REFRESH));
+    }
+}
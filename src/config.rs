@@ -0,0 +1,431 @@
+// Layered configuration: option values from TOML config files, then from
+// NETNEIGHBOR_* environment variables, then from the command line, each layer
+// overriding the one before. Config files use the long option names as keys.
+// SIGHUP asks a running monitor to read them again.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::parser::ValueSource;
+use clap::builder::Resettable;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+/// System-wide config file, applied first when it exists
+pub const SYSTEM_CONFIG: &str = "/etc/netneighbor/config.toml";
+/// Environment variables are this prefix followed by the option's name in
+/// capitals, e.g. `NETNEIGHBOR_DISCONNECT_TIMEOUT`
+pub const ENV_PREFIX: &str = "NETNEIGHBOR_";
+
+// Options that aren't settings, or that say where the settings are
+const NOT_SETTINGS: [&str; 3] = ["help", "version", "config"];
+
+// Set by the SIGHUP handler, cleared when the reload is picked up
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_reload(_signal: libc::c_int) {
+    RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Have SIGHUP request a configuration reload instead of terminating the
+/// process. Install it after any handler that also claims SIGHUP.
+pub fn reload_on_sighup() -> io::Result<()> {
+    let handler: extern "C" fn(libc::c_int) = request_reload;
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    let previous = unsafe { libc::signal(libc::SIGHUP, handler as libc::sighandler_t) };
+    if previous == libc::SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Whether SIGHUP arrived since the request was last taken
+pub fn reload_pending() -> bool {
+    RELOAD_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Whether SIGHUP arrived since the last call, clearing the request
+pub fn take_reload_request() -> bool {
+    RELOAD_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+/// Where an option's effective value came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    File(PathBuf),
+    Env(String),
+    CommandLine,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => f.write_str("default"),
+            Origin::File(path) => write!(f, "{}", path.display()),
+            Origin::Env(name) => write!(f, "environment {}", name),
+            Origin::CommandLine => f.write_str("command line"),
+        }
+    }
+}
+
+/// The per-user config file: `$XDG_CONFIG_HOME/netneighbor/config.toml`,
+/// or `~/.config/netneighbor/config.toml` without XDG_CONFIG_HOME
+pub fn user_config() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("netneighbor").join("config.toml"))
+}
+
+/// Let every option of `command` be set from its `NETNEIGHBOR_*` variable
+pub fn with_env(command: Command) -> Command {
+    command.mut_args(|arg| {
+        let id = arg.get_id().as_str();
+        if arg.get_long().is_none() || ["help", "version"].contains(&id) {
+            return arg;
+        }
+        let name = format!("{}{}", ENV_PREFIX, id.to_uppercase());
+        arg.env(name)
+    })
+}
+
+/// Command line matches with the config file and environment layers applied
+#[derive(Clone)]
+pub struct Layered {
+    pub matches: ArgMatches,
+    /// Config files that were read, in the order they were applied
+    pub files: Vec<PathBuf>,
+    command: Command,
+    origins: BTreeMap<String, Origin>,
+}
+
+/// An option whose effective value differs between two parses
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    /// The option's long name
+    pub option: String,
+    pub old: Option<toml::Value>,
+    pub new: Option<toml::Value>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: &Option<toml::Value>| value.as_ref().map_or("unset".to_string(), |value| value.to_string());
+        write!(f, "{}: {} -> {}", self.option, show(&self.old), show(&self.new))
+    }
+}
+
+/// Parse `args` against `command`, taking options that aren't given on the
+/// command line or in the environment from the config files: the system one,
+/// the user one, then the file named by the `config` option, each overriding
+/// the ones before. Command line errors exit as `get_matches` does; config
+/// file errors name the file and option.
+pub fn parse(command: Command, args: Vec<OsString>) -> Result<Layered, Box<dyn Error>> {
+    let command = with_env(command);
+    let given = command.clone().get_matches_from(&args);
+
+    let mut files: Vec<PathBuf> = [Some(PathBuf::from(SYSTEM_CONFIG)), user_config()]
+        .into_iter()
+        .flatten()
+        .filter(|path| path.exists())
+        .collect();
+    if let Ok(Some(mut path)) = given.try_get_raw("config") {
+        files.extend(path.next().map(PathBuf::from));
+    }
+
+    // Command line tokens for each option set in a file, the last file setting it winning
+    let mut from_files: BTreeMap<String, (Vec<OsString>, PathBuf)> = BTreeMap::new();
+    for path in &files {
+        let content = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        let table: toml::Table = toml::from_str(&content).map_err(|e| {
+            let line = e.span().map_or(1, |span| content[..span.start].lines().count().max(1));
+            format!("invalid config file {}: line {}: {}", path.display(), line, e.message())
+        })?;
+        for (key, value) in table {
+            let arg = setting(&command, &key).ok_or_else(|| format!("{}: unknown option '{}'", path.display(), key))?;
+            let tokens = arg_tokens(&command, arg, &value).map_err(|e| format!("{}: {}: {}", path.display(), key, e))?;
+            from_files.insert(arg.get_id().to_string(), (tokens, path.clone()));
+        }
+    }
+
+    let mut layered_args: Vec<OsString> = args.first().cloned().into_iter().collect();
+    let mut file_origins = BTreeMap::new();
+    for (id, (tokens, path)) in from_files {
+        if matches!(given.value_source(&id), None | Some(ValueSource::DefaultValue)) {
+            layered_args.extend(tokens);
+            file_origins.insert(id, path);
+        }
+    }
+    layered_args.extend(args.into_iter().skip(1));
+
+    // Values were checked one by one above; this catches combinations, e.g. a missing required option
+    let matches = command
+        .clone()
+        .try_get_matches_from(layered_args)
+        .map_err(|e| format!("invalid configuration: {}", clap_message(&e)))?;
+
+    let mut origins = BTreeMap::new();
+    for arg in settings(&command) {
+        let id = arg.get_id().as_str();
+        let origin = match matches.value_source(id) {
+            Some(ValueSource::CommandLine) => file_origins.get(id).map_or(Origin::CommandLine, |path| Origin::File(path.clone())),
+            Some(ValueSource::EnvVariable) => {
+                Origin::Env(arg.get_env().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default())
+            }
+            _ => Origin::Default,
+        };
+        origins.insert(id.to_string(), origin);
+    }
+
+    Ok(Layered { matches, files, command, origins })
+}
+
+impl Layered {
+    /// Where the effective value of the option with this id came from
+    pub fn origin(&self, id: &str) -> Option<&Origin> {
+        self.origins.get(id)
+    }
+
+    /// The effective value of every option that has one, written as a config
+    /// file, with where each value came from
+    pub fn to_toml(&self) -> String {
+        let mut lines = Vec::new();
+        for arg in settings(&self.command) {
+            let (Some(long), Some(value)) = (arg.get_long(), self.value(arg)) else {
+                continue;
+            };
+            let origin = self.origins.get(arg.get_id().as_str()).unwrap_or(&Origin::Default);
+            lines.push(format!("{} = {}  # {}", long, value, origin));
+        }
+        lines.join("\n")
+    }
+
+    /// Options whose effective value in `newer` differs from this one, in
+    /// command line order
+    pub fn changes(&self, newer: &Layered) -> Vec<Change> {
+        settings(&self.command)
+            .filter_map(|arg| {
+                let (old, new) = (self.value(arg), newer.value(arg));
+                (old != new).then(|| Change { option: arg.get_long().unwrap_or_default().to_string(), old, new })
+            })
+            .collect()
+    }
+
+    // An option's effective value as a config file value
+    fn value(&self, arg: &Arg) -> Option<toml::Value> {
+        let values: Vec<String> = match self.matches.try_get_raw(arg.get_id().as_str()) {
+            Ok(Some(values)) => values.map(|value| value.to_string_lossy().into_owned()).collect(),
+            _ => return None,
+        };
+        match arg.get_action() {
+            ArgAction::SetTrue => Some(toml::Value::Boolean(values.first().is_some_and(|value| value == "true"))),
+            _ if takes_list(arg) => Some(toml::Value::Array(values.iter().map(|value| toml_scalar(value)).collect())),
+            _ => values.first().map(|value| toml_scalar(value)),
+        }
+    }
+}
+
+// Top-level options a config file can set
+fn settings(command: &Command) -> impl Iterator<Item = &Arg> {
+    command
+        .get_arguments()
+        .filter(|arg| arg.get_long().is_some() && !NOT_SETTINGS.contains(&arg.get_id().as_str()))
+}
+
+// The option a config file key names: its long name, with '-' or '_' between words
+fn setting<'a>(command: &'a Command, key: &str) -> Option<&'a Arg> {
+    let key = key.replace('_', "-");
+    settings(command).find(|arg| arg.get_long() == Some(key.as_str()))
+}
+
+fn takes_list(arg: &Arg) -> bool {
+    matches!(arg.get_action(), ArgAction::Append) || arg.get_value_delimiter().is_some()
+}
+
+// Command line tokens setting `arg` to a config file value, each value
+// checked with the option's own parser
+fn arg_tokens(command: &Command, arg: &Arg, value: &toml::Value) -> Result<Vec<OsString>, String> {
+    let long = arg.get_long().unwrap_or_default();
+    if matches!(arg.get_action(), ArgAction::SetTrue) {
+        return match value {
+            toml::Value::Boolean(true) => Ok(vec![OsString::from(format!("--{}", long))]),
+            toml::Value::Boolean(false) => Ok(Vec::new()),
+            _ => Err("expected true or false".to_string()),
+        };
+    }
+
+    let items = match value {
+        toml::Value::Array(items) if takes_list(arg) => items.iter().map(scalar).collect::<Result<Vec<_>, _>>()?,
+        toml::Value::Array(_) => return Err("expected a single value".to_string()),
+        value => vec![scalar(value)?],
+    };
+    let tokens: Vec<OsString> = match arg.get_action() {
+        ArgAction::Append => items.iter().map(|item| format!("--{}={}", long, item).into()).collect(),
+        _ if items.is_empty() => Vec::new(),
+        _ => vec![format!("--{}={}", long, items.join(",")).into()],
+    };
+
+    // Parse the tokens with the option alone, so its own value parser checks them
+    let option = arg
+        .clone()
+        .env(Resettable::Reset)
+        .requires(Resettable::Reset)
+        .conflicts_with(Resettable::Reset)
+        .required(false);
+    Command::new(command.get_name().to_string())
+        .no_binary_name(true)
+        .arg(option)
+        .try_get_matches_from(&tokens)
+        .map_err(|e| clap_message(&e))?;
+    Ok(tokens)
+}
+
+fn scalar(value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(value) => Ok(value.clone()),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        toml::Value::Float(value) => Ok(value.to_string()),
+        toml::Value::Boolean(value) => Ok(value.to_string()),
+        _ => Err("expected a string, number or boolean".to_string()),
+    }
+}
+
+fn toml_scalar(value: &str) -> toml::Value {
+    match value.parse::<i64>() {
+        Ok(number) => toml::Value::Integer(number),
+        Err(_) => toml::Value::String(value.to_string()),
+    }
+}
+
+// A clap error on one line, without its "error: " prefix, usage and help hint
+fn clap_message(error: &clap::Error) -> String {
+    let rendered = error.render().to_string();
+    let message: Vec<&str> = rendered
+        .lines()
+        .map(str::trim)
+        .take_while(|line| !line.starts_with("Usage:") && !line.starts_with("For more information"))
+        .filter(|line| !line.is_empty())
+        .collect();
+    let message = message.join(" ");
+    message.strip_prefix("error: ").unwrap_or(&message).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command() -> Command {
+        Command::new("netneighbor")
+            .arg(Arg::new("interval").long("interval").value_parser(clap::value_parser!(u64)).default_value("5"))
+            .arg(Arg::new("disconnect_timeout").long("disconnect-timeout").value_parser(clap::value_parser!(u64)))
+            .arg(Arg::new("interface").long("interface").action(ArgAction::Append))
+            .arg(Arg::new("sources").long("sources").value_delimiter(','))
+            .arg(Arg::new("quiet").long("quiet").action(ArgAction::SetTrue))
+            .arg(Arg::new("config").long("config"))
+    }
+
+    // Parse `args` with `content` as the --config file
+    fn parse_with(name: &str, content: &str, args: &[&str]) -> Result<Layered, Box<dyn Error>> {
+        let path = env::temp_dir().join(format!("netneighbor-{}-{}.toml", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        let mut argv: Vec<OsString> = vec!["netneighbor".into(), "--config".into(), path.clone().into()];
+        argv.extend(args.iter().map(OsString::from));
+        let layered = parse(command(), argv);
+        fs::remove_file(&path).unwrap();
+        layered
+    }
+
+    fn parse_args(args: &[&str]) -> Layered {
+        parse(command(), std::iter::once("netneighbor").chain(args.iter().copied()).map(OsString::from).collect()).unwrap()
+    }
+
+    #[test]
+    fn command_line_overrides_config_file() {
+        let content = "interval = 10\ndisconnect_timeout = 60\ninterface = [\"eth0\", \"wlan0\"]\nsources = [\"netlink\", \"arp\"]\nquiet = true\n";
+        let layered = parse_with("layers", content, &["--interval", "20"]).unwrap();
+
+        assert_eq!(layered.matches.get_one::<u64>("interval"), Some(&20));
+        assert_eq!(layered.matches.get_one::<u64>("disconnect_timeout"), Some(&60));
+        let interfaces: Vec<&String> = layered.matches.get_many("interface").unwrap().collect();
+        assert_eq!(interfaces, ["eth0", "wlan0"]);
+        let sources: Vec<&String> = layered.matches.get_many("sources").unwrap().collect();
+        assert_eq!(sources, ["netlink", "arp"]);
+        assert!(layered.matches.get_flag("quiet"));
+
+        assert_eq!(layered.origin("interval"), Some(&Origin::CommandLine));
+        assert!(matches!(layered.origin("interface"), Some(Origin::File(path)) if path.ends_with(format!("netneighbor-{}-layers.toml", std::process::id()))));
+        assert_eq!(layered.files.len(), 1);
+    }
+
+    #[test]
+    fn false_flags_and_defaults_leave_the_default() {
+        let layered = parse_with("defaults", "quiet = false\n", &[]).unwrap();
+        assert!(!layered.matches.get_flag("quiet"));
+        assert_eq!(layered.matches.get_one::<u64>("interval"), Some(&5));
+        assert_eq!(layered.origin("interval"), Some(&Origin::Default));
+        assert_eq!(layered.origin("quiet"), Some(&Origin::Default));
+    }
+
+    #[test]
+    fn config_file_errors_name_the_file_and_option() {
+        let error = |name: &str, content: &str| parse_with(name, content, &[]).err().expect("config should be rejected").to_string();
+
+        let message = error("unknown", "colour = \"red\"\n");
+        assert!(message.ends_with(".toml: unknown option 'colour'"), "{}", message);
+        let message = error("value", "interval = \"soon\"\n");
+        assert!(message.contains(".toml: interval: invalid value 'soon'"), "{}", message);
+        let message = error("list", "interval = [1, 2]\n");
+        assert!(message.ends_with(".toml: interval: expected a single value"), "{}", message);
+        let message = error("flag", "quiet = \"yes\"\n");
+        assert!(message.ends_with(".toml: quiet: expected true or false"), "{}", message);
+        let message = error("table", "interface = [[\"eth0\"]]\n");
+        assert!(message.ends_with(".toml: interface: expected a string, number or boolean"), "{}", message);
+        let message = error("syntax", "interval = 10\n\ninterval = = 3\n");
+        assert!(message.starts_with("invalid config file ") && message.contains(": line 3: "), "{}", message);
+    }
+
+    #[test]
+    fn missing_config_file_is_an_error() {
+        let path = env::temp_dir().join(format!("netneighbor-{}-missing.toml", std::process::id()));
+        let argv = ["netneighbor", "--config", path.to_str().unwrap()].map(OsString::from).to_vec();
+        let message = parse(command(), argv).err().expect("missing file should be rejected").to_string();
+        assert!(message.starts_with(&format!("cannot read {}: ", path.display())), "{}", message);
+    }
+
+    #[test]
+    fn to_toml_shows_values_and_origins() {
+        let layered = parse_args(&["--interface", "eth0", "--sources", "netlink,arp"]);
+        assert_eq!(
+            layered.to_toml(),
+            "interval = 5  # default\ninterface = [\"eth0\"]  # command line\nsources = [\"netlink\", \"arp\"]  # command line\nquiet = false  # default"
+        );
+    }
+
+    #[test]
+    fn changes_list_differing_options_in_order() {
+        let old = parse_args(&["--interface", "eth0", "--quiet"]);
+        assert!(old.changes(&old.clone()).is_empty());
+
+        let new = parse_args(&["--interval", "10", "--disconnect-timeout", "60", "--interface", "eth0"]);
+        let changes = old.changes(&new);
+        let shown: Vec<String> = changes.iter().map(Change::to_string).collect();
+        assert_eq!(shown, ["interval: 5 -> 10", "disconnect-timeout: unset -> 60", "quiet: true -> false"]);
+        assert_eq!(new.changes(&old)[1], Change { option: "disconnect-timeout".to_string(), old: Some(toml::Value::Integer(60)), new: None });
+    }
+
+    #[test]
+    fn every_option_reads_its_environment_variable() {
+        let command = with_env(command());
+        let env_name = |id: &str| command.get_arguments().find(|arg| arg.get_id() == id).and_then(|arg| arg.get_env()).map(|name| name.to_os_string());
+        assert_eq!(env_name("disconnect_timeout"), Some("NETNEIGHBOR_DISCONNECT_TIMEOUT".into()));
+        assert_eq!(env_name("interval"), Some("NETNEIGHBOR_INTERVAL".into()));
+    }
+}
@@ -0,0 +1,342 @@
+// Device model shared by every neighbor source and the tracker
+
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr};
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde::{Serialize, Serializer};
+
+/// A 48-bit MAC address. Parses the `aa:bb:cc:dd:ee:ff` and `aa-bb-cc-dd-ee-ff`
+/// forms, including single-digit octets as BSD `arp` prints them, in either
+/// case; displays in lowercase colon form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    /// A MAC from exactly six bytes, as in frames and link-layer address attributes
+    pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+        Some(MacAddr(bytes.try_into().ok()?))
+    }
+
+    pub fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+
+    /// An individual (not group) address that isn't all zeroes, as a device on
+    /// the link has. Unresolved entries show up as all zeroes on some systems.
+    pub fn is_unicast(&self) -> bool {
+        self.0[0] & 0x01 == 0 && self.0.iter().any(|&byte| byte != 0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMacError;
+
+impl fmt::Display for ParseMacError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid MAC address")
+    }
+}
+
+impl std::error::Error for ParseMacError {}
+
+impl FromStr for MacAddr {
+    type Err = ParseMacError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let separator = if s.contains('-') { '-' } else { ':' };
+        let mut octets = [0u8; 6];
+        let mut parts = s.split(separator);
+        for octet in octets.iter_mut() {
+            let part = parts.next().ok_or(ParseMacError)?;
+            if part.is_empty() || part.len() > 2 || !part.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(ParseMacError);
+            }
+            *octet = u8::from_str_radix(part, 16).map_err(|_| ParseMacError)?;
+        }
+        match parts.next() {
+            Some(_) => Err(ParseMacError),
+            None => Ok(MacAddr(octets)),
+        }
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", a, b, c, d, e, g)
+    }
+}
+
+// Serialized in its display form, like IpAddr
+impl Serialize for MacAddr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Address families whose neighbors are tracked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum AddressFamily {
+    /// IPv4 (ARP) neighbors only
+    Inet,
+    /// IPv6 (NDP) neighbors only
+    Inet6,
+    #[default]
+    All,
+}
+
+impl AddressFamily {
+    pub fn matches(&self, ip: &IpAddr) -> bool {
+        match self {
+            AddressFamily::Inet => ip.is_ipv4(),
+            AddressFamily::Inet6 => ip.is_ipv6(),
+            AddressFamily::All => true,
+        }
+    }
+}
+
+/// Whether a neighbor can hold this address: loopback, multicast, broadcast
+/// and unspecified addresses never belong to a device on the link
+pub fn is_neighbor_address(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !(ip.is_loopback() || ip.is_multicast() || ip.is_broadcast() || ip.is_unspecified()),
+        IpAddr::V6(ip) => !(ip.is_loopback() || ip.is_multicast() || ip.is_unspecified()),
+    }
+}
+
+/// 169.254.0.0/16 or fe80::/10
+pub fn is_link_local(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_link_local(),
+        IpAddr::V6(ip) => ip.segments()[0] & 0xffc0 == 0xfe80,
+    }
+}
+
+/// Addresses that belong together on one host: an IPv4 address stands alone,
+/// an IPv6 address is grouped with the rest of its /64, where the stable and
+/// rotating temporary (privacy) addresses of an interface all live
+pub fn address_group(ip: &IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => *ip,
+        IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(u128::from(*ip) & !u128::from(u64::MAX))),
+    }
+}
+
+/// Kernel neighbor cache entry states (NUD_* in linux/neighbour.h)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum)]
+pub enum NeighState {
+    Incomplete,
+    Reachable,
+    Stale,
+    Delay,
+    Probe,
+    Failed,
+    Noarp,
+    Permanent,
+    /// No state reported, e.g. entries read from `arp -a`
+    Unknown,
+}
+
+impl NeighState {
+    /// Default presence policy. STALE is excluded so cached entries for devices
+    /// that left don't stay connected forever.
+    pub const DEFAULT_PRESENT: [NeighState; 5] = [
+        NeighState::Reachable,
+        NeighState::Delay,
+        NeighState::Probe,
+        NeighState::Permanent,
+        NeighState::Unknown,
+    ];
+
+    /// Parse the state token printed by `ip neigh show`
+    pub fn from_ip_neigh_token(token: &str) -> Option<Self> {
+        match token {
+            "INCOMPLETE" => Some(NeighState::Incomplete),
+            "REACHABLE" => Some(NeighState::Reachable),
+            "STALE" => Some(NeighState::Stale),
+            "DELAY" => Some(NeighState::Delay),
+            "PROBE" => Some(NeighState::Probe),
+            "FAILED" => Some(NeighState::Failed),
+            "NOARP" => Some(NeighState::Noarp),
+            "PERMANENT" => Some(NeighState::Permanent),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NeighState::Incomplete => "INCOMPLETE",
+            NeighState::Reachable => "REACHABLE",
+            NeighState::Stale => "STALE",
+            NeighState::Delay => "DELAY",
+            NeighState::Probe => "PROBE",
+            NeighState::Failed => "FAILED",
+            NeighState::Noarp => "NOARP",
+            NeighState::Permanent => "PERMANENT",
+            NeighState::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+impl fmt::Display for NeighState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A single neighbor table entry as reported by a source
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Device {
+    pub ip_address: IpAddr,
+    pub mac_address: MacAddr,
+    pub interface: String,
+    pub state: NeighState,
+    /// Hostname the device gave its DHCP server or announced over mDNS
+    pub hostname: Option<String>,
+    /// DHCP client identifier, for sightings from a lease file
+    pub client_id: Option<String>,
+    /// DNS-SD service types the device announced, e.g. `_ipp._tcp`
+    pub services: Vec<String>,
+    /// Names of the sources that saw the device, e.g. `netlink` and `capture`
+    pub sources: Vec<String>,
+}
+
+impl Device {
+    pub fn new(ip: IpAddr, mac: MacAddr, interface: String) -> Self {
+        Device {
+            ip_address: ip,
+            mac_address: mac,
+            interface,
+            state: NeighState::Unknown,
+            hostname: None,
+            client_id: None,
+            services: Vec::new(),
+            sources: Vec::new(),
+        }
+    }
+
+    pub fn with_state(mut self, state: NeighState) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Record the source this sighting came from
+    pub fn seen_by(mut self, source: &str) -> Self {
+        self.add_sources(&[source.to_string()]);
+        self
+    }
+
+    /// Add sources that saw the device, keeping them sorted
+    pub fn add_sources(&mut self, sources: &[String]) {
+        for source in sources {
+            if !self.sources.contains(source) {
+                self.sources.push(source.clone());
+            }
+        }
+        self.sources.sort();
+    }
+
+    /// Fill in the hostname and client identifier this sighting lacks from
+    /// another sighting of the same device, and add its services and sources
+    pub fn inherit_details(&mut self, other: &Device) {
+        if self.hostname.is_none() {
+            self.hostname = other.hostname.clone();
+        }
+        if self.client_id.is_none() {
+            self.client_id = other.client_id.clone();
+        }
+        for service in &other.services {
+            if !self.services.contains(service) {
+                self.services.push(service.clone());
+            }
+        }
+        self.services.sort();
+        self.add_sources(&other.sources);
+    }
+
+    /// Identity used by the tracker: the MAC address on a given interface, so
+    /// address changes don't look like a different device
+    pub fn key(&self) -> String {
+        format!("{}-{}", self.interface, self.mac_address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mac(s: &str) -> MacAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn mac_parses_colon_and_dash_forms_in_either_case() {
+        let expected = MacAddr([0xaa, 0xbb, 0xcc, 0x0d, 0xee, 0xff]);
+        assert_eq!(mac("aa:bb:cc:0d:ee:ff"), expected);
+        assert_eq!(mac("AA-BB-CC-0D-EE-FF"), expected);
+        assert_eq!(mac("Aa:bB:cc:0D:ee:Ff"), expected);
+    }
+
+    #[test]
+    fn mac_parses_single_digit_octets() {
+        // BSD `arp` drops leading zeroes
+        assert_eq!(mac("0:1b:2c:3d:4e:5"), MacAddr([0x00, 0x1b, 0x2c, 0x3d, 0x4e, 0x05]));
+    }
+
+    #[test]
+    fn mac_rejects_malformed_input() {
+        for input in [
+            "",
+            "aa:bb:cc:dd:ee",
+            "aa:bb:cc:dd:ee:ff:00",
+            "aa:bb:cc:dd:ee:",
+            "aa::cc:dd:ee:ff",
+            "aaa:bb:cc:dd:ee:ff",
+            "gg:bb:cc:dd:ee:ff",
+            "aa-bb:cc-dd:ee-ff",
+            "+a:bb:cc:dd:ee:ff",
+            "aabbccddeeff",
+        ] {
+            assert_eq!(input.parse::<MacAddr>(), Err(ParseMacError), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn mac_displays_in_lowercase_colon_form() {
+        assert_eq!(mac("0-1B-2c-3D-4e-5F").to_string(), "00:1b:2c:3d:4e:5f");
+        assert_eq!(MacAddr::from_slice(&[1, 2, 3, 4, 5, 6]).unwrap().to_string(), "01:02:03:04:05:06");
+        assert_eq!(MacAddr::from_slice(&[1, 2, 3, 4, 5]), None);
+    }
+
+    #[test]
+    fn mac_unicast_excludes_group_and_zero_addresses() {
+        assert!(mac("02:00:00:00:00:01").is_unicast());
+        assert!(!mac("01:00:5e:00:00:fb").is_unicast());
+        assert!(!mac("33:33:00:00:00:01").is_unicast());
+        assert!(!mac("ff:ff:ff:ff:ff:ff").is_unicast());
+        assert!(!mac("00:00:00:00:00:00").is_unicast());
+    }
+
+    #[test]
+    fn neighbor_addresses_exclude_special_ranges() {
+        for ip in ["192.168.1.10", "169.254.3.4", "2001:db8::1", "fe80::1"] {
+            assert!(is_neighbor_address(&ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["127.0.0.1", "224.0.0.251", "255.255.255.255", "0.0.0.0", "::1", "ff02::fb", "::"] {
+            assert!(!is_neighbor_address(&ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn ipv6_addresses_group_by_64_prefix() {
+        let group = |ip: &str| address_group(&ip.parse().unwrap());
+        assert_eq!(group("2001:db8:1:2:aaaa::1"), group("2001:db8:1:2:ffff:1:2:3"));
+        assert_eq!(group("2001:db8:1:2::5"), "2001:db8:1:2::".parse::<IpAddr>().unwrap());
+        assert_ne!(group("2001:db8:1:2::1"), group("2001:db8:1:3::1"));
+        // IPv4 addresses stand alone
+        assert_eq!(group("192.168.1.10"), "192.168.1.10".parse::<IpAddr>().unwrap());
+        assert_ne!(group("192.168.1.10"), group("192.168.1.11"));
+    }
+}
@@ -0,0 +1,45 @@
+// Diagnostics from background work (webhook deliveries, probes, capture, lease
+// and hosts files, ...). They go to stderr, except while the dashboard owns the
+// terminal: then the most recent ones are kept for its status line instead.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Local};
+
+// Diagnostics kept for the dashboard; older ones are dropped
+const KEPT: usize = 100;
+
+/// A diagnostic message and when it was reported
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub timestamp: DateTime<Local>,
+    pub message: String,
+}
+
+// None while diagnostics are printed
+static HELD: Mutex<Option<VecDeque<Diagnostic>>> = Mutex::new(None);
+
+/// Keep diagnostics for `recent` instead of printing them, for as long as the
+/// terminal is taken over
+pub fn hold() {
+    HELD.lock().unwrap().get_or_insert_with(VecDeque::new);
+}
+
+/// Report a problem that doesn't stop monitoring
+pub fn warn(message: &str) {
+    let mut held = HELD.lock().unwrap();
+    let Some(held) = held.as_mut() else {
+        eprintln!("{}", message);
+        return;
+    };
+    if held.len() == KEPT {
+        held.pop_front();
+    }
+    held.push_back(Diagnostic { timestamp: Local::now(), message: message.to_string() });
+}
+
+/// Diagnostics kept since `hold`, oldest first
+pub fn recent() -> Vec<Diagnostic> {
+    HELD.lock().unwrap().iter().flatten().cloned().collect()
+}
@@ -0,0 +1,526 @@
+// Persistent device history in a local SQLite database
+
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, SecondsFormat};
+use rusqlite::{params, Connection};
+
+use crate::device::{Device, MacAddr, NeighState};
+use crate::diag;
+use crate::output::get_vendor_from_mac;
+use crate::security::SecurityAlert;
+use crate::sink::EventSink;
+use crate::tracker::{Event, EventKind, Tracker};
+
+// How often last-seen times of tracked devices are written back
+const LAST_SEEN_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS events (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp   TEXT NOT NULL,
+    unix_time   INTEGER NOT NULL,
+    event       TEXT NOT NULL,
+    ip          TEXT NOT NULL,
+    mac         TEXT NOT NULL,
+    vendor      TEXT,
+    interface   TEXT NOT NULL,
+    state       TEXT NOT NULL,
+    previous_ip TEXT,
+    previous_mac TEXT,
+    alert       TEXT
+);
+CREATE INDEX IF NOT EXISTS events_mac ON events (mac);
+CREATE INDEX IF NOT EXISTS events_unix_time ON events (unix_time);
+
+CREATE TABLE IF NOT EXISTS devices (
+    ip          TEXT NOT NULL,
+    mac         TEXT NOT NULL,
+    interface   TEXT NOT NULL,
+    vendor      TEXT,
+    state       TEXT NOT NULL,
+    first_seen  TEXT NOT NULL,
+    last_seen   TEXT NOT NULL,
+    connected   INTEGER NOT NULL,
+    PRIMARY KEY (ip, mac, interface)
+);
+";
+
+/// Filters for querying recorded events; unset fields match everything
+#[derive(Debug, Default, Clone)]
+pub struct HistoryFilter {
+    pub mac: Option<MacAddr>,
+    pub ip: Option<IpAddr>,
+    /// Case-insensitive substring of the vendor name
+    pub vendor: Option<String>,
+    pub since: Option<DateTime<Local>>,
+    pub until: Option<DateTime<Local>>,
+    pub limit: Option<usize>,
+}
+
+/// Per-device summary kept alongside the event log
+#[derive(Debug, Clone)]
+pub struct DeviceRecord {
+    pub device: Device,
+    pub vendor: Option<String>,
+    pub first_seen: DateTime<Local>,
+    pub last_seen: DateTime<Local>,
+    pub connected: bool,
+}
+
+pub struct History {
+    conn: Connection,
+    last_flush: Option<Instant>,
+}
+
+impl History {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        History::with_connection(Connection::open(path)?)
+    }
+
+    fn with_connection(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        migrate(&conn)?;
+        Ok(History { conn, last_flush: None })
+    }
+
+    /// Append an event to the log and update the device's summary row
+    pub fn record(&self, event: &Event) -> rusqlite::Result<()> {
+        let device = &event.device;
+        let timestamp = format_timestamp(&event.timestamp);
+        let vendor = get_vendor_from_mac(&device.mac_address);
+        let connected = event.kind != EventKind::Disconnected;
+        let (ip, mac) = (device.ip_address.to_string(), device.mac_address.to_string());
+        let previous_ip = event.previous.as_ref().map(|previous| previous.ip_address.to_string());
+        let previous_mac = event.previous.as_ref().map(|previous| previous.mac_address.to_string());
+
+        self.conn.execute(
+            "INSERT INTO events (timestamp, unix_time, event, ip, mac, vendor, interface, state, previous_ip, previous_mac, alert)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                timestamp,
+                event.timestamp.timestamp(),
+                event.kind.as_str(),
+                ip,
+                mac,
+                vendor,
+                device.interface,
+                device.state.as_str(),
+                previous_ip,
+                previous_mac,
+                event.alert.map(|alert| alert.as_str()),
+            ],
+        )?;
+
+        // Alerts are only logged; the sighting that raised them updates the device
+        if event.kind == EventKind::Security {
+            return Ok(());
+        }
+
+        // The old address of an IP change is no longer held by the device
+        if event.kind == EventKind::IpChanged && let Some(previous_ip) = previous_ip {
+            self.conn.execute(
+                "UPDATE devices SET connected = 0 WHERE ip = ?1 AND mac = ?2 AND interface = ?3",
+                params![previous_ip, mac, device.interface],
+            )?;
+        }
+
+        // A disconnect is reported after the timeout, so it doesn't move last_seen forward
+        self.conn.execute(
+            "INSERT INTO devices (ip, mac, interface, vendor, state, first_seen, last_seen, connected)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6, ?7)
+             ON CONFLICT (ip, mac, interface) DO UPDATE SET
+                 vendor = excluded.vendor,
+                 state = excluded.state,
+                 last_seen = CASE WHEN excluded.connected THEN excluded.last_seen ELSE last_seen END,
+                 connected = excluded.connected",
+            params![
+                ip,
+                mac,
+                device.interface,
+                vendor,
+                device.state.as_str(),
+                timestamp,
+                connected,
+            ],
+        )?;
+
+        // The device is gone, so none of the addresses it held are connected any more
+        if event.kind == EventKind::Disconnected {
+            self.conn.execute(
+                "UPDATE devices SET connected = 0 WHERE mac = ?1 AND interface = ?2",
+                params![mac, device.interface],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the last-seen times of every address held by tracked devices, at most
+    /// once per flush interval. Addresses first seen without an event get a row too.
+    pub fn flush_last_seen(&mut self, tracker: &Tracker) -> rusqlite::Result<()> {
+        if self.last_flush.is_some_and(|last| last.elapsed() < LAST_SEEN_FLUSH_INTERVAL) {
+            return Ok(());
+        }
+        self.last_flush = Some(Instant::now());
+
+        let tx = self.conn.transaction()?;
+        {
+            let mut upsert = tx.prepare(
+                "INSERT INTO devices (ip, mac, interface, vendor, state, first_seen, last_seen, connected)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6, 1)
+                 ON CONFLICT (ip, mac, interface) DO UPDATE SET
+                     last_seen = excluded.last_seen,
+                     state = excluded.state,
+                     connected = 1",
+            )?;
+            for tracked in tracker.devices() {
+                let device = &tracked.device;
+                let vendor = get_vendor_from_mac(&device.mac_address);
+                for (ip, last_seen) in &tracked.addresses {
+                    upsert.execute(params![
+                        ip.to_string(),
+                        device.mac_address.to_string(),
+                        device.interface,
+                        vendor,
+                        device.state.as_str(),
+                        format_timestamp(&tracker.wall_time(*last_seen)),
+                    ])?;
+                }
+            }
+        }
+        tx.commit()
+    }
+
+    /// Devices that were still connected when the previous session ended
+    pub fn connected_devices(&self) -> rusqlite::Result<Vec<Device>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT ip, mac, interface, state FROM devices WHERE connected = 1")?;
+        let rows = stmt.query_map([], |row| {
+            let (ip, mac, state): (String, String, String) = (row.get(0)?, row.get(1)?, row.get(3)?);
+            Ok(parse_device(&ip, &mac, row.get(2)?, &state))
+        })?;
+        rows.filter_map(Result::transpose).collect()
+    }
+
+    /// Recorded events matching the filter, oldest first
+    pub fn events(&self, filter: &HistoryFilter) -> rusqlite::Result<Vec<Event>> {
+        let mut sql = String::from(
+            "SELECT timestamp, event, ip, mac, interface, state, previous_ip, previous_mac, alert FROM events WHERE 1 = 1",
+        );
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+        push_filters(filter, "unix_time", &mut sql, &mut values);
+        // Newest first so a limit keeps the most recent events, reversed below
+        sql.push_str(" ORDER BY unix_time DESC, id DESC");
+        if let Some(limit) = filter.limit {
+            sql.push_str(" LIMIT ?");
+            values.push((limit as i64).into());
+        }
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+            let timestamp: String = row.get(0)?;
+            let kind: String = row.get(1)?;
+            let (ip, mac): (String, String) = (row.get(2)?, row.get(3)?);
+            let state: String = row.get(5)?;
+            let previous_ip: Option<String> = row.get(6)?;
+            let previous_mac: Option<String> = row.get(7)?;
+            let alert: Option<String> = row.get(8)?;
+            Ok((timestamp, kind, ip, mac, row.get(4)?, state, previous_ip, previous_mac, alert))
+        })?;
+
+        let mut events = Vec::new();
+        for row in rows {
+            let (timestamp, kind, ip, mac, interface, state, previous_ip, previous_mac, alert) = row?;
+            let (Some(timestamp), Some(kind)) = (parse_timestamp(&timestamp), EventKind::parse(&kind)) else {
+                continue;
+            };
+            let Some(device) = parse_device(&ip, &mac, interface, &state) else {
+                continue;
+            };
+            let previous = previous_ip.and_then(|ip| ip.parse().ok()).map(|ip| Device {
+                ip_address: ip,
+                mac_address: previous_mac.and_then(|mac| mac.parse().ok()).unwrap_or(device.mac_address),
+                ..device.clone()
+            });
+            let alert = alert.as_deref().and_then(SecurityAlert::parse);
+            events.push(Event { previous, alert, ..Event::new(kind, device, timestamp) });
+        }
+        events.reverse();
+        Ok(events)
+    }
+
+    /// Device summaries matching the filter, most recently seen first
+    pub fn devices(&self, filter: &HistoryFilter) -> rusqlite::Result<Vec<DeviceRecord>> {
+        let mut sql = String::from(
+            "SELECT ip, mac, interface, vendor, state, first_seen, last_seen, connected FROM devices WHERE 1 = 1",
+        );
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+        push_filters(filter, "unixepoch(last_seen)", &mut sql, &mut values);
+        sql.push_str(" ORDER BY unixepoch(last_seen) DESC");
+        if let Some(limit) = filter.limit {
+            sql.push_str(" LIMIT ?");
+            values.push((limit as i64).into());
+        }
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+            let (ip, mac, state): (String, String, String) = (row.get(0)?, row.get(1)?, row.get(4)?);
+            let first_seen: String = row.get(5)?;
+            let last_seen: String = row.get(6)?;
+            let Some(device) = parse_device(&ip, &mac, row.get(2)?, &state) else {
+                return Ok(None);
+            };
+            Ok(Some(DeviceRecord {
+                device,
+                vendor: row.get(3)?,
+                first_seen: parse_timestamp(&first_seen).unwrap_or_default(),
+                last_seen: parse_timestamp(&last_seen).unwrap_or_default(),
+                connected: row.get(7)?,
+            }))
+        })?;
+        rows.filter_map(Result::transpose).collect()
+    }
+}
+
+impl EventSink for History {
+    fn emit(&mut self, event: &Event) {
+        if let Err(e) = self.record(event) {
+            diag::warn(&format!("Error recording event in history database: {}", e));
+        }
+    }
+
+    fn flush(&mut self, tracker: &Tracker) {
+        if let Err(e) = self.flush_last_seen(tracker) {
+            diag::warn(&format!("Error updating history database: {}", e));
+        }
+    }
+}
+
+// Bring databases created by older versions up to the current schema
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    for column in ["previous_ip", "previous_mac", "alert"] {
+        let exists = conn
+            .prepare("SELECT 1 FROM pragma_table_info('events') WHERE name = ?1")?
+            .exists([column])?;
+        if !exists {
+            conn.execute(&format!("ALTER TABLE events ADD COLUMN {} TEXT", column), [])?;
+        }
+    }
+    Ok(())
+}
+
+fn push_filters(filter: &HistoryFilter, time_column: &str, sql: &mut String, values: &mut Vec<rusqlite::types::Value>) {
+    if let Some(mac) = &filter.mac {
+        // Stored in display form, whichever form the filter was given in
+        sql.push_str(" AND mac = ?");
+        values.push(mac.to_string().into());
+    }
+    if let Some(ip) = &filter.ip {
+        // Stored in canonical form too
+        sql.push_str(" AND ip = ?");
+        values.push(ip.to_string().into());
+    }
+    if let Some(vendor) = &filter.vendor {
+        sql.push_str(" AND vendor LIKE ?");
+        values.push(format!("%{}%", vendor).into());
+    }
+    if let Some(since) = &filter.since {
+        sql.push_str(&format!(" AND {} >= ?", time_column));
+        values.push(since.timestamp().into());
+    }
+    if let Some(until) = &filter.until {
+        sql.push_str(&format!(" AND {} <= ?", time_column));
+        values.push(until.timestamp().into());
+    }
+}
+
+fn format_timestamp(timestamp: &DateTime<Local>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+fn parse_timestamp(value: &str) -> Option<DateTime<Local>> {
+    DateTime::parse_from_rfc3339(value).ok().map(|t| t.with_timezone(&Local))
+}
+
+fn parse_state(value: &str) -> NeighState {
+    NeighState::from_ip_neigh_token(value).unwrap_or(NeighState::Unknown)
+}
+
+// A stored device, unless its addresses were edited into something unreadable
+fn parse_device(ip: &str, mac: &str, interface: String, state: &str) -> Option<Device> {
+    Some(Device::new(ip.parse().ok()?, mac.parse().ok()?, interface).with_state(parse_state(state)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn history() -> History {
+        History::with_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn at(seconds: i64) -> DateTime<Local> {
+        Local.timestamp_opt(1_770_000_000 + seconds, 0).unwrap()
+    }
+
+    fn event(kind: EventKind, ip: &str, mac: &str, interface: &str, seconds: i64) -> Event {
+        let device = Device::new(ip.parse().unwrap(), mac.parse().unwrap(), interface.to_string()).with_state(NeighState::Reachable);
+        Event::new(kind, device, at(seconds))
+    }
+
+    fn moved(ip: &str, previous_ip: &str, mac: &str, seconds: i64) -> Event {
+        let mut event = event(EventKind::IpChanged, ip, mac, "eth0", seconds);
+        event.previous = Some(Device { ip_address: previous_ip.parse().unwrap(), ..event.device.clone() });
+        event
+    }
+
+    // (ip, connected, first_seen, last_seen) of every device row, by address
+    fn rows(history: &History) -> Vec<(String, bool, DateTime<Local>, DateTime<Local>)> {
+        let mut rows: Vec<_> = history
+            .devices(&HistoryFilter::default())
+            .unwrap()
+            .into_iter()
+            .map(|record| (format!("{}@{}", record.device.ip_address, record.device.interface), record.connected, record.first_seen, record.last_seen))
+            .collect();
+        rows.sort_by(|a, b| a.0.cmp(&b.0));
+        rows
+    }
+
+    fn ips(events: &[Event]) -> Vec<String> {
+        events.iter().map(|event| format!("{} {}", event.kind.as_str(), event.device.ip_address)).collect()
+    }
+
+    #[test]
+    fn migrate_adds_missing_columns_once() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE events (id INTEGER PRIMARY KEY AUTOINCREMENT, timestamp TEXT NOT NULL, unix_time INTEGER NOT NULL,
+             event TEXT NOT NULL, ip TEXT NOT NULL, mac TEXT NOT NULL, vendor TEXT, interface TEXT NOT NULL, state TEXT NOT NULL)",
+        )
+        .unwrap();
+        let history = History::with_connection(conn).unwrap();
+        migrate(&history.conn).unwrap();
+
+        let mut gained = Vec::new();
+        for column in ["previous_ip", "previous_mac", "alert"] {
+            let mut stmt = history.conn.prepare("SELECT 1 FROM pragma_table_info('events') WHERE name = ?1").unwrap();
+            gained.push(stmt.exists([column]).unwrap());
+        }
+        assert_eq!(gained, [true; 3]);
+        history.record(&moved("192.168.1.11", "192.168.1.10", "02:00:00:00:00:01", 0)).unwrap();
+    }
+
+    #[test]
+    fn connect_and_disconnect_update_the_device_row() {
+        let history = history();
+        history.record(&event(EventKind::Connected, "192.168.1.10", "02:00:00:00:00:01", "eth0", 0)).unwrap();
+        history.record(&event(EventKind::Connected, "192.168.1.10", "02:00:00:00:00:01", "eth0", 30)).unwrap();
+        assert_eq!(rows(&history), [("192.168.1.10@eth0".to_string(), true, at(0), at(30))]);
+
+        // A disconnect is reported after the timeout, so last_seen stays
+        history.record(&event(EventKind::Disconnected, "192.168.1.10", "02:00:00:00:00:01", "eth0", 60)).unwrap();
+        assert_eq!(rows(&history), [("192.168.1.10@eth0".to_string(), false, at(0), at(30))]);
+        assert!(history.connected_devices().unwrap().is_empty());
+    }
+
+    #[test]
+    fn disconnect_clears_every_address_of_the_mac_on_its_interface() {
+        let history = history();
+        let mac = "02:00:00:00:00:01";
+        history.record(&event(EventKind::Connected, "192.168.1.10", mac, "eth0", 0)).unwrap();
+        history.record(&event(EventKind::Connected, "fe80::1", mac, "eth0", 0)).unwrap();
+        history.record(&event(EventKind::Connected, "192.168.2.10", mac, "wlan0", 0)).unwrap();
+        history.record(&event(EventKind::Disconnected, "192.168.1.10", mac, "eth0", 60)).unwrap();
+
+        let connected: Vec<(String, bool)> = rows(&history).into_iter().map(|(ip, connected, ..)| (ip, connected)).collect();
+        assert_eq!(
+            connected,
+            [("192.168.1.10@eth0".to_string(), false), ("192.168.2.10@wlan0".to_string(), true), ("fe80::1@eth0".to_string(), false)]
+        );
+        let still: Vec<String> = history.connected_devices().unwrap().iter().map(|device| device.interface.clone()).collect();
+        assert_eq!(still, ["wlan0"]);
+    }
+
+    #[test]
+    fn ip_change_releases_the_old_address() {
+        let history = history();
+        history.record(&event(EventKind::Connected, "192.168.1.10", "02:00:00:00:00:01", "eth0", 0)).unwrap();
+        history.record(&moved("192.168.1.11", "192.168.1.10", "02:00:00:00:00:01", 10)).unwrap();
+
+        let connected: Vec<(String, bool)> = rows(&history).into_iter().map(|(ip, connected, ..)| (ip, connected)).collect();
+        assert_eq!(connected, [("192.168.1.10@eth0".to_string(), false), ("192.168.1.11@eth0".to_string(), true)]);
+
+        let events = history.events(&HistoryFilter::default()).unwrap();
+        assert_eq!(events[1].kind, EventKind::IpChanged);
+        assert_eq!(events[1].previous.as_ref().unwrap().ip_address.to_string(), "192.168.1.10");
+    }
+
+    #[test]
+    fn security_events_are_only_logged() {
+        let history = history();
+        let mut alert = event(EventKind::Security, "192.168.1.10", "02:00:00:00:00:66", "eth0", 0);
+        alert.alert = Some(SecurityAlert::IpConflict);
+        alert.previous = Some(Device { mac_address: "02:00:00:00:00:01".parse().unwrap(), ..alert.device.clone() });
+        history.record(&alert).unwrap();
+
+        assert!(rows(&history).is_empty());
+        let events = history.events(&HistoryFilter::default()).unwrap();
+        assert_eq!(events[0].alert, Some(SecurityAlert::IpConflict));
+        assert_eq!(events[0].previous.as_ref().unwrap().mac_address.to_string(), "02:00:00:00:00:01");
+    }
+
+    #[test]
+    fn events_filter_by_mac_ip_and_time() {
+        let history = history();
+        history.record(&event(EventKind::Connected, "192.168.1.10", "02:00:00:00:00:01", "eth0", 0)).unwrap();
+        history.record(&event(EventKind::Connected, "fe80::2", "3c:22:fb:00:00:02", "eth0", 10)).unwrap();
+        history.record(&event(EventKind::Disconnected, "192.168.1.10", "02:00:00:00:00:01", "eth0", 20)).unwrap();
+
+        let filter = |filter: HistoryFilter| ips(&history.events(&filter).unwrap());
+        let mac = Some("02-00-00-00-00-01".parse().unwrap());
+        assert_eq!(filter(HistoryFilter { mac, ..Default::default() }), ["CONNECTED 192.168.1.10", "DISCONNECTED 192.168.1.10"]);
+        // Any spelling of the address matches the canonical form stored
+        let ip = Some("fe80:0:0::2".parse().unwrap());
+        assert_eq!(filter(HistoryFilter { ip, ..Default::default() }), ["CONNECTED fe80::2"]);
+        // Both ends are inclusive
+        let (since, until) = (Some(at(10)), Some(at(20)));
+        assert_eq!(filter(HistoryFilter { since, until, ..Default::default() }), ["CONNECTED fe80::2", "DISCONNECTED 192.168.1.10"]);
+        assert_eq!(filter(HistoryFilter { until: Some(at(9)), ..Default::default() }), ["CONNECTED 192.168.1.10"]);
+        assert_eq!(filter(HistoryFilter { vendor: Some("apple".to_string()), ..Default::default() }), ["CONNECTED fe80::2"]);
+        assert!(filter(HistoryFilter { vendor: Some("no such vendor".to_string()), ..Default::default() }).is_empty());
+    }
+
+    #[test]
+    fn limit_keeps_the_most_recent_oldest_first() {
+        let history = history();
+        for (i, seconds) in [0, 10, 10, 20].into_iter().enumerate() {
+            let ip = format!("192.168.1.{}", 10 + i);
+            history.record(&event(EventKind::Connected, &ip, &format!("02:00:00:00:00:{:02x}", i), "eth0", seconds)).unwrap();
+        }
+
+        let events = history.events(&HistoryFilter { limit: Some(3), ..Default::default() }).unwrap();
+        // Events in the same second keep the order they were recorded in
+        assert_eq!(ips(&events), ["CONNECTED 192.168.1.11", "CONNECTED 192.168.1.12", "CONNECTED 192.168.1.13"]);
+
+        let devices = history.devices(&HistoryFilter { limit: Some(2), ..Default::default() }).unwrap();
+        let seen: Vec<DateTime<Local>> = devices.iter().map(|record| record.last_seen).collect();
+        assert_eq!(seen, [at(20), at(10)]);
+        assert!(history.events(&HistoryFilter { limit: Some(0), ..Default::default() }).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unreadable_rows_are_skipped() {
+        let history = history();
+        history.record(&event(EventKind::Connected, "192.168.1.10", "02:00:00:00:00:01", "eth0", 0)).unwrap();
+        history.conn.execute("UPDATE events SET mac = 'garbage'", []).unwrap();
+        history.conn.execute("UPDATE devices SET ip = 'garbage'", []).unwrap();
+        assert!(history.events(&HistoryFilter::default()).unwrap().is_empty());
+        assert!(history.devices(&HistoryFilter::default()).unwrap().is_empty());
+        assert!(history.connected_devices().unwrap().is_empty());
+    }
+}
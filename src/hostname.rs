@@ -0,0 +1,391 @@
+// Hostnames for discovered devices, from a hosts file, DHCP server lease files
+// and reverse DNS. Lookups never wait for DNS: reverse queries run on
+// background threads and a name is attached once its answer is cached.
+
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::fs;
+use std::mem;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::device::{self, Device, MacAddr};
+use crate::diag;
+use crate::leases;
+use crate::tracker::Event;
+
+pub const HOSTS_FILE: &str = "/etc/hosts";
+
+// How often files are checked for changes
+const RELOAD_CHECK: Duration = Duration::from_secs(5);
+// Reverse lookup threads running at once; more wait for a later lookup. A
+// thread counts until its lookup returns, even after the answer was given up.
+const MAX_LOOKUPS: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct HostnameConfig {
+    /// Resolve addresses with reverse DNS
+    pub reverse_dns: bool,
+    /// hosts(5) file, consulted first
+    pub hosts_file: Option<PathBuf>,
+    /// DHCP server lease files, consulted in order after the hosts file
+    pub lease_files: Vec<PathBuf>,
+    /// Reverse lookups taking longer than this are given up
+    pub dns_timeout: Duration,
+    /// How long reverse DNS answers, including missing names, are kept
+    pub cache_ttl: Duration,
+}
+
+impl Default for HostnameConfig {
+    fn default() -> Self {
+        HostnameConfig {
+            reverse_dns: false,
+            hosts_file: None,
+            lease_files: Vec::new(),
+            dns_timeout: Duration::from_secs(2),
+            cache_ttl: Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileKind {
+    Hosts,
+    Leases,
+}
+
+// A name file, reloaded when its modification time changes
+#[derive(Debug)]
+struct NameFile {
+    path: PathBuf,
+    kind: FileKind,
+    modified: Option<SystemTime>,
+    checked: Option<Instant>,
+    failed: bool,
+    by_ip: HashMap<IpAddr, String>,
+    by_mac: HashMap<MacAddr, String>,
+}
+
+impl NameFile {
+    fn new(path: PathBuf, kind: FileKind) -> Self {
+        NameFile {
+            path,
+            kind,
+            modified: None,
+            checked: None,
+            failed: false,
+            by_ip: HashMap::new(),
+            by_mac: HashMap::new(),
+        }
+    }
+
+    fn refresh(&mut self, now: Instant) {
+        if self.checked.is_some_and(|checked| now.duration_since(checked) < RELOAD_CHECK) {
+            return;
+        }
+        self.checked = Some(now);
+
+        let loaded = fs::metadata(&self.path).and_then(|metadata| {
+            let modified = metadata.modified()?;
+            if self.modified != Some(modified) {
+                self.load()?;
+                self.modified = Some(modified);
+            }
+            Ok(())
+        });
+        match loaded {
+            Ok(()) => self.failed = false,
+            Err(e) => {
+                // Report once until the file can be read again, keeping the names last read
+                if !self.failed {
+                    diag::warn(&format!("Hostnames: cannot read {}: {}", self.path.display(), e));
+                }
+                self.failed = true;
+            }
+        }
+    }
+
+    fn load(&mut self) -> std::io::Result<()> {
+        self.by_ip.clear();
+        self.by_mac.clear();
+        match self.kind {
+            FileKind::Hosts => {
+                for (ip, name) in parse_hosts(&fs::read_to_string(&self.path)?) {
+                    self.by_ip.entry(ip).or_insert(name);
+                }
+            }
+            FileKind::Leases => {
+                for lease in leases::load(&self.path)? {
+                    let Some(name) = lease.hostname else {
+                        continue;
+                    };
+                    if let Some(mac) = lease.mac {
+                        self.by_mac.insert(mac, name.clone());
+                    }
+                    self.by_ip.insert(lease.ip, name);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Address and canonical (first) name of each hosts file line
+fn parse_hosts(content: &str) -> Vec<(IpAddr, String)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('#').next()?.split_whitespace();
+            let ip = fields.next()?.parse::<IpAddr>().ok()?;
+            let name = fields.next()?;
+            Some((ip, name.to_string()))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+enum Cached {
+    Pending(Instant),
+    Resolved { name: Option<String>, expires: Instant },
+}
+
+// Reverse DNS answers, resolved on their own threads
+#[derive(Debug, Default)]
+struct ReverseDns {
+    cache: Arc<Mutex<HashMap<IpAddr, Cached>>>,
+    running: Arc<AtomicUsize>,
+}
+
+impl ReverseDns {
+    // The cached name, starting a lookup if there is none yet
+    fn lookup(&self, ip: IpAddr, timeout: Duration, ttl: Duration) -> Option<String> {
+        let now = Instant::now();
+        let mut cache = self.cache.lock().unwrap();
+        match cache.get(&ip) {
+            Some(Cached::Resolved { name, expires }) if *expires > now => return name.clone(),
+            Some(Cached::Pending(started)) if now.duration_since(*started) < timeout => return None,
+            Some(Cached::Pending(_)) => {
+                cache.insert(ip, Cached::Resolved { name: None, expires: now + ttl });
+                return None;
+            }
+            _ => {}
+        }
+        if self.running.load(Ordering::SeqCst) >= MAX_LOOKUPS {
+            return None;
+        }
+
+        cache.retain(|_, cached| !matches!(cached, Cached::Resolved { expires, .. } if *expires <= now));
+        cache.insert(ip, Cached::Pending(now));
+
+        let cache = Arc::clone(&self.cache);
+        let running = Arc::clone(&self.running);
+        running.fetch_add(1, Ordering::SeqCst);
+        thread::spawn(move || {
+            let name = reverse_lookup(ip);
+            running.fetch_sub(1, Ordering::SeqCst);
+
+            // Answers arriving after the timeout were already given up
+            let mut cache = cache.lock().unwrap();
+            if let Some(Cached::Pending(started)) = cache.get(&ip)
+                && started.elapsed() < timeout
+            {
+                cache.insert(ip, Cached::Resolved { name, expires: Instant::now() + ttl });
+            }
+        });
+        None
+    }
+
+    // Whether a lookup for the address is running and not timed out yet
+    fn pending(&self, ip: IpAddr, timeout: Duration) -> bool {
+        let cache = self.cache.lock().unwrap();
+        matches!(cache.get(&ip), Some(Cached::Pending(started)) if started.elapsed() < timeout)
+    }
+}
+
+fn reverse_lookup(ip: IpAddr) -> Option<String> {
+    let mut host = [0 as libc::c_char; libc::NI_MAXHOST as usize];
+    let result = match ip {
+        IpAddr::V4(ip) => {
+            // SAFETY: sockaddr_in is plain old data, all-zero is a valid value
+            let mut addr: libc::sockaddr_in = unsafe { mem::zeroed() };
+            addr.sin_family = libc::AF_INET as libc::sa_family_t;
+            addr.sin_addr.s_addr = u32::from(ip).to_be();
+            // SAFETY: `addr` and `host` are valid for the lengths passed
+            unsafe {
+                libc::getnameinfo(
+                    &addr as *const libc::sockaddr_in as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+                    host.as_mut_ptr(),
+                    host.len() as libc::socklen_t,
+                    std::ptr::null_mut(),
+                    0,
+                    libc::NI_NAMEREQD,
+                )
+            }
+        }
+        IpAddr::V6(ip) => {
+            // SAFETY: sockaddr_in6 is plain old data, all-zero is a valid value
+            let mut addr: libc::sockaddr_in6 = unsafe { mem::zeroed() };
+            addr.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            addr.sin6_addr.s6_addr = ip.octets();
+            // SAFETY: `addr` and `host` are valid for the lengths passed
+            unsafe {
+                libc::getnameinfo(
+                    &addr as *const libc::sockaddr_in6 as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+                    host.as_mut_ptr(),
+                    host.len() as libc::socklen_t,
+                    std::ptr::null_mut(),
+                    0,
+                    libc::NI_NAMEREQD,
+                )
+            }
+        }
+    };
+    if result != 0 {
+        return None;
+    }
+    // SAFETY: getnameinfo succeeded, so `host` holds a NUL-terminated name
+    let name = unsafe { CStr::from_ptr(host.as_ptr()) };
+    name.to_str().ok().map(|name| name.trim_end_matches('.').to_string())
+}
+
+/// Hostname lookup by address, or by MAC for lease files, in the order hosts
+/// file, the hostname the device was sighted with, lease files, reverse DNS
+#[derive(Debug, Default)]
+pub struct Hostnames {
+    config: HostnameConfig,
+    files: Mutex<Vec<NameFile>>,
+    dns: ReverseDns,
+}
+
+impl Hostnames {
+    pub fn new(config: HostnameConfig) -> Self {
+        let mut files = Vec::new();
+        if let Some(path) = &config.hosts_file {
+            files.push(NameFile::new(path.clone(), FileKind::Hosts));
+        }
+        for path in &config.lease_files {
+            files.push(NameFile::new(path.clone(), FileKind::Leases));
+        }
+        Hostnames { config, files: Mutex::new(files), dns: ReverseDns::default() }
+    }
+
+    /// The device's hostname if one is known right now. A reverse lookup that
+    /// isn't cached is started in the background and this returns None meanwhile.
+    pub fn lookup(&self, device: &Device) -> Option<String> {
+        let (ip, mac) = (&device.ip_address, &device.mac_address);
+        let now = Instant::now();
+        let mut files = self.files.lock().unwrap();
+        for file in files.iter_mut() {
+            file.refresh(now);
+        }
+        let from_files = |kind: FileKind| {
+            files
+                .iter()
+                .filter(|file| file.kind == kind)
+                .find_map(|file| file.by_ip.get(ip).or_else(|| file.by_mac.get(mac)).cloned())
+        };
+        let name = from_files(FileKind::Hosts)
+            .or_else(|| device.hostname.clone())
+            .or_else(|| from_files(FileKind::Leases));
+        if name.is_some() {
+            return name;
+        }
+        drop(files);
+
+        // IPv6 link-local addresses have no reverse zone to ask
+        let ip = device.ip_address;
+        if !self.config.reverse_dns || (ip.is_ipv6() && device::is_link_local(&ip)) {
+            return None;
+        }
+        self.dns.lookup(ip, self.config.dns_timeout, self.config.cache_ttl)
+    }
+
+    /// Whether the device's name may still arrive from a reverse lookup that
+    /// hasn't answered or timed out yet
+    pub fn resolving(&self, device: &Device) -> bool {
+        self.config.reverse_dns && self.dns.pending(device.ip_address, self.config.dns_timeout)
+    }
+
+    /// Attach the hostname of the event's device
+    pub fn annotate(&self, event: &mut Event) {
+        event.hostname = self.lookup(&event.device);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+    const TTL: Duration = Duration::from_secs(300);
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn hosts_lines_give_the_canonical_name() {
+        let content = "\
+# static names
+127.0.0.1\tlocalhost
+192.168.1.10  nas.home nas   # storage
+fe80::1%eth0 router
+2001:db8::5 printer
+
+not-an-address name
+192.168.1.11
+";
+        let hosts = parse_hosts(content);
+        assert_eq!(
+            hosts,
+            [
+                (ip("127.0.0.1"), "localhost".to_string()),
+                (ip("192.168.1.10"), "nas.home".to_string()),
+                (ip("2001:db8::5"), "printer".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn cached_names_are_answered_until_they_expire() {
+        let dns = ReverseDns::default();
+        let now = Instant::now();
+        {
+            let mut cache = dns.cache.lock().unwrap();
+            cache.insert(ip("192.168.1.10"), Cached::Resolved { name: Some("nas".to_string()), expires: now + TTL });
+            cache.insert(ip("192.168.1.11"), Cached::Resolved { name: Some("old".to_string()), expires: now });
+        }
+        // Keep expired entries from starting a real lookup
+        dns.running.store(MAX_LOOKUPS, Ordering::SeqCst);
+
+        assert_eq!(dns.lookup(ip("192.168.1.10"), TIMEOUT, TTL).as_deref(), Some("nas"));
+        assert_eq!(dns.lookup(ip("192.168.1.11"), TIMEOUT, TTL), None);
+    }
+
+    #[test]
+    fn timed_out_lookups_are_cached_as_missing() {
+        let dns = ReverseDns::default();
+        let started = Instant::now();
+        dns.cache.lock().unwrap().insert(ip("192.168.1.10"), Cached::Pending(started));
+        assert!(dns.pending(ip("192.168.1.10"), TIMEOUT));
+        assert!(!dns.pending(ip("192.168.1.10"), Duration::ZERO));
+
+        assert_eq!(dns.lookup(ip("192.168.1.10"), Duration::ZERO, TTL), None);
+        assert!(matches!(dns.cache.lock().unwrap()[&ip("192.168.1.10")], Cached::Resolved { name: None, .. }));
+    }
+
+    #[test]
+    fn blocked_threads_hold_back_new_lookups() {
+        let dns = ReverseDns::default();
+        // Threads still running after their lookups timed out
+        dns.running.store(MAX_LOOKUPS, Ordering::SeqCst);
+        assert_eq!(dns.lookup(ip("192.168.1.10"), TIMEOUT, TTL), None);
+        assert!(dns.cache.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+// Minimal read-only HTTP/1.1 server for the metrics and API endpoints
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// Requests are small GETs; anything bigger is rejected
+const MAX_HEADER_BYTES: usize = 8 * 1024;
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+// Connections answered at once; more are turned away with 503
+const MAX_CONNECTIONS: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn ok(content_type: &'static str, body: String) -> Self {
+        Response { status: 200, content_type, body }
+    }
+
+    pub fn json(body: String) -> Self {
+        Response::ok("application/json", body)
+    }
+
+    pub fn error(status: u16, message: &str) -> Self {
+        let body = serde_json::json!({ "error": message }).to_string();
+        Response { status, content_type: "application/json", body }
+    }
+
+    pub fn not_found() -> Self {
+        Response::error(404, "not found")
+    }
+}
+
+/// Bind `addr` and answer requests with `handler` on background threads.
+/// Returns the bound address once the listener is ready.
+pub fn serve<F>(addr: impl ToSocketAddrs, handler: F) -> io::Result<SocketAddr>
+where
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    let handler = Arc::new(handler);
+    let active = Arc::new(AtomicUsize::new(0));
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            if active.fetch_add(1, Ordering::AcqRel) >= MAX_CONNECTIONS {
+                active.fetch_sub(1, Ordering::AcqRel);
+                // The response fits in the socket buffer, so this doesn't hold up accepting
+                let _ = stream.set_write_timeout(Some(CLIENT_TIMEOUT));
+                let _ = write_response(&stream, "GET", &Response::error(503, "too many connections"));
+                continue;
+            }
+            let slot = Slot(Arc::clone(&active));
+            let handler = Arc::clone(&handler);
+            thread::spawn(move || {
+                let _slot = slot;
+                // Clients that disconnect or time out are simply dropped
+                let _ = handle_connection(stream, handler.as_ref());
+            });
+        }
+    });
+
+    Ok(local_addr)
+}
+
+// A connection counted against MAX_CONNECTIONS until dropped
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+fn handle_connection<F>(stream: TcpStream, handler: &F) -> io::Result<()>
+where
+    F: Fn(&Request) -> Response,
+{
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    // Never buffer more than the header limit, however long a line is
+    let mut reader = BufReader::new((&stream).take(MAX_HEADER_BYTES as u64));
+    let mut head = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            // Out of allowance before the blank line ending the headers
+            if reader.get_ref().limit() == 0 {
+                return write_response(&stream, "GET", &Response::error(431, "request headers too large"));
+            }
+            break;
+        }
+        if line == "\r\n" || line == "\n" {
+            break;
+        }
+        head.push(line);
+    }
+
+    let response = match head.first().and_then(|line| parse_request_line(line)) {
+        Some(request) if request.method == "GET" || request.method == "HEAD" => {
+            let response = handler(&request);
+            return write_response(&stream, &request.method, &response);
+        }
+        Some(_) => Response::error(405, "method not allowed"),
+        None => Response::error(400, "bad request"),
+    };
+    write_response(&stream, "GET", &response)
+}
+
+// "GET /path?key=value HTTP/1.1"
+fn parse_request_line(line: &str) -> Option<Request> {
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?;
+    parts.next()?.starts_with("HTTP/").then_some(())?;
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect();
+
+    Some(Request { method, path: percent_decode(path), query })
+}
+
+// Decode %XX escapes; '+' is left as is so RFC 3339 offsets survive unescaped
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && let Some(byte) = std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn write_response(mut stream: &TcpStream, method: &str, response: &Response) -> io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason,
+        response.content_type,
+        response.body.len()
+    )?;
+    if method != "HEAD" {
+        stream.write_all(response.body.as_bytes())?;
+    }
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Send raw request bytes to a server answering every GET with the path and query
+    fn exchange(request: &[u8]) -> String {
+        let addr = serve("127.0.0.1:0", |request: &Request| {
+            let query: std::collections::BTreeMap<_, _> = request.query.iter().collect();
+            Response::ok("text/plain", format!("{} {:?}", request.path, query))
+        })
+        .unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        // The server may answer and close before reading everything, e.g. with 431
+        let _ = stream.write_all(request);
+        let _ = stream.shutdown(std::net::Shutdown::Write);
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        response
+    }
+
+    #[test]
+    fn request_line_splits_path_and_query() {
+        let request = parse_request_line("GET /events?since=2026-02-12T08:00:00+01:00&limit=5&flag HTTP/1.1\r\n").unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/events");
+        assert_eq!(request.query.get("since").map(String::as_str), Some("2026-02-12T08:00:00+01:00"));
+        assert_eq!(request.query.get("limit").map(String::as_str), Some("5"));
+        assert_eq!(request.query.get("flag").map(String::as_str), Some(""));
+
+        let request = parse_request_line("HEAD /devices%2Fall?mac=aa%3Abb&&=x HTTP/1.0").unwrap();
+        assert_eq!(request.path, "/devices/all");
+        assert_eq!(request.query.get("mac").map(String::as_str), Some("aa:bb"));
+        assert_eq!(request.query.get("").map(String::as_str), Some("x"));
+    }
+
+    #[test]
+    fn request_line_needs_method_target_and_version() {
+        for line in ["", "GET", "GET /", "GET / FTP/1.0", "\r\n"] {
+            assert!(parse_request_line(line).is_none(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn percent_decode_leaves_invalid_escapes_and_plus() {
+        assert_eq!(percent_decode("a%20b"), "a b");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("1+2"), "1+2");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode("%zz%41"), "%zzA");
+        assert_eq!(percent_decode("%FF"), "\u{fffd}");
+    }
+
+    #[test]
+    fn requests_are_answered_by_method() {
+        let response = exchange(b"GET /metrics?a=1 HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("Content-Length: 19\r\n") && response.ends_with("\r\n\r\n/metrics {\"a\": \"1\"}"), "{}", response);
+
+        let response = exchange(b"HEAD /metrics HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n") && response.ends_with("\r\n\r\n"), "{}", response);
+        assert!(exchange(b"POST /metrics HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        assert!(exchange(b"nonsense\r\n\r\n").starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(exchange(b"").starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn oversized_headers_get_431() {
+        let mut request = b"GET / HTTP/1.1\r\n".to_vec();
+        request.extend(format!("X-Padding: {}\r\n\r\n", "a".repeat(MAX_HEADER_BYTES)).as_bytes());
+        assert!(exchange(&request).starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+
+        // Headers just within the limit are fine
+        let head = "GET / HTTP/1.1\r\nX-Padding: \r\n\r\n";
+        let request = head.replace("X-Padding: ", &format!("X-Padding: {}", "a".repeat(MAX_HEADER_BYTES - head.len())));
+        assert_eq!(request.len(), MAX_HEADER_BYTES);
+        assert!(exchange(request.as_bytes()).starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+}
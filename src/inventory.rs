@@ -0,0 +1,88 @@
+// Known-device inventory: friendly names, owners and tags keyed by MAC address
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::device::MacAddr;
+use crate::tracker::{Event, EventKind};
+
+/// What the inventory knows about one device
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct InventoryEntry {
+    pub name: Option<String>,
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct InventoryFile {
+    #[serde(default)]
+    device: Vec<InventoryFileEntry>,
+}
+
+#[derive(Deserialize)]
+struct InventoryFileEntry {
+    mac: String,
+    #[serde(flatten)]
+    entry: InventoryEntry,
+}
+
+/// Inventory loaded from a TOML file with one `[[device]]` table per MAC:
+///
+/// ```toml
+/// [[device]]
+/// mac = "c8:a3:62:67:99:b2"
+/// name = "Office printer"
+/// owner = "IT"
+/// tags = ["printer", "office"]
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Inventory {
+    devices: HashMap<MacAddr, InventoryEntry>,
+}
+
+impl Inventory {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read inventory {}: {}", path.display(), e))?;
+        Self::parse(&content).map_err(|e| format!("invalid inventory {}: {}", path.display(), e).into())
+    }
+
+    pub fn parse(content: &str) -> Result<Self, Box<dyn Error>> {
+        let file: InventoryFile = toml::from_str(content)?;
+
+        let mut devices = HashMap::new();
+        for device in file.device {
+            let mac: MacAddr = device.mac.parse().map_err(|_| format!("invalid MAC address '{}'", device.mac))?;
+            if devices.insert(mac, device.entry).is_some() {
+                return Err(format!("duplicate entry for MAC address '{}'", device.mac).into());
+            }
+        }
+
+        Ok(Inventory { devices })
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    pub fn get(&self, mac: &MacAddr) -> Option<&InventoryEntry> {
+        self.devices.get(mac)
+    }
+
+    /// Attach the inventory entry of the event's device. With `alert_unknown`,
+    /// CONNECTED events for MACs missing from the inventory are flagged as unknown.
+    pub fn annotate(&self, event: &mut Event, alert_unknown: bool) {
+        event.inventory = self.get(&event.device.mac_address).cloned();
+        event.unknown = alert_unknown && event.kind == EventKind::Connected && event.inventory.is_none();
+    }
+}
@@ -0,0 +1,616 @@
+// DHCP server lease files: dnsmasq, ISC dhcpd and Kea's CSV lease store.
+// Files that are appended to as leases change are reduced to the latest state
+// of each address. The lease source watches them with inotify and reports
+// every current lease as a device sighting, or only adds the hostname and
+// client identifier of current leases to devices other sources see.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::ffi::{CString, OsStr};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::net::IpAddr;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveDateTime};
+
+use crate::device::{Device, MacAddr, NeighState};
+use crate::diag;
+use crate::packet::{self, LocalInterface};
+use crate::source::NeighborSource;
+
+/// Source name of devices holding a DHCP lease
+pub const SOURCE: &str = "leases";
+
+/// One address handed out by a DHCP server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    pub ip: IpAddr,
+    pub mac: Option<MacAddr>,
+    /// Hostname the client sent
+    pub hostname: Option<String>,
+    /// DHCP client identifier (DUID for DHCPv6), as colon separated hex
+    pub client_id: Option<String>,
+    /// None for leases that never expire
+    pub expires: Option<DateTime<Local>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaseFormat {
+    Dnsmasq,
+    Dhcpd,
+    Kea,
+}
+
+impl LeaseFormat {
+    /// Tell the formats apart by their content
+    pub fn detect(content: &str) -> Self {
+        let first = content.lines().map(str::trim).find(|line| !line.is_empty() && !line.starts_with('#'));
+        match first {
+            Some(line) if line.starts_with("address,") => LeaseFormat::Kea,
+            Some(line) if line.starts_with("lease ") || line.ends_with(';') || line.ends_with('{') => LeaseFormat::Dhcpd,
+            _ => LeaseFormat::Dnsmasq,
+        }
+    }
+}
+
+pub fn load(path: &Path) -> io::Result<Vec<Lease>> {
+    Ok(parse(&fs::read_to_string(path)?))
+}
+
+/// Current leases in a lease file of any supported format. Lines that can't be
+/// parsed are skipped.
+pub fn parse(content: &str) -> Vec<Lease> {
+    match LeaseFormat::detect(content) {
+        LeaseFormat::Dnsmasq => parse_dnsmasq(content),
+        LeaseFormat::Dhcpd => parse_dhcpd(content),
+        LeaseFormat::Kea => parse_kea(content),
+    }
+}
+
+// `<expiry> <mac> <ip> <hostname> <client-id>`, with `*` for missing values.
+// DHCPv6 leases follow a `duid` line and have the IAID in place of the MAC.
+fn parse_dnsmasq(content: &str) -> Vec<Lease> {
+    let mut leases = Vec::new();
+    let mut ipv6 = false;
+
+    for line in content.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.first() == Some(&"duid") {
+            ipv6 = true;
+            continue;
+        }
+        if fields.len() < 4 {
+            continue;
+        }
+        let Ok(ip) = fields[2].parse() else {
+            continue;
+        };
+        leases.push(Lease {
+            ip,
+            mac: if ipv6 { None } else { fields[1].parse().ok() },
+            hostname: present(fields[3]).map(str::to_string),
+            client_id: fields.get(4).copied().and_then(present).map(str::to_string),
+            expires: match fields[0].parse::<i64>() {
+                Ok(0) | Err(_) => None,
+                Ok(seconds) => from_unix(seconds),
+            },
+        });
+    }
+    leases
+}
+
+// `lease <ip> { ... }` blocks. The server appends a new block whenever a lease
+// changes, so later blocks replace earlier ones and only active bindings remain.
+fn parse_dhcpd(content: &str) -> Vec<Lease> {
+    let mut leases: HashMap<IpAddr, Lease> = HashMap::new();
+    let mut current: Option<(Lease, bool)> = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("lease ") {
+            current = rest
+                .trim_end_matches('{')
+                .trim()
+                .parse::<IpAddr>()
+                .ok()
+                .map(|ip| (Lease { ip, mac: None, hostname: None, client_id: None, expires: None }, true));
+            continue;
+        }
+        let Some((lease, active)) = current.as_mut() else {
+            continue;
+        };
+        if line == "}" {
+            let (lease, active) = current.take().unwrap();
+            if active {
+                leases.insert(lease.ip, lease);
+            } else {
+                leases.remove(&lease.ip);
+            }
+            continue;
+        }
+
+        let statement = line.trim_end_matches(';');
+        let (keyword, value) = statement.split_once(' ').unwrap_or((statement, ""));
+        match keyword {
+            "binding" => *active = value == "state active",
+            "hardware" => lease.mac = value.strip_prefix("ethernet ").and_then(|mac| mac.parse().ok()),
+            "client-hostname" => lease.hostname = Some(String::from_utf8_lossy(&unquote(value)).into_owned()),
+            "uid" if value.starts_with('"') => lease.client_id = Some(hex(&unquote(value))),
+            "uid" => lease.client_id = Some(value.to_lowercase()),
+            // `ends <weekday> <yyyy/mm/dd> <hh:mm:ss>` in UTC, `ends epoch <seconds>` or `ends never`
+            "ends" => {
+                lease.expires = match value.split_whitespace().collect::<Vec<_>>()[..] {
+                    ["epoch", seconds, ..] => seconds.parse().ok().and_then(from_unix),
+                    [_, date, time] => NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y/%m/%d %H:%M:%S")
+                        .ok()
+                        .map(|time| time.and_utc().with_timezone(&Local)),
+                    _ => None,
+                }
+            }
+            _ => {}
+        }
+    }
+    leases.into_values().collect()
+}
+
+// CSV with a header naming the columns, which differ between the DHCPv4 and
+// DHCPv6 files. Lease updates are appended; a zero lifetime or a non-default
+// state means the lease is gone.
+fn parse_kea(content: &str) -> Vec<Lease> {
+    let mut lines = content.lines();
+    let Some(header) = lines.next() else {
+        return Vec::new();
+    };
+    let columns: Vec<&str> = header.trim().split(',').collect();
+    let column = |name: &str| columns.iter().position(|column| *column == name);
+    let (address, hwaddr, hostname, expire, lifetime, state) = (
+        column("address"),
+        column("hwaddr"),
+        column("hostname"),
+        column("expire"),
+        column("valid_lifetime"),
+        column("state"),
+    );
+    let client_id = column("client_id").or(column("duid"));
+
+    let mut leases: HashMap<IpAddr, Lease> = HashMap::new();
+    for line in lines {
+        let fields: Vec<&str> = line.trim().split(',').collect();
+        let field = |index: Option<usize>| index.and_then(|index| fields.get(index)).copied().filter(|value| !value.is_empty());
+        let Some(ip) = field(address).and_then(|ip| ip.parse::<IpAddr>().ok()) else {
+            continue;
+        };
+        if field(lifetime) == Some("0") || field(state).is_some_and(|state| state != "0") {
+            leases.remove(&ip);
+            continue;
+        }
+        leases.insert(ip, Lease {
+            ip,
+            mac: field(hwaddr).and_then(|mac| mac.parse().ok()),
+            // Commas inside values are escaped
+            hostname: field(hostname).map(|name| name.replace("&#x2c", ",").trim_end_matches('.').to_string()),
+            client_id: field(client_id).map(str::to_lowercase),
+            expires: field(expire).and_then(|seconds| seconds.parse().ok()).and_then(from_unix),
+        });
+    }
+    leases.into_values().collect()
+}
+
+fn present(value: &str) -> Option<&str> {
+    (value != "*").then_some(value)
+}
+
+fn from_unix(seconds: i64) -> Option<DateTime<Local>> {
+    DateTime::from_timestamp(seconds, 0).map(|time| time.with_timezone(&Local))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(":")
+}
+
+// Contents of a dhcpd quoted string, with its backslash and octal escapes
+fn unquote(value: &str) -> Vec<u8> {
+    let inner = value.trim().trim_start_matches('"').trim_end_matches('"').as_bytes();
+    let mut bytes = Vec::with_capacity(inner.len());
+    let mut i = 0;
+    while i < inner.len() {
+        if inner[i] == b'\\' && i + 1 < inner.len() {
+            let digits = inner[i + 1..].iter().take(3).take_while(|byte| (b'0'..=b'7').contains(byte)).count();
+            if digits > 0 {
+                let octal = std::str::from_utf8(&inner[i + 1..i + 1 + digits]).unwrap();
+                bytes.push(u8::from_str_radix(octal, 8).unwrap_or(0));
+                i += 1 + digits;
+            } else {
+                bytes.push(inner[i + 1]);
+                i += 2;
+            }
+        } else {
+            bytes.push(inner[i]);
+            i += 1;
+        }
+    }
+    bytes
+}
+
+// Servers rewrite or append in several steps; wait for them to finish
+const SETTLE_DELAY: Duration = Duration::from_millis(200);
+
+const WATCH_MASK: u32 = libc::IN_MODIFY
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO;
+
+type LeaseFiles = Arc<Mutex<HashMap<PathBuf, Vec<Lease>>>>;
+
+/// Keeps the leases of a set of files up to date, re-reading a file whenever
+/// inotify reports a change to it
+pub struct LeaseWatcher {
+    leases: LeaseFiles,
+    // Set whenever a file was re-read, cleared by `take_changed`
+    changed: Arc<AtomicBool>,
+}
+
+impl LeaseWatcher {
+    /// Read the files and watch their directories, which also catches servers
+    /// that replace the file by renaming a new one over it
+    pub fn start(paths: Vec<PathBuf>) -> io::Result<Self> {
+        // SAFETY: plain inotify_init1(2) call; the returned descriptor is checked before use
+        let raw = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if raw < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `raw` is a freshly created, valid descriptor that we exclusively own
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+
+        let mut watches: HashMap<i32, PathBuf> = HashMap::new();
+        let mut leases = HashMap::new();
+        for path in &paths {
+            let directory = directory_of(path);
+            let name = CString::new(directory.as_os_str().as_bytes())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a NUL byte"))?;
+            // SAFETY: `name` is a valid NUL-terminated path for the duration of the call
+            let wd = unsafe { libc::inotify_add_watch(fd.as_raw_fd(), name.as_ptr(), WATCH_MASK) };
+            if wd < 0 {
+                let e = io::Error::last_os_error();
+                return Err(io::Error::new(e.kind(), format!("cannot watch {}: {}", directory.display(), e)));
+            }
+            watches.insert(wd, directory);
+            let current = read_leases(path).map_err(|e| io::Error::new(e.kind(), format!("cannot read {}: {}", path.display(), e)))?;
+            leases.insert(path.clone(), current);
+        }
+
+        let leases = Arc::new(Mutex::new(leases));
+        let changed = Arc::new(AtomicBool::new(false));
+        let (watched, reread) = (Arc::clone(&leases), Arc::clone(&changed));
+        thread::spawn(move || watch(File::from(fd), &watches, &paths, &watched, &reread));
+        Ok(LeaseWatcher { leases, changed })
+    }
+
+    /// Leases that haven't expired, across all files
+    pub fn current(&self) -> Vec<Lease> {
+        let now = Local::now();
+        self.leases
+            .lock()
+            .unwrap()
+            .values()
+            .flatten()
+            .filter(|lease| lease.expires.is_none_or(|expires| expires > now))
+            .cloned()
+            .collect()
+    }
+
+    /// Whether a file was re-read since the last call
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+}
+
+fn directory_of(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+// A file that doesn't exist (yet) holds no leases
+fn read_leases(path: &Path) -> io::Result<Vec<Lease>> {
+    match load(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        result => result,
+    }
+}
+
+fn watch(
+    mut inotify: File,
+    watches: &HashMap<i32, PathBuf>,
+    paths: &[PathBuf],
+    leases: &Mutex<HashMap<PathBuf, Vec<Lease>>>,
+    reread: &AtomicBool,
+) {
+    let mut buf = [0u8; 4096];
+    loop {
+        let len = match inotify.read(&mut buf) {
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                diag::warn(&format!("Leases: watching lease files failed: {}", e));
+                return;
+            }
+        };
+
+        // struct inotify_event: wd, mask, cookie, len, then a NUL padded name
+        let mut changed = HashSet::new();
+        let mut offset = 0;
+        while offset + 16 <= len {
+            let wd = i32::from_ne_bytes(buf[offset..offset + 4].try_into().unwrap());
+            let name_len = u32::from_ne_bytes(buf[offset + 12..offset + 16].try_into().unwrap()) as usize;
+            let name = &buf[offset + 16..(offset + 16 + name_len).min(len)];
+            let name = &name[..name.iter().position(|&byte| byte == 0).unwrap_or(name.len())];
+            if let Some(directory) = watches.get(&wd) {
+                changed.extend(paths.iter().filter(|path| {
+                    path.file_name() == Some(OsStr::from_bytes(name)) && directory_of(path) == *directory
+                }));
+            }
+            offset += 16 + name_len;
+        }
+        if changed.is_empty() {
+            continue;
+        }
+
+        thread::sleep(SETTLE_DELAY);
+        for path in changed {
+            match read_leases(path) {
+                Ok(current) => {
+                    leases.lock().unwrap().insert(path.clone(), current);
+                    reread.store(true, Ordering::Relaxed);
+                }
+                // Keep the leases last read until the file can be read again
+                Err(e) => diag::warn(&format!("Leases: cannot read {}: {}", path.display(), e)),
+            }
+        }
+    }
+}
+
+/// A sighting for every current DHCP lease on a local subnet, carrying the
+/// client's hostname and client identifier. A device is present while its
+/// lease is current, and gone once the lease expires or is released. Sources
+/// made with `details_only` don't vouch for the devices: their sightings only
+/// add the hostname and client identifier to devices other sources see.
+pub struct LeaseSource {
+    watcher: LeaseWatcher,
+    interface: Option<String>,
+    details_only: bool,
+    // Leases reported by `take_heard`, so only new ones are reported again
+    heard: HashSet<(IpAddr, MacAddr)>,
+}
+
+impl LeaseSource {
+    pub fn new(watcher: LeaseWatcher, interface: Option<String>, details_only: bool) -> Self {
+        LeaseSource { watcher, interface, details_only, heard: HashSet::new() }
+    }
+
+    fn sightings(&self) -> io::Result<Vec<Device>> {
+        let interfaces: Vec<LocalInterface> = packet::local_interfaces()?
+            .into_iter()
+            .filter(|interface| self.interface.as_ref().is_none_or(|name| *name == interface.name))
+            .collect();
+        let state = match self.details_only {
+            true => NeighState::Unknown,
+            false => NeighState::Reachable,
+        };
+
+        // Leases without a MAC (DHCPv6 leases only know the DUID) can't be tracked
+        Ok(self
+            .watcher
+            .current()
+            .into_iter()
+            .filter_map(|lease| {
+                let interface = interfaces.iter().find(|interface| on_subnet(interface, lease.ip))?;
+                let mut device = Device::new(lease.ip, lease.mac?, interface.name.clone()).with_state(state).seen_by(SOURCE);
+                device.hostname = lease.hostname;
+                device.client_id = lease.client_id;
+                Some(device)
+            })
+            .collect())
+    }
+}
+
+impl NeighborSource for LeaseSource {
+    fn name(&self) -> &str {
+        SOURCE
+    }
+
+    fn scan(&mut self) -> Result<Vec<Device>, Box<dyn Error>> {
+        Ok(self.sightings()?)
+    }
+
+    fn details_only(&self) -> bool {
+        self.details_only
+    }
+
+    fn set_interface(&mut self, interface: Option<&str>) {
+        self.interface = interface.map(str::to_string);
+    }
+
+    // Leases granted since the last call, as soon as the server wrote them.
+    // Expired and released leases drop out at the next scan.
+    fn take_heard(&mut self) -> Vec<Device> {
+        if self.details_only || !self.watcher.take_changed() {
+            return Vec::new();
+        }
+        let sightings = match self.sightings() {
+            Ok(sightings) => sightings,
+            Err(e) => {
+                diag::warn(&format!("Leases: cannot list local interfaces: {}", e));
+                return Vec::new();
+            }
+        };
+        let previous = std::mem::replace(&mut self.heard, sightings.iter().map(|device| (device.ip_address, device.mac_address)).collect());
+        sightings.into_iter().filter(|device| !previous.contains(&(device.ip_address, device.mac_address))).collect()
+    }
+}
+
+fn on_subnet(interface: &LocalInterface, ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => interface.ipv4.iter().any(|(local, prefix)| {
+            let mask = u32::MAX.checked_shl(32 - *prefix as u32).unwrap_or(0);
+            u32::from(*local) & mask == u32::from(ip) & mask
+        }),
+        IpAddr::V6(ip) => interface.ipv6.iter().any(|(local, prefix)| {
+            let mask = u128::MAX.checked_shl(128 - *prefix as u32).unwrap_or(0);
+            u128::from(*local) & mask == u128::from(ip) & mask
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut leases: Vec<Lease>) -> Vec<Lease> {
+        leases.sort_by_key(|lease| lease.ip);
+        leases
+    }
+
+    fn lease(ip: &str, mac: Option<&str>, hostname: Option<&str>, client_id: Option<&str>, expires: Option<i64>) -> Lease {
+        Lease {
+            ip: ip.parse().unwrap(),
+            mac: mac.map(|mac| mac.parse().unwrap()),
+            hostname: hostname.map(str::to_string),
+            client_id: client_id.map(str::to_string),
+            expires: expires.and_then(from_unix),
+        }
+    }
+
+    #[test]
+    fn detects_formats() {
+        assert_eq!(LeaseFormat::detect("1700000000 aa:bb:cc:dd:ee:ff 192.168.1.10 host *\n"), LeaseFormat::Dnsmasq);
+        assert_eq!(LeaseFormat::detect("# comment\n\nlease 192.168.1.10 {\n}\n"), LeaseFormat::Dhcpd);
+        assert_eq!(LeaseFormat::detect("authoring-byte-order little-endian;\n"), LeaseFormat::Dhcpd);
+        assert_eq!(LeaseFormat::detect("address,hwaddr,client_id,valid_lifetime,expire\n"), LeaseFormat::Kea);
+        assert_eq!(LeaseFormat::detect(""), LeaseFormat::Dnsmasq);
+    }
+
+    #[test]
+    fn dnsmasq_leases() {
+        let content = "\
+1700000000 aa:bb:cc:dd:ee:ff 192.168.1.10 laptop 01:aa:bb:cc:dd:ee:ff
+0 aa:bb:cc:dd:ee:01 192.168.1.11 * *
+1700000000 aa:bb:cc:dd:ee:02 not-an-ip printer *
+short line
+duid 00:01:00:01:2c:5f:aa:bb:aa:bb:cc:dd:ee:ff
+1700000000 12345678 2001:db8::10 phone 00:01:00:01:2c:5f:aa:bb:aa:bb:cc:dd:ee:ff
+";
+        assert_eq!(
+            parse(content),
+            [
+                lease("192.168.1.10", Some("aa:bb:cc:dd:ee:ff"), Some("laptop"), Some("01:aa:bb:cc:dd:ee:ff"), Some(1_700_000_000)),
+                // Expiry 0 is an infinite lease
+                lease("192.168.1.11", Some("aa:bb:cc:dd:ee:01"), None, None, None),
+                // DHCPv6 leases carry the IAID where the MAC would be
+                lease("2001:db8::10", None, Some("phone"), Some("00:01:00:01:2c:5f:aa:bb:aa:bb:cc:dd:ee:ff"), Some(1_700_000_000)),
+            ]
+        );
+    }
+
+    #[test]
+    fn dhcpd_later_blocks_replace_earlier_ones() {
+        let content = r#"
+lease 192.168.1.10 {
+  starts 4 2023/11/14 22:00:00;
+  ends 4 2023/11/14 22:13:20;
+  binding state active;
+  hardware ethernet aa:bb:cc:dd:ee:ff;
+  client-hostname "old-name";
+}
+lease 192.168.1.11 {
+  binding state active;
+  hardware ethernet aa:bb:cc:dd:ee:01;
+  ends never;
+}
+lease 192.168.1.10 {
+  ends epoch 1700000000;
+  binding state active;
+  hardware ethernet aa:bb:cc:dd:ee:ff;
+  uid "\001\252\273\314\335\356\377";
+  client-hostname "laptop\"s";
+}
+lease 192.168.1.11 {
+  binding state free;
+  hardware ethernet aa:bb:cc:dd:ee:01;
+}
+lease 192.168.1.12 {
+  binding state active;
+  uid 01:AA:BB:CC:DD:EE:02;
+}
+lease not-an-ip {
+  binding state active;
+}
+"#;
+        assert_eq!(
+            sorted(parse(content)),
+            [
+                lease("192.168.1.10", Some("aa:bb:cc:dd:ee:ff"), Some("laptop\"s"), Some("01:aa:bb:cc:dd:ee:ff"), Some(1_700_000_000)),
+                lease("192.168.1.12", None, None, Some("01:aa:bb:cc:dd:ee:02"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn dhcpd_ends_date_is_utc() {
+        let content = "lease 192.168.1.10 {\n  ends 2 2023/11/14 22:13:20;\n  binding state active;\n}\n";
+        assert_eq!(parse(content)[0].expires, from_unix(1_700_000_000));
+    }
+
+    #[test]
+    fn dhcpd_unterminated_block_is_ignored() {
+        assert_eq!(parse("lease 192.168.1.10 {\n  binding state active;\n"), []);
+    }
+
+    #[test]
+    fn kea_v4_leases_keep_the_latest_state() {
+        let content = "\
+address,hwaddr,client_id,valid_lifetime,expire,subnet_id,fqdn_fwd,fqdn_rev,hostname,state,user_context
+192.168.1.10,aa:bb:cc:dd:ee:ff,01:AA:BB:CC:DD:EE:FF,3600,1700000000,1,0,0,laptop.lan.,0,
+192.168.1.11,aa:bb:cc:dd:ee:01,,3600,1700000000,1,0,0,a&#x2cb,0,
+192.168.1.12,aa:bb:cc:dd:ee:02,,3600,1700000000,1,0,0,,0,
+192.168.1.12,aa:bb:cc:dd:ee:02,,0,1700000000,1,0,0,,0,
+192.168.1.13,aa:bb:cc:dd:ee:03,,3600,1700000000,1,0,0,,1,
+garbage,line
+";
+        assert_eq!(
+            sorted(parse(content)),
+            [
+                lease("192.168.1.10", Some("aa:bb:cc:dd:ee:ff"), Some("laptop.lan"), Some("01:aa:bb:cc:dd:ee:ff"), Some(1_700_000_000)),
+                lease("192.168.1.11", Some("aa:bb:cc:dd:ee:01"), Some("a,b"), None, Some(1_700_000_000)),
+            ]
+        );
+    }
+
+    #[test]
+    fn kea_v6_leases_use_the_duid() {
+        let content = "\
+address,duid,valid_lifetime,expire,subnet_id,pref_lifetime,lease_type,iaid,prefix_len,fqdn_fwd,fqdn_rev,hostname,hwaddr,state,user_context
+2001:db8::10,00:01:00:01:2c:5f:aa:bb:aa:bb:cc:dd:ee:ff,3600,1700000000,1,1800,0,1,128,0,0,phone,aa:bb:cc:dd:ee:ff,0,
+";
+        assert_eq!(
+            parse(content),
+            [lease("2001:db8::10", Some("aa:bb:cc:dd:ee:ff"), Some("phone"), Some("00:01:00:01:2c:5f:aa:bb:aa:bb:cc:dd:ee:ff"), Some(1_700_000_000))]
+        );
+    }
+
+    #[test]
+    fn unquote_handles_escapes() {
+        assert_eq!(unquote(r#""a\"b\\c\101\0""#), b"a\"b\\cA\0");
+        assert_eq!(hex(&unquote(r#""\001\377""#)), "01:ff");
+    }
+}
@@ -0,0 +1,36 @@
+//! NetNeighbor - network neighbor monitoring library.
+//!
+//! Sources (`source::NeighborSource`) report snapshots of the neighbor table as
+//! `Device` values, and a `Tracker` turns those snapshots into connect/disconnect
+//! events. The `netneighbor` binary is a thin command-line consumer of this API.
+
+pub mod api;
+pub mod capture;
+pub mod config;
+pub mod device;
+pub mod diag;
+pub mod history;
+pub mod hostname;
+pub mod http;
+pub mod inventory;
+pub mod leases;
+pub mod mdns;
+pub mod metrics;
+pub mod netlink;
+pub mod output;
+pub mod packet;
+pub mod parse;
+pub mod pcap;
+pub mod probe;
+pub mod security;
+pub mod sink;
+pub mod source;
+pub mod sweep;
+pub mod tracker;
+pub mod tui;
+pub mod webhook;
+
+pub use device::{AddressFamily, Device, MacAddr, NeighState};
+pub use sink::EventSink;
+pub use source::NeighborSource;
+pub use tracker::{Event, EventKind, Tracker, TrackerConfig};
@@ -1,17 +1,48 @@
-use clap::Parser;
-use std::collections::HashMap;
-use std::process::Command;
-use std::sync::{Arc, Mutex};
-use std::thread;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::collections::{HashSet, VecDeque};
+use std::ffi::OsString;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
-use chrono::Local;
-use colored::*;
-use oui_data::lookup;
-use ctrlc;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+use netneighbor::api::{self, EventBuffer, EventBufferSink};
+use netneighbor::capture::{self, CaptureSource, PacketCapture};
+use netneighbor::config;
+use netneighbor::diag;
+use netneighbor::history::{History, HistoryFilter};
+use netneighbor::hostname::{self, HostnameConfig, Hostnames};
+use netneighbor::http::{self, Response};
+use netneighbor::inventory::Inventory;
+use netneighbor::leases::{LeaseSource, LeaseWatcher};
+use netneighbor::mdns::{self, MdnsListener, MdnsSource};
+use netneighbor::metrics::{Metrics, MetricsSink};
+use netneighbor::netlink::{self, NeighborEvent};
+use netneighbor::output::{format_device_record, format_event_as, format_summary, format_summary_until, OutputFormat};
+use netneighbor::pcap::CaptureFile;
+use netneighbor::probe::{ProbeConfig, Prober};
+use netneighbor::security::{self, SecurityConfig};
+use netneighbor::sink::StdoutSink;
+use netneighbor::source::{self, KernelSource, MergedSource};
+use netneighbor::sweep::{ArpSweep, SweepConfig, SweepSource};
+use netneighbor::tui;
+use netneighbor::webhook::{WebhookConfig, WebhookSink};
+use netneighbor::{AddressFamily, Event, EventKind, EventSink, MacAddr, NeighState, NeighborSource, Tracker, TrackerConfig};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// TOML config file applied over /etc/netneighbor/config.toml and ~/.config/netneighbor/config.toml
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
     /// Refresh interval in seconds
     #[arg(short, long, default_value_t = 2)]
     interval: u64,
@@ -31,284 +62,1089 @@ struct Args {
     /// Disconnection timeout in seconds (device considered disconnected after not seen for this duration)
     #[arg(long, default_value_t = 10)]
     disconnect_timeout: u64,
-}
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct Device {
-    ip_address: String,
-    mac_address: String,
-    interface: String,
+    /// React to kernel neighbor notifications instead of polling at a fixed interval
+    #[arg(short, long, default_value_t = false)]
+    watch: bool,
+
+    /// In watch mode, full resync interval in seconds to catch dropped notifications (0 disables)
+    #[arg(long, default_value_t = 60)]
+    resync: u64,
+
+    /// Neighbor states that count as the device being present (comma separated)
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = NeighState::DEFAULT_PRESENT)]
+    present_states: Vec<NeighState>,
+
+    /// Seconds a connected device's STALE neighbor entry still counts as present, from when it was first seen STALE
+    #[arg(long, default_value_t = 60)]
+    stale_timeout: u64,
+
+    /// Address families to track
+    #[arg(long, value_enum, default_value_t = AddressFamily::All)]
+    family: AddressFamily,
+
+    /// Ignore link-local addresses (169.254.0.0/16, fe80::/10)
+    #[arg(long, default_value_t = false)]
+    exclude_link_local: bool,
+
+    /// Sources whose hostname and client identifier win when sources disagree, most trusted first (comma separated)
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values_t = source::DEFAULT_PRECEDENCE.map(String::from),
+        value_parser = source::DEFAULT_PRECEDENCE,
+    )]
+    source_precedence: Vec<String>,
+
+    /// Also discover devices from ARP, IPv6 neighbor discovery and DHCP traffic seen on the wire (needs CAP_NET_RAW)
+    #[arg(long, default_value_t = false)]
+    capture: bool,
+
+    /// Seconds a device heard in captured traffic or mDNS announcements keeps counting as present after it was last heard
+    #[arg(long, default_value_t = 120)]
+    sighting_ttl: u64,
+
+    /// Learn device hostnames and services (AirPlay, printers, Chromecast, ...) from mDNS announcements (needs CAP_NET_RAW)
+    #[arg(long, default_value_t = false)]
+    mdns: bool,
+
+    /// Confirm devices with unicast ARP / Neighbor Solicitation probes before reporting them disconnected (needs CAP_NET_RAW)
+    #[arg(long, default_value_t = false)]
+    probe: bool,
+
+    /// Unanswered probes before a device's address is given up
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    probe_count: u32,
+
+    /// Seconds between probes
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    probe_interval: u64,
+
+    /// Actively sweep each monitored interface's IPv4 subnets with ARP requests (needs CAP_NET_RAW)
+    #[arg(long, default_value_t = false)]
+    arp_sweep: bool,
+
+    /// Seconds between ARP sweeps
+    #[arg(long, default_value_t = 300)]
+    sweep_interval: u64,
+
+    /// ARP requests sent per second during a sweep
+    #[arg(long, default_value_t = 50)]
+    sweep_rate: u32,
+
+    /// Disable ARP spoofing and MAC conflict alerts
+    #[arg(long, default_value_t = false)]
+    no_security_alerts: bool,
+
+    /// Alert when one MAC claims at least this many addresses within --ip-claim-window
+    #[arg(long, default_value_t = 8)]
+    max_ips_per_mac: usize,
+
+    /// Window in seconds for --max-ips-per-mac
+    #[arg(long, default_value_t = 60)]
+    ip_claim_window: u64,
+
+    /// TOML inventory of known devices (MAC to name, owner and tags) shown in events
+    #[arg(long, global = true)]
+    inventory: Option<PathBuf>,
+
+    /// Flag CONNECTED events for MACs that are not in the inventory
+    #[arg(long, default_value_t = false)]
+    alert_unknown: bool,
+
+    /// Show device hostnames from /etc/hosts and reverse DNS
+    #[arg(long, default_value_t = false)]
+    resolve_hostnames: bool,
+
+    /// DHCP server lease file (dnsmasq, ISC dhcpd or Kea CSV) to take device hostnames from (repeatable)
+    #[arg(long)]
+    leases: Vec<PathBuf>,
+
+    /// Also report every current lease in the --leases files as a device on the subnet it belongs to
+    #[arg(long, default_value_t = false, requires = "leases")]
+    lease_devices: bool,
+
+    /// Only add the client identifier and hostname of current leases in the --leases files to devices other sources see
+    #[arg(long, default_value_t = false, requires = "leases", conflicts_with = "lease_devices")]
+    lease_details: bool,
+
+    /// Seconds a reverse DNS lookup may take before it is given up
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u64).range(1..))]
+    dns_timeout: u64,
+
+    /// Seconds reverse DNS answers are cached
+    #[arg(long, default_value_t = 300)]
+    hostname_ttl: u64,
+
+    /// POST events as JSON to this HTTP(S) URL (repeatable)
+    #[arg(long)]
+    webhook: Vec<String>,
+
+    /// Event types sent to webhooks (comma separated, default all)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    webhook_events: Vec<EventKind>,
+
+    /// File with the webhook request body, using {{event}}, {{ip}}, {{mac}}, {{text}}, ... placeholders
+    #[arg(long)]
+    webhook_template: Option<PathBuf>,
+
+    /// Events queued for webhook delivery before new ones are dropped
+    #[arg(long, default_value_t = 256)]
+    webhook_queue_size: usize,
+
+    /// Retries for a failed webhook delivery, with exponential backoff starting at 1 second
+    #[arg(long, default_value_t = 3)]
+    webhook_retries: u32,
+
+    /// Serve HTTP endpoints (Prometheus /metrics and the JSON API) on this address, e.g. 127.0.0.1:9464
+    #[arg(long)]
+    listen: Option<String>,
+
+    /// Full-screen dashboard with a live device table and event log instead of streaming output
+    #[arg(long, default_value_t = false)]
+    tui: bool,
+
+    /// Recent events kept for the /events endpoint and the dashboard event log
+    #[arg(long, default_value_t = 1000)]
+    event_buffer: usize,
+
+    /// Output format for events
+    #[arg(short, long, value_enum, global = true, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// SQLite database recording connection history and device first/last seen times
+    #[arg(long, global = true)]
+    db: Option<PathBuf>,
 }
 
-impl Device {
-    fn new(ip: String, mac: String, interface: String) -> Self {
-        Device {
-            ip_address: ip,
-            mac_address: mac,
-            interface,
-        }
-    }
-    
-    fn key(&self) -> String {
-        format!("{}-{}", self.ip_address, self.mac_address)
-    }
+#[derive(Subcommand)]
+enum Commands {
+    /// Query connection history recorded with --db
+    History(HistoryArgs),
+    /// Replay ARP, NDP, DHCP and mDNS traffic from a capture file through the tracker on the capture's own clock
+    Replay(ReplayArgs),
+    /// Inspect the layered configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
 }
 
-#[derive(Debug)]
-struct TrackedDevice {
-    device: Device,
-    last_seen: Instant,
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Validate the config files and print the effective configuration
+    Check,
 }
 
-#[derive(Debug)]
-struct MonitoringStats {
-    total_devices_seen: usize,
-    peak_concurrent_devices: usize,
-    start_time: Instant,
+#[derive(clap::Args)]
+struct ReplayArgs {
+    /// pcap or pcapng file (Ethernet or Linux cooked capture)
+    #[arg(long)]
+    pcap: PathBuf,
 }
 
-// Function to format device information with colors for better readability
-fn format_device_output(event: &str, ip: &str, mac: &str, interface: &str) {
-    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+#[derive(clap::Args)]
+struct HistoryArgs {
+    /// Only entries for this MAC address
+    #[arg(long)]
+    mac: Option<MacAddr>,
 
-    // Get vendor information from MAC address
-    let vendor = get_vendor_from_mac(mac);
+    /// Only entries for this IP address
+    #[arg(long, value_parser = parse_ip)]
+    ip: Option<IpAddr>,
 
-    // Color the event based on connection type
-    let event_text = if event == "CONNECTED" {
-        format!("[{}] {}", timestamp, "[CONNECTED]".green())
-    } else {
-        format!("[{}] {}", timestamp, "[DISCONNECTED]".red())
-    };
+    /// Only entries whose vendor name contains this text (case-insensitive)
+    #[arg(long)]
+    vendor: Option<String>,
 
-    if let Some(vendor_name) = vendor {
-        println!("{} IP: {} | MAC: {} | Vendor: {} | Interface: {}",
-                 event_text,
-                 ip.blue(),
-                 mac.yellow(),
-                 vendor_name.cyan(),
-                 interface.magenta());
-    } else {
-        println!("{} IP: {} | MAC: {} | Vendor: Unknown | Interface: {}",
-                 event_text,
-                 ip.blue(),
-                 mac.yellow(),
-                 interface.magenta());
+    /// Only entries at or after this time (RFC 3339, "YYYY-MM-DD HH:MM:SS" or "YYYY-MM-DD")
+    #[arg(long, value_parser = parse_time)]
+    since: Option<DateTime<Local>>,
+
+    /// Only entries at or before this time (same formats as --since)
+    #[arg(long, value_parser = parse_time)]
+    until: Option<DateTime<Local>>,
+
+    /// Maximum number of entries to show (most recent)
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// List devices with their first/last seen times instead of events
+    #[arg(long, default_value_t = false)]
+    devices: bool,
+}
+
+// Accept RFC 3339 timestamps or local date/time in a few common forms
+fn parse_time(value: &str) -> Result<DateTime<Local>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Local));
     }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .ok_or_else(|| format!("invalid time '{}'", value))?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| format!("invalid local time '{}'", value))
 }
 
-// Function to get vendor name from MAC address
-fn get_vendor_from_mac(mac: &str) -> Option<String> {
-    // Use the oui-data crate to look up the vendor
-    match lookup(mac) {
-        Some(vendor) => Some(vendor.organization().to_string()),
-        None => None,
+// An IP address in any form, including IPv4 octets with leading zeros
+fn parse_ip(value: &str) -> Result<IpAddr, String> {
+    if let Ok(ip) = value.parse() {
+        return Ok(ip);
+    }
+    let octets: Vec<u8> = value
+        .split('.')
+        .map(|octet| octet.bytes().all(|byte| byte.is_ascii_digit()).then(|| octet.parse().ok()).flatten())
+        .collect::<Option<_>>()
+        .ok_or_else(|| format!("invalid IP address '{}'", value))?;
+    let octets: [u8; 4] = octets.try_into().map_err(|_| format!("invalid IP address '{}'", value))?;
+    Ok(IpAddr::V4(Ipv4Addr::from(octets)))
+}
+
+// Informational messages go to stderr in JSON mode so stdout stays valid NDJSON,
+// and are dropped while the dashboard owns the terminal
+fn info(args: &Args, message: &str) {
+    if args.tui {
+        return;
+    }
+    match args.output {
+        OutputFormat::Text => println!("{}", message),
+        OutputFormat::Json => eprintln!("{}", message),
     }
 }
 
-fn get_network_devices(interface: Option<&str>) -> Result<Vec<Device>, Box<dyn std::error::Error>> {
-    let mut devices = Vec::new();
+// Options that start sockets, threads or the dashboard; a reload leaves them as they are
+const RESTART_OPTIONS: [&str; 12] = [
+    "capture", "mdns", "sighting-ttl", "probe", "arp-sweep", "sweep-interval", "sweep-rate", "lease-devices", "lease-details",
+    "listen", "tui", "event-buffer",
+];
+// Options the event sinks are built from
+const SINK_OPTIONS: [&str; 7] = ["output", "db", "webhook", "webhook-events", "webhook-template", "webhook-queue-size", "webhook-retries"];
+// Options hostname lookups are set up from
+const HOSTNAME_OPTIONS: [&str; 4] = ["resolve-hostnames", "leases", "dns-timeout", "hostname-ttl"];
+// Options the monitoring loops read when they start
+const LOOP_OPTIONS: [&str; 4] = ["watch", "interface", "resync", "probe-interval"];
 
-    // Execute both commands in a single shell to reduce process overhead
-    let script = "arp -a -n; echo '===SPLIT==='; ip neigh show";
-    let output = Command::new("sh")
-        .args(&["-c", script])
-        .output()?;
+// Longest the monitoring loops go without checking for a reload request
+const RELOAD_CHECK: Duration = Duration::from_secs(1);
 
-    if output.status.success() {
-        let content = String::from_utf8_lossy(&output.stdout);
-        let parts: Vec<&str> = content.split("===SPLIT===").collect();
-        
-        if parts.len() >= 2 {
-            parse_arp_entries(parts[0], &mut devices, interface)?;
-            parse_ip_neigh_entries(parts[1], &mut devices, interface)?;
+// Stops a background thread that checks `stop` between blocking reads, and waits
+// for it to finish
+struct StopOnDrop {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
         }
     }
+}
 
-    Ok(devices)
+// Everything the monitoring loops need: the tracker, where sightings come from
+// and where the resulting events go
+struct Monitor {
+    args: Args,
+    // The configuration and the command line it was parsed from, re-read on
+    // SIGHUP, and the configuration things were started with
+    layered: config::Layered,
+    started: config::Layered,
+    argv: Vec<OsString>,
+    tracker: Arc<Mutex<Tracker>>,
+    source: MergedSource,
+    sinks: Vec<Box<dyn EventSink>>,
+    events: Arc<EventBuffer>,
+    metrics: Option<Arc<Metrics>>,
+    prober: Option<Prober>,
+    // Interface packet capture, the mDNS listener and the ARP sweep were started
+    // on, which they keep until a restart
+    passive_interface: Option<String>,
+    // Events not passed to the sinks yet: CONNECTED events waiting for their
+    // device's reverse lookup, and later events for the same devices
+    held: VecDeque<Event>,
+    // Output format of the exit summary, following reloads
+    summary_format: Arc<Mutex<OutputFormat>>,
 }
 
-fn parse_arp_entries(content: &str, devices: &mut Vec<Device>, interface_filter: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
-    for line in content.lines() {
-        // Example ARP entry: "? (192.168.1.1) at aa:bb:cc:dd:ee:ff [ether] on wlan0"
-        if line.contains("at") && line.contains("on") {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 7 {
-                // Extract IP address (removing parentheses)
-                let ip = parts[1].trim_start_matches('(').trim_end_matches(')');
-
-                // Extract MAC address (after "at")
-                let mac = parts[3];
-
-                // Extract interface (after "on", which is at index 5, interface is at index 6)
-                let iface = parts[6];
-
-                // Apply interface filter if specified
-                if let Some(filter) = interface_filter {
-                    if iface != filter {
-                        continue;
-                    }
+impl Monitor {
+    fn report(&mut self, tracker: &Tracker, events: Vec<Event>) {
+        self.held.extend(events);
+        self.release(tracker);
+    }
+
+    // Pass held events on in order, except CONNECTED events whose device's
+    // reverse lookup is still running (up to --dns-timeout), so the name lands
+    // in the CONNECTED event. Later events for the same device wait behind them.
+    // Returns whether any event was passed on.
+    fn release(&mut self, tracker: &Tracker) -> bool {
+        let hostnames = tracker.hostnames();
+        let mut waiting = HashSet::new();
+        let mut released = false;
+        for mut event in mem::take(&mut self.held) {
+            let mac = event.device.mac_address;
+            if waiting.contains(&mac)
+                || event.kind == EventKind::Connected && event.hostname.is_none() && hostnames.resolving(&event.device)
+            {
+                waiting.insert(mac);
+                self.held.push_back(event);
+                continue;
+            }
+            if event.kind == EventKind::Connected && event.hostname.is_none() {
+                event.hostname = hostnames.lookup(&event.device);
+            }
+            for sink in self.sinks.iter_mut() {
+                sink.emit(&event);
+            }
+            released = true;
+        }
+        released
+    }
+
+    // Pass on held events whose lookups have finished since
+    fn release_held(&mut self) {
+        if self.held.is_empty() {
+            return;
+        }
+        let tracker = Arc::clone(&self.tracker);
+        let tracker = tracker.lock().unwrap();
+        if self.release(&tracker) {
+            self.flush(&tracker);
+        }
+    }
+
+    fn flush(&mut self, tracker: &Tracker) {
+        for sink in self.sinks.iter_mut() {
+            sink.flush(tracker);
+        }
+    }
+
+    // Take a full snapshot from the source and apply it to the tracker
+    fn scan(&mut self) {
+        let started = Instant::now();
+        let result = self.source.scan();
+        if let Some(ref metrics) = self.metrics {
+            metrics.record_scan(started.elapsed(), result.is_ok());
+        }
+        let skipped = self.source.take_skipped();
+        self.tracker.lock().unwrap().record_skipped(&skipped);
+        if self.args.verbose {
+            for line in &skipped.lines {
+                info(&self.args, &format!("Skipping unusable {}", line));
+            }
+        }
+
+        match result {
+            Ok(current_devices) => {
+                let tracker = Arc::clone(&self.tracker);
+                let mut tracker = tracker.lock().unwrap();
+                // Gateways can change (e.g. DHCP renewals), so refresh them with every snapshot
+                tracker.set_gateways(security::default_gateways());
+                let events = tracker.update(current_devices, Instant::now());
+                self.report(&tracker, events);
+                self.flush(&tracker);
+
+                if self.args.verbose && tracker.devices().next().is_none() {
+                    info(&self.args, &format!("[{}] No devices detected", Local::now().format("%Y-%m-%d %H:%M:%S")));
                 }
+            }
+            Err(e) => {
+                diag::warn(&format!("Error reading network state: {}", e));
+            }
+        }
+    }
 
-                // Only add if IP looks like a valid network address (not localhost or multicast)
-                if !ip.starts_with("127.") && !ip.starts_with("224.") && !ip.starts_with("255.") && ip != "::1" {
-                    devices.push(Device::new(ip.to_string(), mac.to_string(), iface.to_string()));
+    // Apply the devices passive sources heard since the last call, so they
+    // show up without waiting for the next resync
+    fn observe_heard(&mut self) {
+        let heard = self.source.take_heard();
+        if heard.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        let tracker = Arc::clone(&self.tracker);
+        let mut tracker = tracker.lock().unwrap();
+
+        let mut events = Vec::new();
+        for device in heard {
+            events.extend(tracker.observe(device, now));
+        }
+        self.report(&tracker, events);
+        self.flush(&tracker);
+    }
+
+    // Feed probe answers to the tracker, send the probes that are due and drop
+    // addresses whose probes all went unanswered
+    fn probe(&mut self) {
+        let Some(ref prober) = self.prober else {
+            return;
+        };
+        let now = Instant::now();
+        let tracker = Arc::clone(&self.tracker);
+        let mut tracker = tracker.lock().unwrap();
+
+        let mut events = Vec::new();
+        for device in prober.answers() {
+            events.extend(tracker.observe(device, now));
+        }
+        prober.probe(&tracker.due_probes(now));
+        events.extend(tracker.expire_unanswered(now));
+
+        if !events.is_empty() {
+            self.report(&tracker, events);
+            self.flush(&tracker);
+        }
+    }
+
+    // Sleep for `duration`, handling liveness probes every probe interval meanwhile.
+    // Returns early when a reload is requested.
+    fn wait(&mut self, duration: Duration) {
+        let step = self.prober.as_ref().map(|_| Duration::from_secs(self.args.probe_interval));
+        let until = Instant::now() + duration;
+        let mut next_probe = Instant::now();
+        loop {
+            if let Some(step) = step
+                && Instant::now() >= next_probe
+            {
+                self.probe();
+                next_probe = Instant::now() + step;
+            }
+            self.release_held();
+            let now = Instant::now();
+            if now >= until || config::reload_pending() {
+                return;
+            }
+            let wake = [Some(until), step.map(|_| next_probe)].into_iter().flatten().min().unwrap_or(until);
+            thread::sleep(RELOAD_CHECK.min(wake - now));
+        }
+    }
+
+    // Re-read the configuration and apply what changed in place, keeping every
+    // tracked device. Options behind running sockets and threads keep their
+    // values until a restart. Returns whether the monitoring loop has to start
+    // over to pick up the changes.
+    fn reload(&mut self) -> bool {
+        let stamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+        // Everything that can fail comes first, so a bad config changes nothing
+        let prepared = config::parse(Args::command(), self.argv.clone()).and_then(|layered| {
+            let mut args = Args::from_arg_matches(&layered.matches)?;
+            keep_running(&mut args, &self.args);
+            // What a restart would change is measured from what is running
+            let restart = |change: &config::Change| RESTART_OPTIONS.contains(&change.option.as_str());
+            let mut changes: Vec<_> = self.layered.changes(&layered).into_iter().filter(|change| !restart(change)).collect();
+            changes.extend(self.started.changes(&layered).into_iter().filter(restart));
+            let inventory = load_inventory(&args)?;
+            // A webhook template file is read again like the inventory, so its edits apply too
+            let reread_template = args.webhook_template.is_some() && !args.webhook.is_empty();
+            let sinks = match reread_template || changes.iter().any(|change| SINK_OPTIONS.contains(&change.option.as_str())) {
+                true => Some(event_sinks(&args, &self.events, self.metrics.as_ref())?),
+                false => None,
+            };
+            Ok((layered, args, changes, inventory, sinks))
+        });
+        let (layered, args, changes, inventory, sinks) = match prepared {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                diag::warn(&format!("[{}] Configuration reload failed ({}), keeping the current configuration", stamp, e));
+                return false;
+            }
+        };
+        let changed = |options: &[&str]| changes.iter().any(|change| options.contains(&change.option.as_str()));
+
+        if let Some(sinks) = sinks {
+            self.sinks = sinks;
+        }
+        if changed(&["interface"]) {
+            self.source.set_interface(args.interface.as_deref());
+        }
+        if changed(&["source-precedence"]) {
+            self.source.set_precedence(args.source_precedence.clone());
+        }
+        let forgotten = {
+            let mut tracker = self.tracker.lock().unwrap();
+            if changed(&HOSTNAME_OPTIONS) {
+                tracker.set_hostnames(hostnames(&args));
+            }
+            tracker.set_inventory(inventory);
+            tracker.set_config(live_tracker_config(&args, self.prober.is_some()))
+        };
+
+        let restart_loop = changed(&LOOP_OPTIONS);
+        *self.summary_format.lock().unwrap() = args.output;
+        self.args = args;
+        self.layered = layered;
+
+        match changes.is_empty() {
+            true => info(&self.args, &format!("[{}] Configuration reloaded, nothing changed", stamp)),
+            false => info(&self.args, &format!("[{}] Configuration reloaded:", stamp)),
+        }
+        for change in &changes {
+            let option = change.option.as_str();
+            match RESTART_OPTIONS.contains(&option) || option == "leases" && (self.args.lease_devices || self.args.lease_details) {
+                true => info(&self.args, &format!("  {} (takes effect after a restart)", change)),
+                false => info(&self.args, &format!("  {}", change)),
+            }
+        }
+        if let Some(ref started) = self.passive_interface
+            && self.args.interface.as_ref() != Some(started)
+        {
+            info(&self.args, &format!("  Packet capture, mDNS and ARP sweep keep listening on {} until a restart", started));
+        }
+        if forgotten > 0 {
+            let devices = match forgotten {
+                1 => "1 device".to_string(),
+                count => format!("{} devices", count),
+            };
+            info(&self.args, &format!("  Stopped tracking {} outside the new filters", devices));
+        }
+        restart_loop
+    }
+
+    // Reload if SIGHUP asked for it; true if the monitoring loop has to start over
+    fn reload_if_requested(&mut self) -> bool {
+        config::take_reload_request() && self.reload()
+    }
+
+    // Watch for notifications if requested, polling otherwise or if they are
+    // unavailable. A reload that changes how to monitor starts over.
+    fn run(&mut self) -> ! {
+        loop {
+            if self.args.watch {
+                match self.watch_loop() {
+                    Ok(()) => continue,
+                    Err(e) => diag::warn(&format!("Neighbor notifications unavailable ({}), falling back to polling", e)),
                 }
             }
+            self.poll_loop();
+        }
+    }
+
+    // Returns when a reload turns on watch mode
+    fn poll_loop(&mut self) {
+        loop {
+            self.scan();
+            self.wait(Duration::from_secs(self.args.interval));
+            if self.reload_if_requested() && self.args.watch {
+                return;
+            }
         }
     }
-    Ok(())
-}
 
-fn parse_ip_neigh_entries(content: &str, devices: &mut Vec<Device>, interface_filter: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
-    for line in content.lines() {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() >= 6 {
-            let ip = parts[0];
-
-            // Look for MAC address after "lladdr" and interface after "dev"
-            let mut mac = None;
-            let mut iface = None;
-
-            let mut i = 0;
-            while i < parts.len() {
-                if parts[i] == "lladdr" && i + 1 < parts.len() {
-                    mac = Some(parts[i + 1]);
-                } else if parts[i] == "dev" && i + 1 < parts.len() {
-                    iface = Some(parts[i + 1]);
+    // React to kernel neighbor notifications as they arrive, with an optional periodic
+    // full resync. Fails if the subscription fails so the caller can fall back to
+    // polling, and returns when a reload changes the watch settings.
+    fn watch_loop(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut monitor = netlink::NeighborMonitor::subscribe(self.args.interface.as_deref())?;
+
+        // Read notifications on a dedicated thread so the resync timer keeps running.
+        // Whichever way this loop returns, the thread is stopped and the subscription
+        // closed before a restart subscribes again.
+        let (sender, receiver) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let reader_stop = Arc::clone(&stop);
+        let reader = thread::spawn(move || {
+            while !reader_stop.load(Ordering::Relaxed) {
+                let batch = monitor.next_events();
+                if batch.as_ref().is_ok_and(Vec::is_empty) {
+                    continue;
+                }
+                let failed = batch.as_ref().is_err_and(|e| e.raw_os_error() != Some(libc::ENOBUFS));
+                if sender.send(batch).is_err() || failed {
+                    return;
                 }
-                i += 1;
             }
+        });
+        let _reader = StopOnDrop { stop, thread: Some(reader) };
 
-            // Apply interface filter if specified
-            if let Some(filter) = interface_filter {
-                if let Some(iface_val) = iface {
-                    if iface_val != filter {
-                        continue;
+        // Start from a full snapshot so devices already present are reported
+        let mut resync_needed = true;
+        let resync_interval = (self.args.resync > 0).then(|| Duration::from_secs(self.args.resync));
+        let mut next_resync = Instant::now();
+        let probe_interval = self.prober.as_ref().map(|_| Duration::from_secs(self.args.probe_interval));
+        let mut next_probe = Instant::now();
+
+        loop {
+            if self.reload_if_requested() {
+                return Ok(());
+            }
+            self.observe_heard();
+            self.release_held();
+            if resync_needed || resync_interval.is_some() && Instant::now() >= next_resync {
+                self.scan();
+                resync_needed = false;
+                if let Some(interval) = resync_interval {
+                    next_resync = Instant::now() + interval;
+                }
+            }
+            if let Some(interval) = probe_interval
+                && Instant::now() >= next_probe
+            {
+                self.probe();
+                next_probe = Instant::now() + interval;
+            }
+
+            // Wake up for whichever of the resync, the next probe round and the reload check comes first
+            let deadline = [resync_interval.map(|_| next_resync), probe_interval.map(|_| next_probe)]
+                .into_iter()
+                .flatten()
+                .fold(Instant::now() + RELOAD_CHECK, Instant::min);
+            let batch = match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(batch) => batch,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Err("neighbor notification thread stopped".into()),
+            };
+
+            match batch {
+                Ok(notifications) => {
+                    let now = Instant::now();
+                    let tracker = Arc::clone(&self.tracker);
+                    let mut tracker = tracker.lock().unwrap();
+                    for notification in notifications {
+                        match notification {
+                            NeighborEvent::Added(device) => {
+                                let events = tracker.observe(device, now);
+                                self.report(&tracker, events);
+                            }
+                            NeighborEvent::Removed { ip_address, interface } => {
+                                let events = tracker.remove(ip_address, &interface, now);
+                                self.report(&tracker, events);
+                            }
+                            // Unresolved entries no longer refresh last_seen, so they time out at the next resync
+                            NeighborEvent::Unresolved { ip_address, interface, state } => {
+                                tracker.set_state(ip_address, &interface, state)
+                            }
+                        }
                     }
+                    self.flush(&tracker);
                 }
+                // The kernel dropped notifications because we fell behind; rebuild from a full dump
+                Err(e) if e.raw_os_error() == Some(libc::ENOBUFS) => {
+                    if self.args.verbose {
+                        info(&self.args, &format!("[{}] Neighbor notifications overflowed, resyncing", Local::now().format("%Y-%m-%d %H:%M:%S")));
+                    }
+                    resync_needed = true;
+                }
+                Err(e) => return Err(e.into()),
             }
+        }
+    }
+}
+
+// Print recorded events or device summaries from the history database
+fn run_history(args: &Args, history_args: &HistoryArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let path = args.db.as_ref().ok_or("the history command requires --db PATH")?;
+    let history = History::open(path)?;
+    let inventory = load_inventory(args)?;
+
+    let filter = HistoryFilter {
+        mac: history_args.mac,
+        ip: history_args.ip,
+        vendor: history_args.vendor.clone(),
+        since: history_args.since,
+        until: history_args.until,
+        limit: history_args.limit,
+    };
+
+    if history_args.devices {
+        for record in history.devices(&filter)? {
+            println!("{}", format_device_record(args.output, &record));
+        }
+    } else {
+        for mut event in history.events(&filter)? {
+            inventory.annotate(&mut event, false);
+            println!("{}", format_event_as(args.output, &event));
+        }
+    }
+
+    Ok(())
+}
+
+// Tracker settings shared by live monitoring and replay
+fn tracker_config(args: &Args) -> TrackerConfig {
+    TrackerConfig {
+        disconnect_timeout: Duration::from_secs(args.disconnect_timeout),
+        present_states: args.present_states.clone(),
+        stale_timeout: Duration::from_secs(args.stale_timeout),
+        security: SecurityConfig {
+            enabled: !args.no_security_alerts,
+            max_addresses_per_mac: args.max_ips_per_mac,
+            claim_window: Duration::from_secs(args.ip_claim_window),
+        },
+        alert_unknown: args.alert_unknown,
+        probe: None,
+        family: args.family,
+        link_local: !args.exclude_link_local,
+        interface: args.interface.clone(),
+    }
+}
+
+// Tracker settings for live monitoring, probing if the prober is running
+fn live_tracker_config(args: &Args, probing: bool) -> TrackerConfig {
+    TrackerConfig {
+        probe: probing.then(|| ProbeConfig {
+            count: args.probe_count,
+            interval: Duration::from_secs(args.probe_interval),
+        }),
+        ..tracker_config(args)
+    }
+}
+
+// Carry over the settings of what is already running, which a reload can't change
+fn keep_running(args: &mut Args, running: &Args) {
+    args.capture = running.capture;
+    args.mdns = running.mdns;
+    args.sighting_ttl = running.sighting_ttl;
+    args.probe = running.probe;
+    args.arp_sweep = running.arp_sweep;
+    args.sweep_interval = running.sweep_interval;
+    args.sweep_rate = running.sweep_rate;
+    args.lease_devices = running.lease_devices;
+    args.lease_details = running.lease_details;
+    args.listen = running.listen.clone();
+    args.tui = running.tui;
+    args.event_buffer = running.event_buffer;
+}
+
+fn load_inventory(args: &Args) -> Result<Inventory, Box<dyn std::error::Error>> {
+    match &args.inventory {
+        Some(path) => Inventory::load(path),
+        None => Ok(Inventory::default()),
+    }
+}
+
+fn hostnames(args: &Args) -> Hostnames {
+    Hostnames::new(HostnameConfig {
+        reverse_dns: args.resolve_hostnames,
+        hosts_file: args.resolve_hostnames.then(|| PathBuf::from(hostname::HOSTS_FILE)),
+        lease_files: args.leases.clone(),
+        dns_timeout: Duration::from_secs(args.dns_timeout),
+        cache_ttl: Duration::from_secs(args.hostname_ttl),
+    })
+}
+
+// Where events go: stdout unless the dashboard owns the terminal, the history
+// database, webhooks, and the event buffer and metrics behind --listen and --tui
+fn event_sinks(
+    args: &Args,
+    events: &Arc<EventBuffer>,
+    metrics: Option<&Arc<Metrics>>,
+) -> Result<Vec<Box<dyn EventSink>>, Box<dyn std::error::Error>> {
+    let mut sinks: Vec<Box<dyn EventSink>> = Vec::new();
+    if !args.tui {
+        sinks.push(Box::new(StdoutSink::new(args.output)));
+    }
+    if let Some(ref path) = args.db {
+        sinks.push(Box::new(History::open(path)?));
+    }
+
+    let template = match args.webhook_template {
+        Some(ref path) => Some(
+            std::fs::read_to_string(path)
+                .map_err(|e| format!("cannot read webhook template {}: {}", path.display(), e))?,
+        ),
+        None => None,
+    };
+    for url in &args.webhook {
+        sinks.push(Box::new(WebhookSink::new(WebhookConfig {
+            events: args.webhook_events.clone(),
+            template: template.clone(),
+            queue_size: args.webhook_queue_size,
+            max_retries: args.webhook_retries,
+            ..WebhookConfig::new(url)
+        })));
+    }
+
+    if args.listen.is_some() || args.tui {
+        sinks.push(Box::new(EventBufferSink::new(Arc::clone(events))));
+    }
+    if let Some(metrics) = metrics {
+        sinks.push(Box::new(MetricsSink::new(Arc::clone(metrics))));
+    }
+    Ok(sinks)
+}
+
+// Run the sightings in a capture file through the tracker, with packet timestamps
+// as the clock. Disconnections are checked every --interval seconds of capture
+// time, as the live monitor would have checked them at its scans.
+fn run_replay(args: &Args, replay_args: &ReplayArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let path = &replay_args.pcap;
+    let capture = CaptureFile::open(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+
+    let mut sinks: Vec<Box<dyn EventSink>> = vec![Box::new(StdoutSink::new(args.output))];
+    if let Some(ref db) = args.db {
+        sinks.push(Box::new(History::open(db)?));
+    }
+    let inventory = load_inventory(args)?;
+
+    let origin = Instant::now();
+    let interval = Duration::from_secs(args.interval.max(1));
+    let mut tracker: Option<Tracker> = None;
+    let mut next_scan = origin + interval;
+    let mut now = origin;
+
+    for packet in capture {
+        let packet = packet.map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        let interface = packet.interface.as_deref().unwrap_or("pcap");
+        if args.interface.as_ref().is_some_and(|name| name != interface) {
+            continue;
+        }
+
+        let tracker = tracker.get_or_insert_with(|| {
+            let mut tracker = Tracker::with_origin(tracker_config(args), origin, packet.timestamp);
+            tracker.set_inventory(inventory.clone());
+            tracker
+        });
+        // Packets of different interfaces can be slightly out of order
+        let offset = (packet.timestamp - tracker.wall_time(origin)).to_std().unwrap_or_default();
+        now = now.max(origin + offset);
 
-            if let (Some(mac_val), Some(iface_val)) = (mac, iface) {
-                devices.push(Device::new(ip.to_string(), mac_val.to_string(), iface_val.to_string()));
+        let mut events = Vec::new();
+        while next_scan <= now {
+            events.extend(tracker.update(Vec::new(), next_scan));
+            next_scan += interval;
+        }
+        for device in capture::sightings(&packet.frame, interface).into_iter().chain(mdns::sighting(&packet.frame, interface)) {
+            events.extend(tracker.observe(device, now));
+        }
+
+        for event in events {
+            for sink in sinks.iter_mut() {
+                sink.emit(&event);
             }
         }
     }
+
+    let Some(tracker) = tracker else {
+        return Err(format!("no packets in {}", path.display()).into());
+    };
+    for sink in sinks.iter_mut() {
+        sink.flush(&tracker);
+    }
+    println!("{}", format_summary_until(args.output, tracker.stats(), now, tracker.wall_time(now)));
     Ok(())
 }
 
+// Print which config files were read and every option's effective value
+fn run_config_check(layered: &config::Layered) {
+    if layered.files.is_empty() {
+        println!("# No config files found");
+    }
+    for path in &layered.files {
+        println!("# Read {}", path.display());
+    }
+    println!("{}", layered.to_toml());
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    let layered = config::parse(Args::command(), argv.clone())?;
+    let args = Args::from_arg_matches(&layered.matches).unwrap_or_else(|e| e.exit());
+
+    match &args.command {
+        Some(Commands::History(history_args)) => return run_history(&args, history_args),
+        Some(Commands::Replay(replay_args)) => return run_replay(&args, replay_args),
+        Some(Commands::Config(ConfigCommand::Check)) => {
+            run_config_check(&layered);
+            return Ok(());
+        }
+        None => {}
+    }
 
-    println!("NetNeighbor - Network Connection Monitor");
-    println!("Monitoring every {} seconds", args.interval);
-    println!("Disconnection timeout: {} seconds", args.disconnect_timeout);
+    info(&args, "NetNeighbor - Network Connection Monitor");
+    if args.watch {
+        info(&args, "Watching kernel neighbor notifications");
+        if args.resync > 0 {
+            info(&args, &format!("Full resync every {} seconds", args.resync));
+        }
+    } else {
+        info(&args, &format!("Monitoring every {} seconds", args.interval));
+    }
+    info(&args, &format!("Disconnection timeout: {} seconds", args.disconnect_timeout));
     if let Some(ref iface) = args.interface {
-        println!("Interface: {}", iface);
+        info(&args, &format!("Interface: {}", iface));
     } else {
-        println!("Monitoring all interfaces");
+        info(&args, "Monitoring all interfaces");
+    }
+    match args.family {
+        AddressFamily::Inet => info(&args, "Tracking IPv4 neighbors only"),
+        AddressFamily::Inet6 => info(&args, "Tracking IPv6 neighbors only"),
+        AddressFamily::All => {}
+    }
+    if args.exclude_link_local {
+        info(&args, "Ignoring link-local addresses");
+    }
+    if let Some(ref db) = args.db {
+        info(&args, &format!("Recording history to {}", db.display()));
+    }
+    if let Some(ref inventory) = args.inventory {
+        info(&args, &format!("Known devices from {}", inventory.display()));
+    }
+    if args.resolve_hostnames {
+        info(&args, "Resolving hostnames from /etc/hosts and reverse DNS");
+    }
+    for path in &args.leases {
+        match (args.lease_devices, args.lease_details) {
+            (true, _) => info(&args, &format!("Devices and hostnames from DHCP leases in {}", path.display())),
+            (_, true) => info(&args, &format!("Client identifiers and hostnames from DHCP leases in {}", path.display())),
+            _ => info(&args, &format!("Hostnames from DHCP leases in {}", path.display())),
+        }
+    }
+    for url in &args.webhook {
+        info(&args, &format!("Sending events to {}", url));
+    }
+    if args.capture {
+        info(&args, "Capturing ARP, NDP and DHCP traffic");
     }
-    println!("Press Ctrl+C to stop\n");
+    if args.mdns {
+        info(&args, "Listening for mDNS announcements");
+    }
+    if args.probe {
+        info(&args, &format!("Probing devices {} times, {} seconds apart, before disconnecting them", args.probe_count, args.probe_interval));
+    }
+    if args.arp_sweep {
+        info(&args, &format!("ARP sweep every {} seconds at {} requests/s", args.sweep_interval, args.sweep_rate));
+    }
+    if let Some(ref addr) = args.listen {
+        info(&args, &format!("Serving metrics and API at http://{}/", addr));
+    }
+    info(&args, "Press Ctrl+C to stop\n");
 
-    // Initialize monitoring statistics
-    let stats = Arc::new(Mutex::new(MonitoringStats {
-        total_devices_seen: 0,
-        peak_concurrent_devices: 0,
-        start_time: Instant::now(),
-    }));
+    // From here on the dashboard may own the terminal, which shows diagnostics itself
+    if args.tui {
+        diag::hold();
+    }
 
-    // Store a clone for the signal handler
-    let stats_clone = Arc::clone(&stats);
+    let prober = match args.probe {
+        true => match Prober::start() {
+            Ok(prober) => Some(prober),
+            Err(e) => {
+                diag::warn(&format!("Liveness probing unavailable ({}), disconnecting on timeout only", e));
+                None
+            }
+        },
+        false => None,
+    };
 
-    // Set up Ctrl+C handler
-    ctrlc::set_handler(move || {
-        let stats = stats_clone.lock().unwrap();
-        
-        // Calculate duration
-        let duration = stats.start_time.elapsed();
-        let mins = duration.as_secs() / 60;
-        let secs = duration.as_secs() % 60;
-        
-        println!("\n{}", "=".repeat(50));
-        println!("{}", "NETNEIGHBOR SESSION SUMMARY".bold().yellow());
-        println!("{}", "=".repeat(50));
-        println!("Total devices seen: {}", stats.total_devices_seen);
-        println!("Peak concurrent devices: {}", stats.peak_concurrent_devices);
-        println!("Monitoring duration: {}m {}s", mins, secs);
-        println!("{}", "=".repeat(50));
-        
-        std::process::exit(0);
-    }).expect("Error setting Ctrl+C handler");
+    let tracker = Arc::new(Mutex::new(Tracker::new(live_tracker_config(&args, prober.is_some()))));
+    tracker.lock().unwrap().set_inventory(load_inventory(&args)?);
+    tracker.lock().unwrap().set_hostnames(hostnames(&args));
 
-    let mut tracked_devices: HashMap<String, TrackedDevice> = HashMap::new();
+    if let Some(ref path) = args.db {
+        // Devices still connected at the end of the last session aren't announced again
+        let known = History::open(path)?.connected_devices()?;
+        tracker.lock().unwrap().restore(known, Instant::now());
+    }
 
-    loop {
-        match get_network_devices(args.interface.as_deref()) {
-            Ok(current_devices) => {
-                let now = Instant::now();
-
-                // Create a set of current device keys for O(1) lookup instead of O(n) vector search
-                let current_device_keys: std::collections::HashSet<String> =
-                    current_devices.iter().map(|d| d.key()).collect();
-
-                // Process current devices - update last seen time
-                for device in current_devices {
-                    let key = device.key();
-
-                    // Check if this is a new connection
-                    if !tracked_devices.contains_key(&key) {
-                        format_device_output("CONNECTED", &device.ip_address, &device.mac_address, &device.interface);
-                        
-                        // Update stats for new device
-                        {
-                            let mut stats = stats.lock().unwrap();
-                            stats.total_devices_seen += 1;
-                        }
-                    }
+    // Recent events for the JSON API and the dashboard
+    let events = Arc::new(EventBuffer::new(args.event_buffer));
 
-                    // Update the tracked device with current time
-                    tracked_devices.insert(key, TrackedDevice {
-                        device,
-                        last_seen: now,
-                    });
+    let metrics = match args.listen {
+        Some(ref addr) => {
+            let metrics = Arc::new(Metrics::new());
+            let (server_metrics, server_tracker, server_events) =
+                (Arc::clone(&metrics), Arc::clone(&tracker), Arc::clone(&events));
+            http::serve(addr.as_str(), move |request| match request.path.as_str() {
+                "/metrics" => {
+                    let tracker = server_tracker.lock().unwrap();
+                    Response::ok("text/plain; version=0.0.4", server_metrics.render(&tracker))
                 }
+                _ => api::handle(request, &server_tracker, &server_events),
+            })
+            .map_err(|e| format!("cannot listen on {}: {}", addr, e))?;
+            Some(metrics)
+        }
+        None => None,
+    };
+    let sinks = event_sinks(&args, &events, metrics.as_ref())?;
 
-                // Update peak concurrent devices count
-                {
-                    let mut stats = stats.lock().unwrap();
-                    if tracked_devices.len() > stats.peak_concurrent_devices {
-                        stats.peak_concurrent_devices = tracked_devices.len();
-                    }
-                }
+    // Store a clone for the signal handler
+    let tracker_clone = Arc::clone(&tracker);
 
-                // Check for disconnections - devices not seen within timeout period
-                // Collect keys to remove to avoid borrowing issues
-                let mut keys_to_remove = Vec::new();
+    // Set up Ctrl+C handler
+    let summary_format = Arc::new(Mutex::new(args.output));
+    let handler_format = Arc::clone(&summary_format);
+    ctrlc::set_handler(move || {
+        let tracker = tracker_clone.lock().unwrap();
+        println!("{}", format_summary(*handler_format.lock().unwrap(), tracker.stats()));
 
-                for (key, tracked_device) in &tracked_devices {
-                    if now.duration_since(tracked_device.last_seen).as_secs() > args.disconnect_timeout {
-                        // Check if this device is still in current devices (it might have just been updated)
-                        if !current_device_keys.contains(key) {
-                            keys_to_remove.push((key.clone(), tracked_device.device.clone()));
-                        }
-                    }
-                }
+        std::process::exit(0);
+    }).expect("Error setting Ctrl+C handler");
 
-                // Report disconnections and remove from tracking
-                for (key, device) in keys_to_remove {
-                    format_device_output("DISCONNECTED", &device.ip_address, &device.mac_address, &device.interface);
-                    tracked_devices.remove(&key);
-                }
+    // Replaces the handler above for SIGHUP, which reloads the configuration instead
+    if let Err(e) = config::reload_on_sighup() {
+        diag::warn(&format!("Configuration reload unavailable ({}), SIGHUP stops monitoring", e));
+    }
 
-                if args.verbose && tracked_devices.is_empty() {
-                    println!("[{}] No devices detected", Local::now().format("%Y-%m-%d %H:%M:%S"));
-                }
-            }
-            Err(e) => {
-                eprintln!("Error reading network state: {}", e);
-            }
+    let mut sources: Vec<Box<dyn NeighborSource>> = vec![Box::new(KernelSource::new(args.interface.as_deref()))];
+    if args.lease_devices || args.lease_details {
+        match LeaseWatcher::start(args.leases.clone()) {
+            Ok(watcher) => sources.push(Box::new(LeaseSource::new(watcher, args.interface.clone(), args.lease_details))),
+            Err(e) => diag::warn(&format!("DHCP lease source unavailable ({}), using the neighbor table only", e)),
+        }
+    }
+    if args.capture {
+        match PacketCapture::start(args.interface.clone()) {
+            Ok(capture) => sources.push(Box::new(CaptureSource::new(capture, Duration::from_secs(args.sighting_ttl)))),
+            Err(e) => diag::warn(&format!("Packet capture unavailable ({}), using the neighbor table only", e)),
+        }
+    }
+    if args.mdns {
+        match MdnsListener::start(args.interface.clone()) {
+            Ok(listener) => sources.push(Box::new(MdnsSource::new(listener, Duration::from_secs(args.sighting_ttl)))),
+            Err(e) => diag::warn(&format!("mDNS listener unavailable ({}), continuing without device services", e)),
+        }
+    }
+    if args.arp_sweep {
+        let config = SweepConfig {
+            interface: args.interface.clone(),
+            interval: Duration::from_secs(args.sweep_interval),
+            rate: args.sweep_rate,
+            ..SweepConfig::default()
+        };
+        match ArpSweep::start(config) {
+            Ok(sweep) => sources.push(Box::new(SweepSource::new(sweep))),
+            Err(e) => diag::warn(&format!("ARP sweep unavailable ({}), continuing passively", e)),
         }
+    }
+    let source = MergedSource::new(sources, args.source_precedence.clone());
+    let passive_interface = args.interface.clone().filter(|_| args.capture || args.mdns || args.arp_sweep);
+    let tui = args.tui;
+    let mut monitor = Monitor {
+        args,
+        started: layered.clone(),
+        layered,
+        argv,
+        tracker: Arc::clone(&tracker),
+        source,
+        sinks,
+        events: Arc::clone(&events),
+        metrics,
+        prober,
+        passive_interface,
+        held: VecDeque::new(),
+        summary_format: Arc::clone(&summary_format),
+    };
+
+    if !tui {
+        monitor.run();
+    }
 
-        thread::sleep(Duration::from_secs(args.interval));
+    // The dashboard owns the terminal; monitoring continues in the background
+    thread::spawn(move || monitor.run());
+    tui::run(Arc::clone(&tracker), events)?;
+
+    for diagnostic in diag::recent() {
+        eprintln!("[{}] {}", diagnostic.timestamp.format("%Y-%m-%d %H:%M:%S"), diagnostic.message);
     }
-}
\ No newline at end of file
+
+    let output = *summary_format.lock().unwrap();
+    println!("{}", format_summary(output, tracker.lock().unwrap().stats()));
+    Ok(())
+}
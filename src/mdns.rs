@@ -0,0 +1,570 @@
+// Passive multicast DNS listener. Devices announce their hostname and the
+// DNS-SD services they offer (_airplay._tcp, _ipp._tcp, _googlecast._tcp, ...),
+// which tells a phone from a TV or a printer where the OUI vendor can't.
+// Nothing is ever queried; only announcements and answers to other hosts'
+// queries are read.
+
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::device::{Device, MacAddr, NeighState};
+use crate::diag;
+use crate::netlink::interface_name;
+use crate::packet::{self, PacketSocket, ETH_P_ALL, ETH_P_IP, ETH_P_IPV6, IPPROTO_UDP};
+use crate::source::NeighborSource;
+
+/// Source name of devices heard announcing over mDNS
+pub const SOURCE: &str = "mdns";
+
+pub const MDNS_PORT: u16 = 5353;
+
+// Link-layer groups of 224.0.0.251 and ff02::fb
+const MDNS_IPV4_GROUP: [u8; 6] = [0x01, 0x00, 0x5e, 0x00, 0x00, 0xfb];
+const MDNS_IPV6_GROUP: [u8; 6] = [0x33, 0x33, 0x00, 0x00, 0x00, 0xfb];
+
+const DNS_HEADER_LEN: usize = 12;
+const DNS_FLAG_RESPONSE: u16 = 0x8000;
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const SERVICE_ENUMERATION: &str = "_services._dns-sd._udp.local";
+
+// Compression pointers followed before a name is considered corrupt
+const MAX_POINTERS: usize = 16;
+
+/// What a device said about itself in an mDNS response
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Announcement {
+    /// Name of its address records, e.g. `Living-Room.local`
+    pub hostname: Option<String>,
+    /// Service types, e.g. `_googlecast._tcp`
+    pub services: BTreeSet<String>,
+}
+
+impl Announcement {
+    fn merge(&mut self, other: Announcement) {
+        if other.hostname.is_some() {
+            self.hostname = other.hostname;
+        }
+        self.services.extend(other.services);
+    }
+
+    /// Give the device this hostname unless it has one, and these services
+    pub fn annotate(&self, device: &mut Device) {
+        if device.hostname.is_none() {
+            device.hostname = self.hostname.clone();
+        }
+        let mut services: BTreeSet<String> = device.services.drain(..).collect();
+        services.extend(self.services.iter().cloned());
+        device.services = services.into_iter().collect();
+    }
+}
+
+/// Hostname and services in an mDNS response sent by `sender`. Address
+/// records for other hosts are ignored; without one for `sender`, the target
+/// of its service records names it.
+pub fn parse_response(message: &[u8], sender: IpAddr) -> Option<Announcement> {
+    if message.len() < DNS_HEADER_LEN || u16_at(message, 2) & DNS_FLAG_RESPONSE == 0 {
+        return None;
+    }
+    let questions = u16_at(message, 4);
+    let records = u16_at(message, 6) as usize + u16_at(message, 8) as usize + u16_at(message, 10) as usize;
+
+    let mut offset = DNS_HEADER_LEN;
+    for _ in 0..questions {
+        offset = read_name(message, offset)?.1 + 4;
+    }
+
+    let mut announcement = Announcement::default();
+    let mut service_target = None;
+    for _ in 0..records {
+        let (name, next) = read_name(message, offset)?;
+        let header = message.get(next..next + 10)?;
+        let record_type = u16_at(header, 0);
+        let ttl = u32::from_be_bytes(header[4..8].try_into().unwrap());
+        let data_len = u16_at(header, 8) as usize;
+        let data_start = next + 10;
+        let data = message.get(data_start..data_start + data_len)?;
+        offset = data_start + data_len;
+
+        // Goodbye records (TTL 0) withdraw what they name rather than announce it
+        if ttl == 0 {
+            continue;
+        }
+        match record_type {
+            TYPE_A if data_len == 4 => {
+                let address = IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(data).unwrap()));
+                if address == sender {
+                    announcement.hostname = Some(name);
+                }
+            }
+            TYPE_AAAA if data_len == 16 => {
+                let address = IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(data).unwrap()));
+                if address == sender {
+                    announcement.hostname = Some(name);
+                }
+            }
+            TYPE_PTR => {
+                let (target, _) = read_name(message, data_start)?;
+                let service = match name.eq_ignore_ascii_case(SERVICE_ENUMERATION) {
+                    true => service_type(&target),
+                    false => service_type(&name),
+                };
+                announcement.services.extend(service);
+            }
+            // Priority, weight and port precede the target host
+            TYPE_SRV if data_len > 6 => {
+                announcement.services.extend(service_type(&name));
+                service_target = Some(read_name(message, data_start + 6)?.0);
+            }
+            _ => {}
+        }
+    }
+
+    if announcement.hostname.is_none() {
+        announcement.hostname = service_target;
+    }
+    (announcement.hostname.is_some() || !announcement.services.is_empty()).then_some(announcement)
+}
+
+// `_ipp._tcp` out of `Office Printer._ipp._tcp.local`, `_ipp._tcp.local` or
+// the subtype `_universal._sub._ipp._tcp.local`
+fn service_type(name: &str) -> Option<String> {
+    let labels: Vec<&str> = name.split('.').collect();
+    labels.windows(2).find_map(|pair| {
+        let protocol = pair[1].to_ascii_lowercase();
+        (pair[0].starts_with('_') && (protocol == "_tcp" || protocol == "_udp"))
+            .then(|| format!("{}.{}", pair[0].to_ascii_lowercase(), protocol))
+    })
+}
+
+// Name starting at `offset` with its labels joined by dots, and the offset
+// just past it in the message
+fn read_name(message: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    let mut pointers = 0;
+    loop {
+        let len = *message.get(offset)? as usize;
+        match len {
+            0 => {
+                return Some((labels.join("."), end.unwrap_or(offset + 1)));
+            }
+            len if len & 0xc0 == 0xc0 => {
+                pointers += 1;
+                if pointers > MAX_POINTERS {
+                    return None;
+                }
+                end.get_or_insert(offset + 2);
+                offset = (u16_at(message.get(offset..offset + 2)?, 0) & 0x3fff) as usize;
+            }
+            len if len < 64 => {
+                let label = message.get(offset + 1..offset + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                offset += 1 + len;
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn u16_at(buf: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([buf[offset], buf[offset + 1]])
+}
+
+/// The device sending an mDNS response in an Ethernet frame, as a REACHABLE
+/// sighting on `interface` carrying what it announced
+pub fn sighting(frame: &[u8], interface: &str) -> Option<Device> {
+    let (ethertype, payload) = packet::parse_ethernet(frame)?;
+    let (sender, segment) = match ethertype {
+        ETH_P_IP => {
+            let (header, segment) = packet::parse_ipv4(payload)?;
+            (header.protocol == IPPROTO_UDP).then_some((IpAddr::V4(header.src), segment))?
+        }
+        ETH_P_IPV6 => {
+            let (header, segment) = packet::parse_ipv6(payload)?;
+            (header.protocol == IPPROTO_UDP).then_some((IpAddr::V6(header.src), segment))?
+        }
+        _ => return None,
+    };
+    let (src_port, dst_port, message) = packet::parse_udp(segment)?;
+    if src_port != MDNS_PORT || dst_port != MDNS_PORT || sender.is_unspecified() || frame[6] & 1 != 0 {
+        return None;
+    }
+    let announcement = parse_response(message, sender)?;
+
+    let mac = MacAddr::from_slice(&frame[6..12])?;
+    let mut device = Device::new(sender, mac, interface.to_string()).with_state(NeighState::Reachable).seen_by(SOURCE);
+    announcement.annotate(&mut device);
+    Some(device)
+}
+
+/// BPF program passing only IPv4 and IPv6 UDP datagrams to the mDNS port
+pub fn mdns_filter() -> [libc::sock_filter; 16] {
+    const LOAD_HALF: u32 = libc::BPF_LD | libc::BPF_H | libc::BPF_ABS;
+    const LOAD_BYTE: u32 = libc::BPF_LD | libc::BPF_B | libc::BPF_ABS;
+    const LOAD_IP_HEADER_LEN: u32 = libc::BPF_LDX | libc::BPF_B | libc::BPF_MSH;
+    const LOAD_HALF_AFTER_IP: u32 = libc::BPF_LD | libc::BPF_H | libc::BPF_IND;
+    const JUMP_EQ: u32 = libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K;
+    const JUMP_SET: u32 = libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K;
+    const RETURN: u32 = libc::BPF_RET | libc::BPF_K;
+    let op = packet::bpf_op;
+
+    // Jump offsets count instructions to skip: 14 is "accept", 15 is "drop"
+    [
+        /* 0 */ op(LOAD_HALF, 12, 0, 0),
+        /* 1 */ op(JUMP_EQ, ETH_P_IPV6 as u32, 0, 4),
+        /* 2 */ op(LOAD_BYTE, 20, 0, 0),
+        /* 3 */ op(JUMP_EQ, IPPROTO_UDP as u32, 0, 11),
+        /* 4 */ op(LOAD_HALF, 56, 0, 0),
+        /* 5 */ op(JUMP_EQ, MDNS_PORT as u32, 8, 9),
+        /* 6 */ op(JUMP_EQ, ETH_P_IP as u32, 0, 8),
+        /* 7 */ op(LOAD_BYTE, 23, 0, 0),
+        /* 8 */ op(JUMP_EQ, IPPROTO_UDP as u32, 0, 6),
+        /* 9 */ op(LOAD_HALF, 20, 0, 0),
+        /* 10 */ op(JUMP_SET, 0x1fff, 4, 0),
+        /* 11 */ op(LOAD_IP_HEADER_LEN, 14, 0, 0),
+        /* 12 */ op(LOAD_HALF_AFTER_IP, 16, 0, 0),
+        /* 13 */ op(JUMP_EQ, MDNS_PORT as u32, 0, 1),
+        /* 14 */ op(RETURN, u32::MAX, 0, 0),
+        /* 15 */ op(RETURN, 0, 0, 0),
+    ]
+}
+
+#[derive(Debug, Default)]
+struct MdnsState {
+    // Sightings keyed by (ip, mac, interface), each with when it was last heard
+    sightings: HashMap<(IpAddr, MacAddr, String), (Device, Instant)>,
+    // Sightings heard since they were last taken
+    new: HashMap<(IpAddr, MacAddr, String), Device>,
+    // Everything each MAC has announced so far
+    announced: HashMap<MacAddr, Announcement>,
+}
+
+/// Listens for mDNS responses on a background thread, keeping the devices that
+/// send them, with when each was last heard, and what each MAC address announced
+pub struct MdnsListener {
+    state: Arc<Mutex<MdnsState>>,
+}
+
+impl MdnsListener {
+    /// Open a packet socket, join the mDNS groups and start listening on every
+    /// interface, or only on `interface`
+    pub fn start(interface: Option<String>) -> io::Result<Self> {
+        let socket = PacketSocket::open(ETH_P_ALL)?;
+        socket.attach_filter(&mdns_filter())?;
+        // Interfaces only pass multicast frames for groups someone joined
+        for local in packet::local_interfaces()? {
+            if !local.has_neighbors() || interface.as_ref().is_some_and(|name| *name != local.name) {
+                continue;
+            }
+            socket.add_multicast(local.index, MDNS_IPV4_GROUP)?;
+            socket.add_multicast(local.index, MDNS_IPV6_GROUP)?;
+        }
+        let state = Arc::new(Mutex::new(MdnsState::default()));
+
+        let listener_state = Arc::clone(&state);
+        thread::spawn(move || listen(&socket, &listener_state, interface.as_deref()));
+
+        Ok(MdnsListener { state })
+    }
+
+    /// Devices heard within the last `ttl`. They include the ones `take_new` would return.
+    pub fn current(&self, ttl: Duration) -> Vec<Device> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.new.clear();
+        state.sightings.retain(|_, (_, heard)| now.duration_since(*heard) <= ttl);
+        state.sightings.values().map(|(device, _)| device.clone()).collect()
+    }
+
+    /// Devices heard since the last call or `current`
+    pub fn take_new(&self) -> Vec<Device> {
+        std::mem::take(&mut self.state.lock().unwrap().new).into_values().collect()
+    }
+
+    /// What the device's MAC address has announced so far
+    pub fn announcement(&self, mac: &MacAddr) -> Option<Announcement> {
+        self.state.lock().unwrap().announced.get(mac).cloned()
+    }
+}
+
+fn listen(socket: &PacketSocket, state: &Mutex<MdnsState>, interface_filter: Option<&str>) {
+    // Responses may use the full Ethernet MTU or more
+    let mut buf = vec![0u8; 9216];
+    let mut interface_names = HashMap::new();
+
+    loop {
+        let (len, received_on) = match socket.recv(&mut buf) {
+            Ok(received) => received,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                diag::warn(&format!("mDNS: receive failed: {}", e));
+                return;
+            }
+        };
+        if received_on.outgoing {
+            continue;
+        }
+        let Some(interface) = interface_name(received_on.ifindex as u32, &mut interface_names) else {
+            continue;
+        };
+        if interface_filter.is_some_and(|filter| filter != interface) {
+            continue;
+        }
+        let Some(device) = sighting(&buf[..len], &interface) else {
+            continue;
+        };
+
+        let mut state = state.lock().unwrap();
+        let announcement = Announcement {
+            hostname: device.hostname.clone(),
+            services: device.services.iter().cloned().collect(),
+        };
+        state.announced.entry(device.mac_address).or_default().merge(announcement);
+        let key = (device.ip_address, device.mac_address, device.interface.clone());
+        state.new.insert(key.clone(), device.clone());
+        state.sightings.insert(key, (device, Instant::now()));
+    }
+}
+
+/// Devices heard announcing over mDNS since the previous scan. The hostname
+/// and services announced by each MAC are added to every entry of a merged
+/// snapshot, including the addresses it didn't announce from.
+pub struct MdnsSource {
+    listener: MdnsListener,
+    ttl: Duration,
+}
+
+impl MdnsSource {
+    pub fn new(listener: MdnsListener, ttl: Duration) -> Self {
+        MdnsSource { listener, ttl }
+    }
+}
+
+impl NeighborSource for MdnsSource {
+    fn name(&self) -> &str {
+        SOURCE
+    }
+
+    fn scan(&mut self) -> Result<Vec<Device>, Box<dyn Error>> {
+        Ok(self.listener.current(self.ttl))
+    }
+
+    fn take_heard(&mut self) -> Vec<Device> {
+        self.listener.take_new()
+    }
+
+    fn annotate(&self, devices: &mut [Device]) {
+        for device in devices.iter_mut() {
+            if let Some(announcement) = self.listener.announcement(&device.mac_address) {
+                announcement.annotate(device);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SENDER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+
+    fn name(name: &str) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        for label in name.split('.') {
+            encoded.push(label.len() as u8);
+            encoded.extend_from_slice(label.as_bytes());
+        }
+        encoded.push(0);
+        encoded
+    }
+
+    fn record(owner: &[u8], record_type: u16, ttl: u32, data: &[u8]) -> Vec<u8> {
+        let mut record = owner.to_vec();
+        record.extend_from_slice(&record_type.to_be_bytes());
+        record.extend_from_slice(&0x8001u16.to_be_bytes());
+        record.extend_from_slice(&ttl.to_be_bytes());
+        record.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        record.extend_from_slice(data);
+        record
+    }
+
+    fn srv(target: &str) -> Vec<u8> {
+        let mut data = vec![0, 0, 0, 0, 0x1f, 0x90];
+        data.extend(name(target));
+        data
+    }
+
+    // A response with the given answer records
+    fn response(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut message = vec![0, 0, 0x84, 0, 0, 0];
+        message.extend_from_slice(&(records.len() as u16).to_be_bytes());
+        message.extend_from_slice(&[0, 0, 0, 0]);
+        for record in records {
+            message.extend_from_slice(record);
+        }
+        message
+    }
+
+    fn services(announcement: &Announcement) -> Vec<&str> {
+        announcement.services.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn address_record_of_the_sender_names_it() {
+        let message = response(&[
+            record(&name("Other.local"), TYPE_A, 120, &[192, 168, 1, 21]),
+            record(&name("Living-Room.local"), TYPE_A, 120, &[192, 168, 1, 20]),
+        ]);
+        let announcement = parse_response(&message, SENDER).unwrap();
+        assert_eq!(announcement.hostname.as_deref(), Some("Living-Room.local"));
+        assert!(announcement.services.is_empty());
+
+        let sender = "2001:db8::20".parse().unwrap();
+        let aaaa = record(&name("Phone.local"), TYPE_AAAA, 120, &"2001:db8::20".parse::<Ipv6Addr>().unwrap().octets());
+        assert_eq!(parse_response(&response(&[aaaa]), sender).unwrap().hostname.as_deref(), Some("Phone.local"));
+    }
+
+    #[test]
+    fn records_about_other_hosts_only_announce_nothing() {
+        let message = response(&[record(&name("Other.local"), TYPE_A, 120, &[192, 168, 1, 21])]);
+        assert_eq!(parse_response(&message, SENDER), None);
+    }
+
+    #[test]
+    fn services_from_ptr_and_srv_records() {
+        let message = response(&[
+            record(&name("_services._dns-sd._udp.local"), TYPE_PTR, 4500, &name("_googlecast._tcp.local")),
+            record(&name("_universal._sub._IPP._TCP.local"), TYPE_PTR, 4500, &name("Printer._ipp._tcp.local")),
+            record(&name("Speaker._airplay._tcp.local"), TYPE_SRV, 120, &srv("Speaker.local")),
+        ]);
+        let announcement = parse_response(&message, SENDER).unwrap();
+        assert_eq!(services(&announcement), ["_airplay._tcp", "_googlecast._tcp", "_ipp._tcp"]);
+        // Without an address record, the service target names the sender
+        assert_eq!(announcement.hostname.as_deref(), Some("Speaker.local"));
+    }
+
+    #[test]
+    fn address_record_wins_over_service_target() {
+        let message = response(&[
+            record(&name("Speaker._airplay._tcp.local"), TYPE_SRV, 120, &srv("Target.local")),
+            record(&name("Speaker.local"), TYPE_A, 120, &[192, 168, 1, 20]),
+        ]);
+        assert_eq!(parse_response(&message, SENDER).unwrap().hostname.as_deref(), Some("Speaker.local"));
+    }
+
+    #[test]
+    fn goodbye_records_are_ignored() {
+        let message = response(&[
+            record(&name("Gone.local"), TYPE_A, 0, &[192, 168, 1, 20]),
+            record(&name("Gone._ipp._tcp.local"), TYPE_SRV, 0, &srv("Gone.local")),
+        ]);
+        assert_eq!(parse_response(&message, SENDER), None);
+    }
+
+    #[test]
+    fn compressed_names_are_followed() {
+        // The second record's owner points at "local" inside the first
+        let first = record(&name("Printer.local"), TYPE_A, 120, &[192, 168, 1, 99]);
+        let local_offset = (DNS_HEADER_LEN + 1 + "Printer".len()) as u8;
+        let mut owner = vec![4];
+        owner.extend_from_slice(b"Lamp");
+        owner.extend_from_slice(&[0xc0, local_offset]);
+        let second = record(&owner, TYPE_A, 120, &[192, 168, 1, 20]);
+        assert_eq!(parse_response(&response(&[first, second]), SENDER).unwrap().hostname.as_deref(), Some("Lamp.local"));
+    }
+
+    #[test]
+    fn malformed_messages_are_rejected() {
+        let valid = response(&[record(&name("Lamp.local"), TYPE_A, 120, &[192, 168, 1, 20])]);
+        // Queries, truncated headers and truncated records
+        let mut query = valid.clone();
+        query[2] = 0;
+        assert_eq!(parse_response(&query, SENDER), None);
+        assert_eq!(parse_response(&valid[..DNS_HEADER_LEN - 1], SENDER), None);
+        assert_eq!(parse_response(&valid[..valid.len() - 1], SENDER), None);
+
+        // A name pointing at itself
+        let looped = {
+            let mut message = response(&[]);
+            message[7] = 1;
+            message.extend_from_slice(&[0xc0, DNS_HEADER_LEN as u8]);
+            message
+        };
+        assert_eq!(parse_response(&looped, SENDER), None);
+
+        // Label lengths 64-191 are reserved
+        let mut reserved = valid.clone();
+        reserved[DNS_HEADER_LEN] = 0x40;
+        assert_eq!(parse_response(&reserved, SENDER), None);
+    }
+
+    #[test]
+    fn service_types() {
+        assert_eq!(service_type("Office Printer._ipp._tcp.local").as_deref(), Some("_ipp._tcp"));
+        assert_eq!(service_type("_Spotify-Connect._TCP.local").as_deref(), Some("_spotify-connect._tcp"));
+        assert_eq!(service_type("_sleep-proxy._udp.local").as_deref(), Some("_sleep-proxy._udp"));
+        assert_eq!(service_type("Living-Room.local"), None);
+        assert_eq!(service_type("_tcp.local"), None);
+    }
+
+    fn ipv4_udp_frame(src_mac: [u8; 6], src: Ipv4Addr, ports: (u16, u16), payload: &[u8]) -> Vec<u8> {
+        let mut udp = Vec::new();
+        udp.extend_from_slice(&ports.0.to_be_bytes());
+        udp.extend_from_slice(&ports.1.to_be_bytes());
+        udp.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend_from_slice(payload);
+
+        let mut ip = vec![0x45, 0];
+        ip.extend_from_slice(&((20 + udp.len()) as u16).to_be_bytes());
+        ip.extend_from_slice(&[0, 0, 0, 0, 255, IPPROTO_UDP, 0, 0]);
+        ip.extend_from_slice(&src.octets());
+        ip.extend_from_slice(&[224, 0, 0, 251]);
+        ip.extend(udp);
+        packet::ethernet_frame(MDNS_IPV4_GROUP, src_mac, ETH_P_IP, &ip)
+    }
+
+    #[test]
+    fn sighting_of_an_announcing_device() {
+        let mac = [0x02, 0, 0, 0, 0, 0x20];
+        let message = response(&[
+            record(&name("Lamp.local"), TYPE_A, 120, &[192, 168, 1, 20]),
+            record(&name("Lamp._hap._tcp.local"), TYPE_SRV, 120, &srv("Lamp.local")),
+        ]);
+        let device = sighting(&ipv4_udp_frame(mac, Ipv4Addr::new(192, 168, 1, 20), (MDNS_PORT, MDNS_PORT), &message), "eth0").unwrap();
+        assert_eq!(device.ip_address, SENDER);
+        assert_eq!(device.mac_address, MacAddr(mac));
+        assert_eq!(device.state, NeighState::Reachable);
+        assert_eq!(device.hostname.as_deref(), Some("Lamp.local"));
+        assert_eq!(device.services, ["_hap._tcp"]);
+        assert_eq!(device.sources, [SOURCE]);
+
+        // Messages from other ports, unspecified senders and group source MACs aren't sightings
+        let frame = |mac, src, ports| ipv4_udp_frame(mac, src, ports, &message);
+        assert_eq!(sighting(&frame(mac, Ipv4Addr::new(192, 168, 1, 20), (40000, MDNS_PORT)), "eth0"), None);
+        assert_eq!(sighting(&frame(mac, Ipv4Addr::UNSPECIFIED, (MDNS_PORT, MDNS_PORT)), "eth0"), None);
+        assert_eq!(sighting(&frame([0x03, 0, 0, 0, 0, 0x20], Ipv4Addr::new(192, 168, 1, 20), (MDNS_PORT, MDNS_PORT)), "eth0"), None);
+    }
+
+    #[test]
+    fn announcement_annotates_without_replacing_a_hostname() {
+        let announcement = Announcement {
+            hostname: Some("Lamp.local".to_string()),
+            services: BTreeSet::from(["_hap._tcp".to_string()]),
+        };
+        let mut device = Device::new(SENDER, MacAddr([2, 0, 0, 0, 0, 0x20]), "eth0".to_string());
+        device.hostname = Some("lamp-from-dhcp".to_string());
+        device.services = vec!["_airplay._tcp".to_string(), "_hap._tcp".to_string()];
+        announcement.annotate(&mut device);
+        assert_eq!(device.hostname.as_deref(), Some("lamp-from-dhcp"));
+        assert_eq!(device.services, ["_airplay._tcp", "_hap._tcp"]);
+    }
+}
@@ -0,0 +1,227 @@
+// Prometheus metrics: event and scan counters plus gauges computed from the tracker
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use clap::ValueEnum;
+
+use crate::output::get_vendor_from_mac;
+use crate::sink::EventSink;
+use crate::tracker::{Event, EventKind, Tracker};
+
+#[derive(Debug, Default)]
+struct Counters {
+    events: BTreeMap<&'static str, u64>,
+    scans: u64,
+    scan_errors: u64,
+    scan_duration_sum: Duration,
+    last_scan_duration: Option<Duration>,
+}
+
+/// Counters shared between the monitoring loop and the metrics endpoint
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: Mutex<Counters>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_event(&self, event: &Event) {
+        *self.counters.lock().unwrap().events.entry(event.kind.as_str()).or_default() += 1;
+    }
+
+    /// Record a full scan of a neighbor source and whether it succeeded
+    pub fn record_scan(&self, duration: Duration, success: bool) {
+        let mut counters = self.counters.lock().unwrap();
+        counters.scans += 1;
+        if success {
+            counters.scan_duration_sum += duration;
+            counters.last_scan_duration = Some(duration);
+        } else {
+            counters.scan_errors += 1;
+        }
+    }
+
+    /// Render every metric in the Prometheus text exposition format
+    pub fn render(&self, tracker: &Tracker) -> String {
+        let mut out = String::new();
+
+        // Current devices per interface and per vendor
+        let mut per_interface: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut per_vendor: BTreeMap<String, usize> = BTreeMap::new();
+        for tracked in tracker.devices() {
+            *per_interface.entry(tracked.device.interface.as_str()).or_default() += 1;
+            let vendor = get_vendor_from_mac(&tracked.device.mac_address).unwrap_or_else(|| "Unknown".to_string());
+            *per_vendor.entry(vendor).or_default() += 1;
+        }
+
+        header(&mut out, "netneighbor_devices", "gauge", "Devices currently tracked, per interface");
+        for (interface, count) in &per_interface {
+            let _ = writeln!(out, "netneighbor_devices{{interface=\"{}\"}} {}", escape_label(interface), count);
+        }
+
+        header(&mut out, "netneighbor_devices_by_vendor", "gauge", "Devices currently tracked, per MAC vendor");
+        for (vendor, count) in &per_vendor {
+            let _ = writeln!(out, "netneighbor_devices_by_vendor{{vendor=\"{}\"}} {}", escape_label(vendor), count);
+        }
+
+        let stats = tracker.stats();
+        header(&mut out, "netneighbor_devices_seen_total", "counter", "Distinct devices seen since startup");
+        let _ = writeln!(out, "netneighbor_devices_seen_total {}", stats.total_devices_seen);
+        header(&mut out, "netneighbor_peak_devices", "gauge", "Peak number of concurrently tracked devices");
+        let _ = writeln!(out, "netneighbor_peak_devices {}", stats.peak_concurrent_devices);
+        header(&mut out, "netneighbor_uptime_seconds", "gauge", "Seconds since monitoring started");
+        let _ = writeln!(out, "netneighbor_uptime_seconds {}", stats.start_time.elapsed().as_secs());
+        header(&mut out, "netneighbor_skipped_lines_total", "counter", "Neighbor table entries skipped by scans, per reason");
+        for (reason, count) in stats.skipped.by_reason() {
+            let _ = writeln!(out, "netneighbor_skipped_lines_total{{reason=\"{}\"}} {}", reason, count);
+        }
+
+        let counters = self.counters.lock().unwrap();
+
+        // Every event kind is listed so the series exist before the first event
+        header(&mut out, "netneighbor_events_total", "counter", "Events reported, per event type");
+        for kind in EventKind::value_variants() {
+            let count = counters.events.get(kind.as_str()).copied().unwrap_or(0);
+            let _ = writeln!(out, "netneighbor_events_total{{event=\"{}\"}} {}", kind.as_str(), count);
+        }
+
+        header(&mut out, "netneighbor_scans_total", "counter", "Full neighbor table scans attempted");
+        let _ = writeln!(out, "netneighbor_scans_total {}", counters.scans);
+        header(&mut out, "netneighbor_scan_errors_total", "counter", "Full neighbor table scans that failed");
+        let _ = writeln!(out, "netneighbor_scan_errors_total {}", counters.scan_errors);
+
+        header(&mut out, "netneighbor_scan_duration_seconds", "summary", "Duration of successful scans");
+        let _ = writeln!(out, "netneighbor_scan_duration_seconds_sum {}", counters.scan_duration_sum.as_secs_f64());
+        let _ = writeln!(out, "netneighbor_scan_duration_seconds_count {}", counters.scans - counters.scan_errors);
+        if let Some(duration) = counters.last_scan_duration {
+            header(&mut out, "netneighbor_last_scan_duration_seconds", "gauge", "Duration of the most recent successful scan");
+            let _ = writeln!(out, "netneighbor_last_scan_duration_seconds {}", duration.as_secs_f64());
+        }
+
+        out
+    }
+}
+
+/// Counts delivered events into shared metrics
+pub struct MetricsSink {
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsSink {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        MetricsSink { metrics }
+    }
+}
+
+impl EventSink for MetricsSink {
+    fn emit(&mut self, event: &Event) {
+        self.metrics.record_event(event);
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// Label values escape backslash, double quote and newline
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    use crate::device::{Device, NeighState};
+    use crate::parse::Skipped;
+    use crate::tracker::TrackerConfig;
+
+    fn device(ip: &str, mac: &str, interface: &str) -> Device {
+        Device::new(ip.parse().unwrap(), mac.parse().unwrap(), interface.to_string()).with_state(NeighState::Reachable)
+    }
+
+    // Sample lines of the rendered text, without HELP and TYPE comments
+    fn samples(text: &str) -> Vec<&str> {
+        text.lines().filter(|line| !line.starts_with('#')).collect()
+    }
+
+    #[test]
+    fn render_lists_devices_events_and_scans() {
+        let mut tracker = Tracker::new(TrackerConfig::default());
+        let events = tracker.update(
+            vec![
+                device("192.168.1.10", "3c:22:fb:00:00:01", "eth0"),
+                device("192.168.1.11", "02:00:00:00:00:02", "eth0"),
+                device("192.168.2.10", "02:00:00:00:00:03", "wlan0"),
+            ],
+            Instant::now(),
+        );
+        tracker.record_skipped(&Skipped { invalid: 2, malformed: 1, ..Skipped::default() });
+
+        let metrics = Metrics::new();
+        for event in &events {
+            metrics.record_event(event);
+        }
+        metrics.record_scan(Duration::from_millis(250), true);
+        metrics.record_scan(Duration::from_millis(750), true);
+        metrics.record_scan(Duration::from_secs(5), false);
+
+        let text = metrics.render(&tracker);
+        let samples = samples(&text);
+        for expected in [
+            "netneighbor_devices{interface=\"eth0\"} 2",
+            "netneighbor_devices{interface=\"wlan0\"} 1",
+            "netneighbor_devices_by_vendor{vendor=\"Unknown\"} 2",
+            "netneighbor_devices_seen_total 3",
+            "netneighbor_peak_devices 3",
+            "netneighbor_skipped_lines_total{reason=\"incomplete\"} 0",
+            "netneighbor_skipped_lines_total{reason=\"invalid\"} 2",
+            "netneighbor_skipped_lines_total{reason=\"malformed\"} 1",
+            "netneighbor_events_total{event=\"CONNECTED\"} 3",
+            "netneighbor_events_total{event=\"SECURITY\"} 0",
+            "netneighbor_scans_total 3",
+            "netneighbor_scan_errors_total 1",
+            "netneighbor_scan_duration_seconds_sum 1",
+            "netneighbor_scan_duration_seconds_count 2",
+            "netneighbor_last_scan_duration_seconds 0.75",
+        ] {
+            assert!(samples.contains(&expected), "missing {:?} in\n{}", expected, text);
+        }
+        assert!(samples.iter().any(|line| line.starts_with("netneighbor_devices_by_vendor{vendor=\"Apple") && line.ends_with("\"} 1")), "{}", text);
+        assert!(text.contains("# HELP netneighbor_events_total Events reported, per event type\n# TYPE netneighbor_events_total counter\n"));
+    }
+
+    #[test]
+    fn every_sample_follows_its_type_line() {
+        let text = Metrics::new().render(&Tracker::new(TrackerConfig::default()));
+        let mut typed = Vec::new();
+        for line in text.lines() {
+            match line.strip_prefix("# TYPE ") {
+                Some(rest) => typed.push(rest.split(' ').next().unwrap().to_string()),
+                None if line.starts_with('#') => {}
+                None => {
+                    let name = line.split(['{', ' ']).next().unwrap();
+                    let family = typed.last().unwrap();
+                    assert!(name == family || name.strip_prefix(family.as_str()).is_some_and(|suffix| ["_sum", "_count"].contains(&suffix)), "{}", line);
+                }
+            }
+        }
+        // No scan yet, so there is no last duration
+        assert!(!text.contains("netneighbor_last_scan_duration_seconds"));
+        assert!(text.contains("netneighbor_scan_duration_seconds_count 0\n"));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape_label("Acme \"Labs\"\\Co\nLtd"), "Acme \\\"Labs\\\"\\\\Co\\nLtd");
+        assert_eq!(escape_label("eth0"), "eth0");
+    }
+}
@@ -0,0 +1,562 @@
+// Native neighbor table reader using rtnetlink (AF_NETLINK / NETLINK_ROUTE).
+//
+// Sends an RTM_GETNEIGH dump request and decodes the RTM_NEWNEIGH replies
+// directly into `Device` values, so no external `arp`/`ip` processes are needed.
+// `NeighborMonitor` subscribes to the RTNLGRP_NEIGH multicast group instead, so
+// changes to the neighbor table are delivered as they happen.
+
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::io;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::Duration;
+
+use crate::device::{self, Device, MacAddr, NeighState};
+use crate::parse::{Skip, Skipped};
+
+/// Source name of neighbor table entries read over rtnetlink
+pub const SOURCE: &str = "netlink";
+
+const NLMSG_HDR_LEN: usize = 16;
+const NDMSG_LEN: usize = 12;
+const RTATTR_HDR_LEN: usize = 4;
+const RECV_BUFFER_SIZE: usize = 32 * 1024;
+// Larger socket buffer for subscriptions so bursts of updates are not dropped
+const SUBSCRIPTION_SOCKET_BUFFER: libc::c_int = 1024 * 1024;
+// How long a subscription waits for notifications before handing back an empty batch
+const SUBSCRIPTION_RECV_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Netlink messages and attributes are padded to 4-byte boundaries
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+struct NetlinkSocket {
+    fd: OwnedFd,
+}
+
+impl NetlinkSocket {
+    fn open(groups: u32) -> io::Result<Self> {
+        // SAFETY: plain socket(2) call; the returned descriptor is checked before use
+        let raw = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if raw < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `raw` is a freshly created, valid descriptor that we exclusively own
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+
+        // SAFETY: sockaddr_nl is plain old data, all-zero is a valid value
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = groups;
+
+        // SAFETY: `addr` is a properly initialized sockaddr_nl of the given length
+        let ret = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(NetlinkSocket { fd })
+    }
+
+    fn send_neigh_dump_request(&self, seq: u32) -> io::Result<()> {
+        let total_len = NLMSG_HDR_LEN + NDMSG_LEN;
+        let mut request = Vec::with_capacity(total_len);
+
+        // struct nlmsghdr
+        request.extend_from_slice(&(total_len as u32).to_ne_bytes());
+        request.extend_from_slice(&libc::RTM_GETNEIGH.to_ne_bytes());
+        request.extend_from_slice(&((libc::NLM_F_REQUEST | libc::NLM_F_DUMP) as u16).to_ne_bytes());
+        request.extend_from_slice(&seq.to_ne_bytes());
+        request.extend_from_slice(&0u32.to_ne_bytes());
+
+        // struct ndmsg, AF_UNSPEC dumps both IPv4 and IPv6 neighbors
+        request.push(libc::AF_UNSPEC as u8);
+        request.extend_from_slice(&[0u8; NDMSG_LEN - 1]);
+
+        // SAFETY: sockaddr_nl is plain old data, all-zero is a valid value
+        let mut kernel: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        kernel.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+
+        // SAFETY: `request` and `kernel` are valid for the lengths passed
+        let sent = unsafe {
+            libc::sendto(
+                self.fd.as_raw_fd(),
+                request.as_ptr() as *const libc::c_void,
+                request.len(),
+                0,
+                &kernel as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn set_receive_buffer(&self, size: libc::c_int) -> io::Result<()> {
+        // SAFETY: `size` is a valid c_int living for the duration of the call
+        let ret = unsafe {
+            libc::setsockopt(
+                self.fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVBUF,
+                &size as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn set_receive_timeout(&self, timeout: Duration) -> io::Result<()> {
+        let tv = libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+        };
+        // SAFETY: `tv` is a valid timeval living for the duration of the call
+        let ret = unsafe {
+            libc::setsockopt(
+                self.fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &tv as *const libc::timeval as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        // SAFETY: `buf` is valid for writes of `buf.len()` bytes
+        let received = unsafe {
+            libc::recv(
+                self.fd.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(received as usize)
+    }
+}
+
+// Read the whole kernel neighbor table (ARP and NDP caches) over rtnetlink, with
+// the entries that didn't become devices counted by reason
+pub fn dump_neighbors(interface_filter: Option<&str>) -> io::Result<(Vec<Device>, Skipped)> {
+    let socket = NetlinkSocket::open(0)?;
+    let seq = 1;
+    socket.send_neigh_dump_request(seq)?;
+
+    let mut devices = Vec::new();
+    let mut skipped = Skipped::default();
+    let mut interface_names = HashMap::new();
+    let mut buf = vec![0u8; RECV_BUFFER_SIZE];
+
+    loop {
+        let len = socket.recv(&mut buf)?;
+        if len == 0 {
+            return Ok((devices, skipped));
+        }
+
+        let mut offset = 0;
+        while offset + NLMSG_HDR_LEN <= len {
+            let msg_len = read_u32(&buf, offset) as usize;
+            let msg_type = read_u16(&buf, offset + 4);
+            if msg_len < NLMSG_HDR_LEN || offset + msg_len > len {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated netlink message"));
+            }
+
+            match msg_type as libc::c_int {
+                libc::NLMSG_DONE => return Ok((devices, skipped)),
+                libc::NLMSG_ERROR => {
+                    let errno = read_i32(&buf, offset + NLMSG_HDR_LEN);
+                    if errno != 0 {
+                        return Err(io::Error::from_raw_os_error(-errno));
+                    }
+                }
+                _ if msg_type == libc::RTM_NEWNEIGH => {
+                    let payload = &buf[offset + NLMSG_HDR_LEN..offset + msg_len];
+                    match parse_neighbor(payload, interface_filter, &mut interface_names).map(NeighborEntry::into_device) {
+                        Ok(Some(device)) => devices.push(device),
+                        // Entries without a link-layer address are unresolved (INCOMPLETE/FAILED)
+                        Ok(None) => skipped.count(Skip::Incomplete),
+                        Err(skip) => skipped.count(skip),
+                    }
+                }
+                _ => {}
+            }
+
+            offset += align(msg_len);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum NeighborEvent {
+    Added(Device),
+    // Deleted entries usually no longer carry a link-layer address, only IP and interface
+    Removed { ip_address: IpAddr, interface: String },
+    // Entry changed to a state without a link-layer address (INCOMPLETE/FAILED)
+    Unresolved { ip_address: IpAddr, interface: String, state: NeighState },
+}
+
+// A decoded neighbor entry; `mac_address` is missing for unresolved entries
+#[derive(Debug)]
+struct NeighborEntry {
+    ip_address: IpAddr,
+    mac_address: Option<MacAddr>,
+    interface: String,
+    state: NeighState,
+}
+
+impl NeighborEntry {
+    fn into_device(self) -> Option<Device> {
+        let mac = self.mac_address?;
+        Some(Device::new(self.ip_address, mac, self.interface).with_state(self.state).seen_by(SOURCE))
+    }
+}
+
+// Subscription to kernel neighbor table change notifications
+pub struct NeighborMonitor {
+    socket: NetlinkSocket,
+    interface_filter: Option<String>,
+    interface_names: HashMap<u32, String>,
+    buf: Vec<u8>,
+}
+
+impl NeighborMonitor {
+    pub fn subscribe(interface_filter: Option<&str>) -> io::Result<Self> {
+        let socket = NetlinkSocket::open(libc::RTMGRP_NEIGH as u32)?;
+        socket.set_receive_buffer(SUBSCRIPTION_SOCKET_BUFFER)?;
+        socket.set_receive_timeout(SUBSCRIPTION_RECV_TIMEOUT)?;
+
+        Ok(NeighborMonitor {
+            socket,
+            interface_filter: interface_filter.map(str::to_string),
+            interface_names: HashMap::new(),
+            buf: vec![0u8; RECV_BUFFER_SIZE],
+        })
+    }
+
+    // Block until the kernel sends the next batch of RTM_NEWNEIGH/RTM_DELNEIGH notifications.
+    // An ENOBUFS error means notifications were dropped and the caller should resync.
+    // The batch is empty when nothing arrived for a second or a signal interrupted the
+    // wait, so a caller reading on its own thread gets to check whether to stop.
+    pub fn next_events(&mut self) -> io::Result<Vec<NeighborEvent>> {
+        let len = match self.socket.recv(&mut self.buf) {
+            Ok(len) => len,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut events = Vec::new();
+
+        let mut offset = 0;
+        while offset + NLMSG_HDR_LEN <= len {
+            let msg_len = read_u32(&self.buf, offset) as usize;
+            let msg_type = read_u16(&self.buf, offset + 4);
+            if msg_len < NLMSG_HDR_LEN || offset + msg_len > len {
+                break;
+            }
+
+            if msg_type == libc::RTM_NEWNEIGH || msg_type == libc::RTM_DELNEIGH {
+                // Interfaces can be renamed or recreated while subscribed, so don't cache names
+                self.interface_names.clear();
+                let payload = &self.buf[offset + NLMSG_HDR_LEN..offset + msg_len];
+                // Skipped entries are counted when the table is dumped
+                let Ok(entry) = parse_neighbor(payload, self.interface_filter.as_deref(), &mut self.interface_names) else {
+                    offset += align(msg_len);
+                    continue;
+                };
+
+                if msg_type == libc::RTM_DELNEIGH {
+                    events.push(NeighborEvent::Removed {
+                        ip_address: entry.ip_address,
+                        interface: entry.interface,
+                    });
+                } else if entry.mac_address.is_none() {
+                    events.push(NeighborEvent::Unresolved {
+                        ip_address: entry.ip_address,
+                        interface: entry.interface,
+                        state: entry.state,
+                    });
+                } else if let Some(device) = entry.into_device() {
+                    events.push(NeighborEvent::Added(device));
+                }
+            }
+
+            offset += align(msg_len);
+        }
+
+        Ok(events)
+    }
+}
+
+// Decode one RTM_NEWNEIGH/RTM_DELNEIGH payload (struct ndmsg followed by rtattrs)
+fn parse_neighbor(payload: &[u8], interface_filter: Option<&str>, interface_names: &mut HashMap<u32, String>) -> Result<NeighborEntry, Skip> {
+    if payload.len() < NDMSG_LEN {
+        return Err(Skip::Malformed);
+    }
+
+    let family = payload[0] as libc::c_int;
+    let ifindex = read_i32(payload, 4) as u32;
+    let state = read_u16(payload, 8);
+
+    let interface = interface_name(ifindex, interface_names).ok_or(Skip::Malformed)?;
+    if interface_filter.is_some_and(|filter| interface != filter) {
+        return Err(Skip::OtherInterface);
+    }
+    // NOARP entries are static multicast/broadcast mappings, not real neighbors
+    if state & libc::NUD_NOARP != 0 {
+        return Err(Skip::Invalid);
+    }
+
+    let mut ip = None;
+    let mut lladdr = None;
+
+    let mut offset = NDMSG_LEN;
+    while offset + RTATTR_HDR_LEN <= payload.len() {
+        let attr_len = read_u16(payload, offset) as usize;
+        let attr_type = read_u16(payload, offset + 2);
+        if attr_len < RTATTR_HDR_LEN || offset + attr_len > payload.len() {
+            break;
+        }
+        let data = &payload[offset + RTATTR_HDR_LEN..offset + attr_len];
+
+        match attr_type {
+            libc::NDA_DST => ip = parse_ip(family, data),
+            libc::NDA_LLADDR => lladdr = Some(data),
+            _ => {}
+        }
+
+        offset += align(attr_len);
+    }
+
+    let ip = ip.ok_or(Skip::Malformed)?;
+    if !device::is_neighbor_address(&ip) {
+        return Err(Skip::Invalid);
+    }
+    // Only 6-byte unicast link-layer addresses belong to a device on an Ethernet-like
+    // link; tunnels report 4 or 16 byte ones and some drivers all zeros
+    let mac = match lladdr {
+        Some(data) => Some(MacAddr::from_slice(data).filter(MacAddr::is_unicast).ok_or(Skip::Invalid)?),
+        None => None,
+    };
+
+    // Entries without a link-layer address are unresolved (INCOMPLETE/FAILED)
+    Ok(NeighborEntry { ip_address: ip, mac_address: mac, interface, state: neigh_state(state) })
+}
+
+// Map the NUD_* bitmask to a single state; the kernel sets exactly one bit for real entries
+fn neigh_state(nud: u16) -> NeighState {
+    match nud {
+        libc::NUD_INCOMPLETE => NeighState::Incomplete,
+        libc::NUD_REACHABLE => NeighState::Reachable,
+        libc::NUD_STALE => NeighState::Stale,
+        libc::NUD_DELAY => NeighState::Delay,
+        libc::NUD_PROBE => NeighState::Probe,
+        libc::NUD_FAILED => NeighState::Failed,
+        libc::NUD_NOARP => NeighState::Noarp,
+        libc::NUD_PERMANENT => NeighState::Permanent,
+        _ => NeighState::Unknown,
+    }
+}
+
+fn parse_ip(family: libc::c_int, data: &[u8]) -> Option<IpAddr> {
+    match family {
+        libc::AF_INET => {
+            let octets: [u8; 4] = data.try_into().ok()?;
+            Some(IpAddr::V4(Ipv4Addr::from(octets)))
+        }
+        libc::AF_INET6 => {
+            let octets: [u8; 16] = data.try_into().ok()?;
+            Some(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => None,
+    }
+}
+
+// Resolve an interface index to its name, caching lookups for the duration of a dump
+pub(crate) fn interface_name(ifindex: u32, cache: &mut HashMap<u32, String>) -> Option<String> {
+    if let Some(name) = cache.get(&ifindex) {
+        return Some(name.clone());
+    }
+
+    let mut buf = [0 as libc::c_char; libc::IF_NAMESIZE];
+    // SAFETY: `buf` is IF_NAMESIZE bytes long as required by if_indextoname(3)
+    let ret = unsafe { libc::if_indextoname(ifindex, buf.as_mut_ptr()) };
+    if ret.is_null() {
+        return None;
+    }
+    // SAFETY: on success if_indextoname writes a NUL-terminated name into `buf`
+    let name = unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy().into_owned();
+
+    cache.insert(ifindex, name.clone());
+    Some(name)
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_ne_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]])
+}
+
+fn read_i32(buf: &[u8], offset: usize) -> i32 {
+    read_u32(buf, offset) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETH0: u32 = 2;
+    const WLAN0: u32 = 3;
+
+    fn names() -> HashMap<u32, String> {
+        HashMap::from([(ETH0, "eth0".to_string()), (WLAN0, "wlan0".to_string())])
+    }
+
+    fn attr(kind: u16, data: &[u8]) -> Vec<u8> {
+        let mut attr = Vec::new();
+        attr.extend_from_slice(&((RTATTR_HDR_LEN + data.len()) as u16).to_ne_bytes());
+        attr.extend_from_slice(&kind.to_ne_bytes());
+        attr.extend_from_slice(data);
+        attr.resize(align(attr.len()), 0);
+        attr
+    }
+
+    // struct ndmsg followed by the given attributes
+    fn ndmsg(family: libc::c_int, ifindex: u32, state: u16, attrs: &[Vec<u8>]) -> Vec<u8> {
+        let mut payload = vec![0; NDMSG_LEN];
+        payload[0] = family as u8;
+        payload[4..8].copy_from_slice(&(ifindex as i32).to_ne_bytes());
+        payload[8..10].copy_from_slice(&state.to_ne_bytes());
+        for attr in attrs {
+            payload.extend_from_slice(attr);
+        }
+        payload
+    }
+
+    fn parse(payload: &[u8], filter: Option<&str>) -> Result<NeighborEntry, Skip> {
+        parse_neighbor(payload, filter, &mut names())
+    }
+
+    const MAC: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x10];
+
+    #[test]
+    fn ipv4_entry_decodes() {
+        let payload = ndmsg(libc::AF_INET, ETH0, libc::NUD_REACHABLE, &[attr(libc::NDA_DST, &[192, 168, 1, 10]), attr(libc::NDA_LLADDR, &MAC)]);
+        let entry = parse(&payload, None).unwrap();
+        assert_eq!(entry.ip_address.to_string(), "192.168.1.10");
+        assert_eq!(entry.mac_address.unwrap().to_string(), "02:00:00:00:00:10");
+        assert_eq!(entry.interface, "eth0");
+        assert_eq!(entry.state, NeighState::Reachable);
+
+        let device = entry.into_device().unwrap();
+        assert_eq!(device.sources, [SOURCE]);
+    }
+
+    #[test]
+    fn ipv6_entry_decodes_past_other_attributes() {
+        let ip: Ipv6Addr = "fe80::1".parse().unwrap();
+        // NDA_CACHEINFO and NDA_PROBES come before the address in some kernels
+        let payload = ndmsg(
+            libc::AF_INET6,
+            WLAN0,
+            libc::NUD_STALE,
+            &[attr(libc::NDA_CACHEINFO, &[0; 16]), attr(libc::NDA_DST, &ip.octets()), attr(libc::NDA_PROBES, &[0; 4]), attr(libc::NDA_LLADDR, &MAC)],
+        );
+        let entry = parse(&payload, None).unwrap();
+        assert_eq!(entry.ip_address, IpAddr::V6(ip));
+        assert_eq!(entry.interface, "wlan0");
+        assert_eq!(entry.state, NeighState::Stale);
+    }
+
+    #[test]
+    fn unresolved_entry_has_no_mac() {
+        let payload = ndmsg(libc::AF_INET, ETH0, libc::NUD_FAILED, &[attr(libc::NDA_DST, &[192, 168, 1, 10])]);
+        let entry = parse(&payload, None).unwrap();
+        assert_eq!(entry.mac_address, None);
+        assert_eq!(entry.state, NeighState::Failed);
+        assert!(entry.into_device().is_none());
+    }
+
+    #[test]
+    fn entries_are_skipped_with_a_reason() {
+        let dst = attr(libc::NDA_DST, &[192, 168, 1, 10]);
+        let lladdr = attr(libc::NDA_LLADDR, &MAC);
+
+        let other = ndmsg(libc::AF_INET, WLAN0, libc::NUD_REACHABLE, &[dst.clone(), lladdr.clone()]);
+        assert_eq!(parse(&other, Some("eth0")).err(), Some(Skip::OtherInterface));
+        assert!(parse(&other, Some("wlan0")).is_ok());
+
+        let noarp = ndmsg(libc::AF_INET, ETH0, libc::NUD_NOARP, &[dst.clone(), lladdr.clone()]);
+        assert_eq!(parse(&noarp, None).err(), Some(Skip::Invalid));
+
+        let multicast = ndmsg(libc::AF_INET, ETH0, libc::NUD_REACHABLE, &[attr(libc::NDA_DST, &[224, 0, 0, 251]), lladdr.clone()]);
+        assert_eq!(parse(&multicast, None).err(), Some(Skip::Invalid));
+
+        // Tunnel entries carry 4-byte link-layer addresses
+        let tunnel = ndmsg(libc::AF_INET, ETH0, libc::NUD_REACHABLE, &[dst.clone(), attr(libc::NDA_LLADDR, &[10, 0, 0, 1])]);
+        assert_eq!(parse(&tunnel, None).err(), Some(Skip::Invalid));
+
+        let zero = ndmsg(libc::AF_INET, ETH0, libc::NUD_REACHABLE, &[dst.clone(), attr(libc::NDA_LLADDR, &[0; 6])]);
+        assert_eq!(parse(&zero, None).err(), Some(Skip::Invalid));
+    }
+
+    #[test]
+    fn malformed_payloads_are_rejected() {
+        let dst = attr(libc::NDA_DST, &[192, 168, 1, 10]);
+        assert_eq!(parse(&[0; NDMSG_LEN - 1], None).err(), Some(Skip::Malformed));
+        assert_eq!(parse(&ndmsg(libc::AF_INET, ETH0, libc::NUD_REACHABLE, &[]), None).err(), Some(Skip::Malformed));
+        assert_eq!(parse(&ndmsg(libc::AF_INET, 0x7fff_0000, libc::NUD_REACHABLE, std::slice::from_ref(&dst)), None).err(), Some(Skip::Malformed));
+
+        // An address of the wrong length for the family
+        let short = ndmsg(libc::AF_INET6, ETH0, libc::NUD_REACHABLE, std::slice::from_ref(&dst));
+        assert_eq!(parse(&short, None).err(), Some(Skip::Malformed));
+
+        // An attribute running past the end of the payload stops decoding
+        let mut truncated = ndmsg(libc::AF_INET, ETH0, libc::NUD_REACHABLE, &[dst]);
+        truncated[NDMSG_LEN..NDMSG_LEN + 2].copy_from_slice(&64u16.to_ne_bytes());
+        assert_eq!(parse(&truncated, None).err(), Some(Skip::Malformed));
+    }
+
+    #[test]
+    fn nud_bits_map_to_states() {
+        assert_eq!(neigh_state(libc::NUD_INCOMPLETE), NeighState::Incomplete);
+        assert_eq!(neigh_state(libc::NUD_REACHABLE), NeighState::Reachable);
+        assert_eq!(neigh_state(libc::NUD_STALE), NeighState::Stale);
+        assert_eq!(neigh_state(libc::NUD_DELAY), NeighState::Delay);
+        assert_eq!(neigh_state(libc::NUD_PROBE), NeighState::Probe);
+        assert_eq!(neigh_state(libc::NUD_FAILED), NeighState::Failed);
+        assert_eq!(neigh_state(libc::NUD_NOARP), NeighState::Noarp);
+        assert_eq!(neigh_state(libc::NUD_PERMANENT), NeighState::Permanent);
+        assert_eq!(neigh_state(libc::NUD_NONE), NeighState::Unknown);
+        assert_eq!(neigh_state(libc::NUD_REACHABLE | libc::NUD_PERMANENT), NeighState::Unknown);
+    }
+}
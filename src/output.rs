@@ -0,0 +1,311 @@
+// Event formatting: colored human readable text or one JSON object per line
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::Instant;
+
+use chrono::{DateTime, Local, SecondsFormat};
+use clap::ValueEnum;
+use colored::*;
+use oui_data::lookup;
+use serde::Serialize;
+
+use crate::device::MacAddr;
+use crate::history::DeviceRecord;
+use crate::tracker::{Event, EventKind, MonitoringStats};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Colored human readable lines
+    Text,
+    /// One JSON object per line (NDJSON)
+    Json,
+}
+
+#[derive(Serialize)]
+struct EventRecord<'a> {
+    event: &'static str,
+    timestamp: String,
+    ip: &'a IpAddr,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_ip: Option<&'a IpAddr>,
+    mac: &'a MacAddr,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_mac: Option<&'a MacAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alert: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    severity: Option<&'static str>,
+    vendor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hostname: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    services: &'a [String],
+    interface: &'a str,
+    state: &'static str,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    sources: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    unknown: bool,
+}
+
+#[derive(Serialize)]
+struct SummaryRecord {
+    event: &'static str,
+    timestamp: String,
+    total_devices_seen: usize,
+    peak_concurrent_devices: usize,
+    duration_seconds: u64,
+    skipped_lines: BTreeMap<&'static str, usize>,
+}
+
+#[derive(Serialize)]
+struct DeviceRecordJson<'a> {
+    ip: &'a IpAddr,
+    mac: &'a MacAddr,
+    vendor: Option<&'a str>,
+    interface: &'a str,
+    state: &'static str,
+    first_seen: String,
+    last_seen: String,
+    connected: bool,
+}
+
+pub fn format_event_as(format: OutputFormat, event: &Event) -> String {
+    match format {
+        OutputFormat::Text => format_event(event),
+        OutputFormat::Json => format_event_json(event),
+    }
+}
+
+// Function to format device information with colors for better readability
+pub fn format_event(event: &Event) -> String {
+    let timestamp = event.timestamp.format("%Y-%m-%d %H:%M:%S").to_string();
+    let device = &event.device;
+    let mac = device.mac_address.to_string();
+    let interface = &device.interface;
+
+    // Get vendor information from MAC address
+    let vendor = get_vendor_from_mac(&device.mac_address);
+
+    // Color the event based on connection type
+    let event_text = match event.kind {
+        EventKind::Connected if event.unknown => {
+            format!("[{}] {} {}", timestamp, "[CONNECTED]".green(), "[UNKNOWN DEVICE]".red().bold())
+        }
+        EventKind::Connected => format!("[{}] {}", timestamp, "[CONNECTED]".green()),
+        EventKind::Disconnected => format!("[{}] {}", timestamp, "[DISCONNECTED]".red()),
+        EventKind::IpChanged => format!("[{}] {}", timestamp, "[IP CHANGED]".yellow()),
+        EventKind::Security => format!("[{}] {}", timestamp, "[SECURITY]".red().bold()),
+    };
+
+    // Security alerts describe the conflicting bindings instead of a plain sighting
+    if event.kind == EventKind::Security {
+        let description = event.alert.map(|alert| alert.description()).unwrap_or("Suspicious binding");
+        let binding = match &event.previous {
+            Some(previous) => format!("{} was {} ({}), now {} ({})",
+                                      device.ip_address.to_string().blue(),
+                                      previous.mac_address.to_string().yellow(),
+                                      get_vendor_from_mac(&previous.mac_address).as_deref().unwrap_or("Unknown").cyan(),
+                                      mac.yellow(),
+                                      vendor.as_deref().unwrap_or("Unknown").cyan()),
+            None => format!("IP: {} | MAC: {} | Vendor: {}",
+                            device.ip_address.to_string().blue(),
+                            mac.yellow(),
+                            vendor.as_deref().unwrap_or("Unknown").cyan()),
+        };
+        return format!("{} {} | {}{}{} | Interface: {}",
+                       event_text,
+                       description.red().bold(),
+                       binding,
+                       format_host_details(event),
+                       format_inventory(event),
+                       interface.magenta());
+    }
+
+    // Show the old address for IP changes
+    let ip = match &event.previous {
+        Some(previous) if event.kind == EventKind::IpChanged => {
+            format!("{} -> {}", previous.ip_address, device.ip_address.to_string().blue())
+        }
+        _ => device.ip_address.to_string().blue().to_string(),
+    };
+
+    format!("{} IP: {} | MAC: {} | Vendor: {}{}{} | Interface: {} | State: {}",
+            event_text,
+            ip,
+            mac.yellow(),
+            vendor.as_deref().unwrap_or("Unknown").cyan(),
+            format_host_details(event),
+            format_inventory(event),
+            interface.magenta(),
+            device.state)
+}
+
+// Hostname and announced mDNS services, shown without the underscores and protocol
+fn format_host_details(event: &Event) -> String {
+    let mut text = String::new();
+    if let Some(hostname) = &event.hostname {
+        text.push_str(&format!(" | Hostname: {}", hostname));
+    }
+    if !event.device.services.is_empty() {
+        text.push_str(&format!(" | Services: {}", service_names(&event.device.services)));
+    }
+    text
+}
+
+/// e.g. "airplay, ipp" for `_airplay._tcp` and `_ipp._tcp`
+pub fn service_names(services: &[String]) -> String {
+    let names: Vec<&str> = services
+        .iter()
+        .map(|service| service.split('.').next().unwrap_or(service).trim_start_matches('_'))
+        .collect();
+    names.join(", ")
+}
+
+// Name, owner and tags from the inventory, if the device is known
+fn format_inventory(event: &Event) -> String {
+    let Some(entry) = &event.inventory else {
+        return String::new();
+    };
+
+    let mut text = String::new();
+    if let Some(name) = &entry.name {
+        text.push_str(&format!(" | Name: {}", name.bold()));
+    }
+    if let Some(owner) = &entry.owner {
+        text.push_str(&format!(" | Owner: {}", owner));
+    }
+    if !entry.tags.is_empty() {
+        text.push_str(&format!(" | Tags: {}", entry.tags.join(", ")));
+    }
+    text
+}
+
+// Function to get vendor name from MAC address
+pub fn get_vendor_from_mac(mac: &MacAddr) -> Option<String> {
+    // Use the oui-data crate to look up the vendor
+    lookup(&mac.to_string()).map(|vendor| vendor.organization().to_string())
+}
+
+// Serialize an event as a single-line JSON object
+pub fn format_event_json(event: &Event) -> String {
+    let device = &event.device;
+    let record = EventRecord {
+        event: event.kind.as_str(),
+        timestamp: event.timestamp.to_rfc3339_opts(SecondsFormat::Secs, false),
+        ip: &device.ip_address,
+        previous_ip: event
+            .previous
+            .as_ref()
+            .filter(|previous| previous.ip_address != device.ip_address)
+            .map(|previous| &previous.ip_address),
+        mac: &device.mac_address,
+        previous_mac: event
+            .previous
+            .as_ref()
+            .filter(|previous| previous.mac_address != device.mac_address)
+            .map(|previous| &previous.mac_address),
+        alert: event.alert.map(|alert| alert.as_str()),
+        severity: (event.kind == EventKind::Security).then_some("high"),
+        name: event.inventory.as_ref().and_then(|entry| entry.name.as_deref()),
+        owner: event.inventory.as_ref().and_then(|entry| entry.owner.as_deref()),
+        tags: event.inventory.as_ref().map_or(&[], |entry| entry.tags.as_slice()),
+        unknown: event.unknown,
+        vendor: get_vendor_from_mac(&device.mac_address),
+        hostname: event.hostname.as_deref(),
+        client_id: device.client_id.as_deref(),
+        services: &device.services,
+        interface: &device.interface,
+        state: device.state.as_str(),
+        sources: &device.sources,
+    };
+    serde_json::to_string(&record).expect("event record is always serializable")
+}
+
+// Session summary printed when monitoring stops
+pub fn format_summary(format: OutputFormat, stats: &MonitoringStats) -> String {
+    format_summary_until(format, stats, Instant::now(), Local::now())
+}
+
+/// Summary of a session that ended at `end`, or `timestamp` on the wall clock,
+/// e.g. the last packet of a replayed capture
+pub fn format_summary_until(format: OutputFormat, stats: &MonitoringStats, end: Instant, timestamp: DateTime<Local>) -> String {
+    let duration = end.saturating_duration_since(stats.start_time);
+
+    match format {
+        OutputFormat::Text => {
+            let mins = duration.as_secs() / 60;
+            let secs = duration.as_secs() % 60;
+            let rule = "=".repeat(50);
+            let skipped_reasons: Vec<String> = stats
+                .skipped
+                .by_reason()
+                .iter()
+                .map(|(reason, count)| format!("{} {}", count, reason))
+                .collect();
+            [
+                format!("\n{}", rule),
+                format!("{}", "NETNEIGHBOR SESSION SUMMARY".bold().yellow()),
+                rule.clone(),
+                format!("Total devices seen: {}", stats.total_devices_seen),
+                format!("Peak concurrent devices: {}", stats.peak_concurrent_devices),
+                format!("Monitoring duration: {}m {}s", mins, secs),
+                format!("Skipped neighbor entries: {} ({})", stats.skipped.total(), skipped_reasons.join(", ")),
+                rule,
+            ]
+            .join("\n")
+        }
+        OutputFormat::Json => {
+            let record = SummaryRecord {
+                event: "SUMMARY",
+                timestamp: timestamp.to_rfc3339_opts(SecondsFormat::Secs, false),
+                total_devices_seen: stats.total_devices_seen,
+                peak_concurrent_devices: stats.peak_concurrent_devices,
+                duration_seconds: duration.as_secs(),
+                skipped_lines: stats.skipped.by_reason().into_iter().collect(),
+            };
+            serde_json::to_string(&record).expect("summary record is always serializable")
+        }
+    }
+}
+
+// Per-device summary from the history database
+pub fn format_device_record(format: OutputFormat, record: &DeviceRecord) -> String {
+    let device = &record.device;
+
+    match format {
+        OutputFormat::Text => {
+            let status = if record.connected { "CONNECTED".green() } else { "DISCONNECTED".red() };
+            format!("IP: {} | MAC: {} | Vendor: {} | Interface: {} | First seen: {} | Last seen: {} | {}",
+                    device.ip_address.to_string().blue(),
+                    device.mac_address.to_string().yellow(),
+                    record.vendor.as_deref().unwrap_or("Unknown").cyan(),
+                    device.interface.magenta(),
+                    record.first_seen.format("%Y-%m-%d %H:%M:%S"),
+                    record.last_seen.format("%Y-%m-%d %H:%M:%S"),
+                    status)
+        }
+        OutputFormat::Json => {
+            let json = DeviceRecordJson {
+                ip: &device.ip_address,
+                mac: &device.mac_address,
+                vendor: record.vendor.as_deref(),
+                interface: &device.interface,
+                state: device.state.as_str(),
+                first_seen: record.first_seen.to_rfc3339_opts(SecondsFormat::Secs, false),
+                last_seen: record.last_seen.to_rfc3339_opts(SecondsFormat::Secs, false),
+                connected: record.connected,
+            };
+            serde_json::to_string(&json).expect("device record is always serializable")
+        }
+    }
+}
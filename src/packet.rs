@@ -0,0 +1,685 @@
+// Raw link-layer access over AF_PACKET sockets, local interface addresses from
+// getifaddrs(3), and Ethernet/ARP frame encoding shared by the active scanners.
+//
+// Opening a packet socket requires CAP_NET_RAW.
+
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::io;
+use std::mem;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+pub const ETH_P_ALL: u16 = 0x0003;
+pub const ETH_P_IP: u16 = 0x0800;
+pub const ETH_P_ARP: u16 = 0x0806;
+pub const ETH_P_IPV6: u16 = 0x86dd;
+
+pub const ETH_HEADER_LEN: usize = 14;
+pub const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+
+const ARP_PACKET_LEN: usize = 28;
+pub const ARP_REQUEST: u16 = 1;
+pub const ARP_REPLY: u16 = 2;
+
+const IPV4_MIN_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+const UDP_HEADER_LEN: usize = 8;
+pub const IPPROTO_UDP: u8 = 17;
+pub const IPPROTO_ICMPV6: u8 = 58;
+pub const ICMPV6_ROUTER_SOLICITATION: u8 = 133;
+pub const ICMPV6_NEIGHBOR_SOLICITATION: u8 = 135;
+pub const ICMPV6_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+/// A raw AF_PACKET socket receiving frames of one protocol on every interface
+pub struct PacketSocket {
+    fd: OwnedFd,
+}
+
+impl PacketSocket {
+    pub fn open(protocol: u16) -> io::Result<Self> {
+        // SAFETY: plain socket(2) call; the returned descriptor is checked before use
+        let raw = unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                protocol.to_be() as libc::c_int,
+            )
+        };
+        if raw < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `raw` is a freshly created, valid descriptor that we exclusively own
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+        Ok(PacketSocket { fd })
+    }
+
+    /// Only deliver frames accepted by this classic BPF program
+    pub fn attach_filter(&self, program: &[libc::sock_filter]) -> io::Result<()> {
+        let fprog = libc::sock_fprog {
+            len: program.len() as libc::c_ushort,
+            filter: program.as_ptr() as *mut libc::sock_filter,
+        };
+        // SAFETY: `fprog` points to `program`, which outlives the call; the kernel copies it
+        let result = unsafe {
+            libc::setsockopt(
+                self.fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_ATTACH_FILTER,
+                &fprog as *const libc::sock_fprog as *const libc::c_void,
+                mem::size_of::<libc::sock_fprog>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Have the interface accept frames sent to a link-layer multicast address
+    pub fn add_multicast(&self, ifindex: i32, mac: [u8; 6]) -> io::Result<()> {
+        let mut address = [0u8; 8];
+        address[..6].copy_from_slice(&mac);
+        let request = libc::packet_mreq {
+            mr_ifindex: ifindex,
+            mr_type: libc::PACKET_MR_MULTICAST as libc::c_ushort,
+            mr_alen: 6,
+            mr_address: address,
+        };
+        // SAFETY: `request` is a valid packet_mreq for the length passed; the kernel copies it
+        let result = unsafe {
+            libc::setsockopt(
+                self.fd.as_raw_fd(),
+                libc::SOL_PACKET,
+                libc::PACKET_ADD_MEMBERSHIP,
+                &request as *const libc::packet_mreq as *const libc::c_void,
+                mem::size_of::<libc::packet_mreq>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Send a complete Ethernet frame out of the given interface
+    pub fn send(&self, ifindex: i32, frame: &[u8]) -> io::Result<()> {
+        // SAFETY: sockaddr_ll is plain old data, all-zero is a valid value
+        let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+        addr.sll_family = libc::AF_PACKET as libc::c_ushort;
+        addr.sll_ifindex = ifindex;
+        addr.sll_halen = 6;
+        addr.sll_addr[..6].copy_from_slice(&frame[..6]);
+
+        // SAFETY: `frame` and `addr` are valid for the lengths passed
+        let sent = unsafe {
+            libc::sendto(
+                self.fd.as_raw_fd(),
+                frame.as_ptr() as *const libc::c_void,
+                frame.len(),
+                0,
+                &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Receive one frame, returning its length and the interface it arrived on.
+    /// Frames this host sent itself are reported too, flagged as outgoing.
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<(usize, ReceivedOn)> {
+        // SAFETY: sockaddr_ll is plain old data, all-zero is a valid value
+        let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+        let mut addr_len = mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+
+        // SAFETY: `buf` is valid for writes of `buf.len()` bytes and `addr`/`addr_len`
+        // describe a writable sockaddr_ll
+        let received = unsafe {
+            libc::recvfrom(
+                self.fd.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+                &mut addr as *mut libc::sockaddr_ll as *mut libc::sockaddr,
+                &mut addr_len,
+            )
+        };
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let received_on = ReceivedOn {
+            ifindex: addr.sll_ifindex,
+            outgoing: addr.sll_pkttype == libc::PACKET_OUTGOING,
+        };
+        Ok((received as usize, received_on))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReceivedOn {
+    pub ifindex: i32,
+    /// The frame was sent by this host rather than received
+    pub outgoing: bool,
+}
+
+/// A local network interface with its link-layer and IP addresses
+#[derive(Debug, Clone, Default)]
+pub struct LocalInterface {
+    pub name: String,
+    pub index: i32,
+    pub mac: Option<[u8; 6]>,
+    /// Addresses with their prefix lengths
+    pub ipv4: Vec<(Ipv4Addr, u8)>,
+    pub ipv6: Vec<(Ipv6Addr, u8)>,
+    pub flags: u32,
+}
+
+impl LocalInterface {
+    /// Up, not loopback, and resolving neighbors with ARP/NDP
+    pub fn has_neighbors(&self) -> bool {
+        let flags = self.flags as libc::c_int;
+        flags & libc::IFF_UP != 0
+            && flags & (libc::IFF_LOOPBACK | libc::IFF_NOARP | libc::IFF_POINTOPOINT) == 0
+            && self.mac.is_some()
+    }
+}
+
+/// Every local interface, from getifaddrs(3)
+pub fn local_interfaces() -> io::Result<Vec<LocalInterface>> {
+    let mut list: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: getifaddrs writes a list head we free with freeifaddrs below
+    if unsafe { libc::getifaddrs(&mut list) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut interfaces: HashMap<String, LocalInterface> = HashMap::new();
+    let mut entry = list;
+    while !entry.is_null() {
+        // SAFETY: `entry` is a non-null node of the list returned by getifaddrs
+        let ifa = unsafe { &*entry };
+        entry = ifa.ifa_next;
+
+        // SAFETY: ifa_name is a NUL-terminated string owned by the list
+        let name = unsafe { CStr::from_ptr(ifa.ifa_name) }.to_string_lossy().into_owned();
+        let interface = interfaces.entry(name.clone()).or_insert_with(|| LocalInterface {
+            index: interface_index(&name),
+            name,
+            flags: ifa.ifa_flags,
+            ..Default::default()
+        });
+        if ifa.ifa_addr.is_null() {
+            continue;
+        }
+
+        // SAFETY: ifa_addr points to a sockaddr whose family tells its real type;
+        // ifa_netmask, when set, has the same family
+        unsafe {
+            match (*ifa.ifa_addr).sa_family as libc::c_int {
+                libc::AF_PACKET => {
+                    let ll = &*(ifa.ifa_addr as *const libc::sockaddr_ll);
+                    if ll.sll_halen == 6 {
+                        let mut mac = [0u8; 6];
+                        mac.copy_from_slice(&ll.sll_addr[..6]);
+                        interface.mac = Some(mac);
+                    }
+                }
+                libc::AF_INET => {
+                    let addr = &*(ifa.ifa_addr as *const libc::sockaddr_in);
+                    let prefix = if ifa.ifa_netmask.is_null() {
+                        32
+                    } else {
+                        let mask = &*(ifa.ifa_netmask as *const libc::sockaddr_in);
+                        u32::from_be(mask.sin_addr.s_addr).count_ones() as u8
+                    };
+                    interface.ipv4.push((Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)), prefix));
+                }
+                libc::AF_INET6 => {
+                    let addr = &*(ifa.ifa_addr as *const libc::sockaddr_in6);
+                    let prefix = if ifa.ifa_netmask.is_null() {
+                        128
+                    } else {
+                        let mask = &*(ifa.ifa_netmask as *const libc::sockaddr_in6);
+                        u128::from_be_bytes(mask.sin6_addr.s6_addr).count_ones() as u8
+                    };
+                    interface.ipv6.push((Ipv6Addr::from(addr.sin6_addr.s6_addr), prefix));
+                }
+                _ => {}
+            }
+        }
+    }
+    // SAFETY: `list` came from getifaddrs and is not used afterwards
+    unsafe { libc::freeifaddrs(list) };
+
+    let mut interfaces: Vec<LocalInterface> = interfaces.into_values().collect();
+    interfaces.sort_by_key(|interface| interface.index);
+    Ok(interfaces)
+}
+
+fn interface_index(name: &str) -> i32 {
+    let Ok(name) = std::ffi::CString::new(name) else {
+        return 0;
+    };
+    // SAFETY: `name` is a valid NUL-terminated string
+    unsafe { libc::if_nametoindex(name.as_ptr()) as i32 }
+}
+
+/// Ethernet header followed by `payload`
+pub fn ethernet_frame(dst: [u8; 6], src: [u8; 6], ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(ETH_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&dst);
+    frame.extend_from_slice(&src);
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Ethertype and payload of an Ethernet frame, skipping one 802.1Q VLAN tag
+pub fn parse_ethernet(frame: &[u8]) -> Option<(u16, &[u8])> {
+    if frame.len() < ETH_HEADER_LEN {
+        return None;
+    }
+    let ethertype = u16::from_be_bytes([frame[12], frame[13]]);
+    if ethertype == 0x8100 && frame.len() >= ETH_HEADER_LEN + 4 {
+        return Some((u16::from_be_bytes([frame[16], frame[17]]), &frame[ETH_HEADER_LEN + 4..]));
+    }
+    Some((ethertype, &frame[ETH_HEADER_LEN..]))
+}
+
+/// An IPv4-over-Ethernet ARP packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArpPacket {
+    pub operation: u16,
+    pub sender_mac: [u8; 6],
+    pub sender_ip: Ipv4Addr,
+    pub target_mac: [u8; 6],
+    pub target_ip: Ipv4Addr,
+}
+
+impl ArpPacket {
+    /// Who-has `target_ip`, sent to `dst_mac` (broadcast for discovery, the
+    /// device's own MAC for a unicast liveness probe)
+    pub fn request_frame(src_mac: [u8; 6], src_ip: Ipv4Addr, target_ip: Ipv4Addr, dst_mac: [u8; 6]) -> Vec<u8> {
+        let packet = ArpPacket {
+            operation: ARP_REQUEST,
+            sender_mac: src_mac,
+            sender_ip: src_ip,
+            target_mac: [0; 6],
+            target_ip,
+        };
+        ethernet_frame(dst_mac, src_mac, ETH_P_ARP, &packet.encode())
+    }
+
+    pub fn encode(&self) -> [u8; ARP_PACKET_LEN] {
+        let mut buf = [0u8; ARP_PACKET_LEN];
+        buf[0..2].copy_from_slice(&1u16.to_be_bytes()); // hardware type: Ethernet
+        buf[2..4].copy_from_slice(&ETH_P_IP.to_be_bytes());
+        buf[4] = 6;
+        buf[5] = 4;
+        buf[6..8].copy_from_slice(&self.operation.to_be_bytes());
+        buf[8..14].copy_from_slice(&self.sender_mac);
+        buf[14..18].copy_from_slice(&self.sender_ip.octets());
+        buf[18..24].copy_from_slice(&self.target_mac);
+        buf[24..28].copy_from_slice(&self.target_ip.octets());
+        buf
+    }
+
+    /// Decode the payload of an ARP frame
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        if payload.len() < ARP_PACKET_LEN
+            || payload[0..2] != 1u16.to_be_bytes()
+            || payload[2..4] != ETH_P_IP.to_be_bytes()
+            || payload[4] != 6
+            || payload[5] != 4
+        {
+            return None;
+        }
+
+        let mut sender_mac = [0u8; 6];
+        let mut target_mac = [0u8; 6];
+        sender_mac.copy_from_slice(&payload[8..14]);
+        target_mac.copy_from_slice(&payload[18..24]);
+        Some(ArpPacket {
+            operation: u16::from_be_bytes([payload[6], payload[7]]),
+            sender_mac,
+            sender_ip: Ipv4Addr::new(payload[14], payload[15], payload[16], payload[17]),
+            target_mac,
+            target_ip: Ipv4Addr::new(payload[24], payload[25], payload[26], payload[27]),
+        })
+    }
+}
+
+/// Neighbor Solicitation for `target` sent straight to `dst_mac`, as used for
+/// unicast reachability confirmation (RFC 4861 section 7.2.2)
+pub fn neighbor_solicitation_frame(src_mac: [u8; 6], src_ip: Ipv6Addr, target: Ipv6Addr, dst_mac: [u8; 6]) -> Vec<u8> {
+    // Reserved field, target address and a source link-layer address option
+    let mut icmp = Vec::with_capacity(32);
+    icmp.extend_from_slice(&[ICMPV6_NEIGHBOR_SOLICITATION, 0, 0, 0, 0, 0, 0, 0]);
+    icmp.extend_from_slice(&target.octets());
+    icmp.extend_from_slice(&[1, 1]);
+    icmp.extend_from_slice(&src_mac);
+    let checksum = icmpv6_checksum(src_ip, target, &icmp);
+    icmp[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+    let mut packet = Vec::with_capacity(IPV6_HEADER_LEN + icmp.len());
+    packet.extend_from_slice(&[0x60, 0, 0, 0]);
+    packet.extend_from_slice(&(icmp.len() as u16).to_be_bytes());
+    packet.push(IPPROTO_ICMPV6);
+    packet.push(255); // hop limit, required for neighbor discovery
+    packet.extend_from_slice(&src_ip.octets());
+    packet.extend_from_slice(&target.octets());
+    packet.extend_from_slice(&icmp);
+    ethernet_frame(dst_mac, src_mac, ETH_P_IPV6, &packet)
+}
+
+/// Target address of a Neighbor Advertisement, given the payload of an IPv6 frame
+pub fn parse_neighbor_advertisement(payload: &[u8]) -> Option<Ipv6Addr> {
+    let (header, icmp) = parse_ipv6(payload)?;
+    if header.protocol != IPPROTO_ICMPV6
+        || header.hop_limit != 255
+        || icmp.len() < 24
+        || icmp[0] != ICMPV6_NEIGHBOR_ADVERTISEMENT
+    {
+        return None;
+    }
+    let target: [u8; 16] = icmp[8..24].try_into().ok()?;
+    Some(Ipv6Addr::from(target))
+}
+
+/// Addresses and upper-layer protocol of an IP packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpHeader<A> {
+    pub protocol: u8,
+    pub src: A,
+    pub dst: A,
+    /// TTL for IPv4
+    pub hop_limit: u8,
+}
+
+/// Header and payload of an IPv4 packet. Fragments other than the first are
+/// skipped since they carry no upper-layer header.
+pub fn parse_ipv4(packet: &[u8]) -> Option<(IpHeader<Ipv4Addr>, &[u8])> {
+    if packet.len() < IPV4_MIN_HEADER_LEN || packet[0] >> 4 != 4 {
+        return None;
+    }
+    let header_len = (packet[0] & 0x0f) as usize * 4;
+    let total_len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+    let fragment_offset = u16::from_be_bytes([packet[6], packet[7]]) & 0x1fff;
+    if header_len < IPV4_MIN_HEADER_LEN || total_len < header_len || packet.len() < total_len || fragment_offset != 0 {
+        return None;
+    }
+
+    let header = IpHeader {
+        protocol: packet[9],
+        src: Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]),
+        dst: Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]),
+        hop_limit: packet[8],
+    };
+    Some((header, &packet[header_len..total_len]))
+}
+
+/// Header and payload of an IPv6 packet. Extension headers aren't followed, so
+/// `protocol` is the first next-header value.
+pub fn parse_ipv6(packet: &[u8]) -> Option<(IpHeader<Ipv6Addr>, &[u8])> {
+    if packet.len() < IPV6_HEADER_LEN || packet[0] >> 4 != 6 {
+        return None;
+    }
+    let payload_len = u16::from_be_bytes([packet[4], packet[5]]) as usize;
+    let src: [u8; 16] = packet[8..24].try_into().ok()?;
+    let dst: [u8; 16] = packet[24..40].try_into().ok()?;
+
+    let header = IpHeader {
+        protocol: packet[6],
+        src: Ipv6Addr::from(src),
+        dst: Ipv6Addr::from(dst),
+        hop_limit: packet[7],
+    };
+    Some((header, packet.get(IPV6_HEADER_LEN..IPV6_HEADER_LEN + payload_len)?))
+}
+
+/// Source port, destination port and payload of a UDP datagram
+pub fn parse_udp(segment: &[u8]) -> Option<(u16, u16, &[u8])> {
+    if segment.len() < UDP_HEADER_LEN {
+        return None;
+    }
+    let len = (u16::from_be_bytes([segment[4], segment[5]]) as usize).clamp(UDP_HEADER_LEN, segment.len());
+    Some((
+        u16::from_be_bytes([segment[0], segment[1]]),
+        u16::from_be_bytes([segment[2], segment[3]]),
+        &segment[UDP_HEADER_LEN..len],
+    ))
+}
+
+/// One classic BPF instruction
+pub fn bpf_op(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code: code as u16, jt, jf, k }
+}
+
+/// BPF program passing only untagged Ethernet frames carrying a Neighbor
+/// Advertisement, so the socket isn't woken for all other IPv6 traffic
+pub fn neighbor_advertisement_filter() -> [libc::sock_filter; 8] {
+    const LOAD_HALF: u32 = libc::BPF_LD | libc::BPF_H | libc::BPF_ABS;
+    const LOAD_BYTE: u32 = libc::BPF_LD | libc::BPF_B | libc::BPF_ABS;
+    const JUMP_EQ: u32 = libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K;
+    const RETURN: u32 = libc::BPF_RET | libc::BPF_K;
+    // Mismatches jump forward to the final "drop" instruction
+    let op = |code, k, jf| bpf_op(code, k, 0, jf);
+    [
+        op(LOAD_HALF, 12, 0),
+        op(JUMP_EQ, ETH_P_IPV6 as u32, 5),
+        op(LOAD_BYTE, (ETH_HEADER_LEN + 6) as u32, 0),
+        op(JUMP_EQ, IPPROTO_ICMPV6 as u32, 3),
+        op(LOAD_BYTE, (ETH_HEADER_LEN + IPV6_HEADER_LEN) as u32, 0),
+        op(JUMP_EQ, ICMPV6_NEIGHBOR_ADVERTISEMENT as u32, 1),
+        op(RETURN, u32::MAX, 0),
+        op(RETURN, 0, 0),
+    ]
+}
+
+// One's complement sum over the IPv6 pseudo-header and the ICMPv6 message
+fn icmpv6_checksum(src: Ipv6Addr, dst: Ipv6Addr, message: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    let mut add = |bytes: &[u8]| {
+        for chunk in bytes.chunks(2) {
+            sum += u16::from_be_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)]) as u32;
+        }
+    };
+    add(&src.octets());
+    add(&dst.octets());
+    add(&(message.len() as u32).to_be_bytes());
+    add(&[0, 0, 0, IPPROTO_ICMPV6]);
+    add(message);
+
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+
+    #[test]
+    fn arp_request_frame_bytes() {
+        let frame = ArpPacket::request_frame(MAC, Ipv4Addr::new(192, 168, 1, 2), Ipv4Addr::new(192, 168, 1, 10), BROADCAST_MAC);
+        let expected = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06,
+            0x00, 0x01, 0x08, 0x00, 0x06, 0x04, 0x00, 0x01,
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 192, 168, 1, 2,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 192, 168, 1, 10,
+        ];
+        assert_eq!(frame, expected);
+
+        let (ethertype, payload) = parse_ethernet(&frame).unwrap();
+        assert_eq!(ethertype, ETH_P_ARP);
+        let packet = ArpPacket::parse(payload).unwrap();
+        assert_eq!(packet.operation, ARP_REQUEST);
+        assert_eq!(packet.sender_mac, MAC);
+        assert_eq!(packet.sender_ip, Ipv4Addr::new(192, 168, 1, 2));
+        assert_eq!(packet.target_mac, [0; 6]);
+        assert_eq!(packet.target_ip, Ipv4Addr::new(192, 168, 1, 10));
+    }
+
+    #[test]
+    fn arp_reply_round_trips() {
+        let reply = ArpPacket {
+            operation: ARP_REPLY,
+            sender_mac: [0x3c, 0x22, 0xfb, 0x12, 0x34, 0x56],
+            sender_ip: Ipv4Addr::new(192, 168, 1, 10),
+            target_mac: MAC,
+            target_ip: Ipv4Addr::new(192, 168, 1, 2),
+        };
+        assert_eq!(ArpPacket::parse(&reply.encode()), Some(reply));
+    }
+
+    #[test]
+    fn arp_parse_rejects_other_hardware_and_protocols() {
+        let frame = ArpPacket::request_frame(MAC, Ipv4Addr::new(192, 168, 1, 2), Ipv4Addr::new(192, 168, 1, 10), BROADCAST_MAC);
+        let payload = &frame[ETH_HEADER_LEN..];
+        assert!(ArpPacket::parse(&payload[..ARP_PACKET_LEN - 1]).is_none());
+        for (offset, value) in [(1, 6), (3, 0xdd), (4, 8), (5, 16)] {
+            let mut other = payload.to_vec();
+            other[offset] = value;
+            assert!(ArpPacket::parse(&other).is_none(), "byte {offset} = {value}");
+        }
+    }
+
+    fn ipv6(ip: &str) -> Ipv6Addr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn neighbor_solicitation_frame_bytes() {
+        let device = [0x02, 0x00, 0x00, 0x00, 0x00, 0x02];
+        let frame = neighbor_solicitation_frame(MAC, ipv6("fe80::1"), ipv6("fe80::2"), device);
+
+        let mut expected = vec![0x02, 0x00, 0x00, 0x00, 0x00, 0x02, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x86, 0xdd];
+        expected.extend_from_slice(&[0x60, 0x00, 0x00, 0x00, 0x00, 0x20, 58, 255]);
+        expected.extend_from_slice(&ipv6("fe80::1").octets());
+        expected.extend_from_slice(&ipv6("fe80::2").octets());
+        expected.extend_from_slice(&[135, 0, 0x7a, 0x1b, 0, 0, 0, 0]);
+        expected.extend_from_slice(&ipv6("fe80::2").octets());
+        expected.extend_from_slice(&[1, 1, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(frame, expected);
+    }
+
+    #[test]
+    fn icmpv6_checksum_verifies_to_zero() {
+        let frame = neighbor_solicitation_frame(MAC, ipv6("2001:db8::1"), ipv6("2001:db8::abcd"), BROADCAST_MAC);
+        let icmp = &frame[ETH_HEADER_LEN + IPV6_HEADER_LEN..];
+        assert_eq!(icmpv6_checksum(ipv6("2001:db8::1"), ipv6("2001:db8::abcd"), icmp), 0);
+
+        // Odd lengths are padded with a zero byte: !(0x1200 + length 1 + next header 58)
+        assert_eq!(icmpv6_checksum(ipv6("::"), ipv6("::"), &[0x12]), !0x123b);
+    }
+
+    #[test]
+    fn neighbor_advertisement_target_is_read() {
+        let mut frame = neighbor_solicitation_frame(MAC, ipv6("fe80::2"), ipv6("fe80::1"), MAC);
+        frame[ETH_HEADER_LEN + IPV6_HEADER_LEN] = ICMPV6_NEIGHBOR_ADVERTISEMENT;
+        let payload = &frame[ETH_HEADER_LEN..];
+        assert_eq!(parse_neighbor_advertisement(payload), Some(ipv6("fe80::1")));
+
+        // Neighbor discovery from off-link has a lower hop limit
+        let mut forwarded = payload.to_vec();
+        forwarded[7] = 64;
+        assert_eq!(parse_neighbor_advertisement(&forwarded), None);
+
+        let solicitation = neighbor_solicitation_frame(MAC, ipv6("fe80::2"), ipv6("fe80::1"), MAC);
+        assert_eq!(parse_neighbor_advertisement(&solicitation[ETH_HEADER_LEN..]), None);
+    }
+
+    #[test]
+    fn ethernet_skips_one_vlan_tag() {
+        let frame = ethernet_frame(BROADCAST_MAC, MAC, 0x8100, &[0x00, 0x0a, 0x08, 0x06, 0xaa]);
+        assert_eq!(parse_ethernet(&frame), Some((ETH_P_ARP, &[0xaa][..])));
+        assert_eq!(parse_ethernet(&frame[..ETH_HEADER_LEN - 1]), None);
+    }
+
+    // IPv4 header of `header_len` bytes followed by `payload`
+    fn ipv4(header_len: usize, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0; header_len];
+        packet[0] = 0x40 | (header_len / 4) as u8;
+        packet[2..4].copy_from_slice(&((header_len + payload.len()) as u16).to_be_bytes());
+        packet[8] = 64;
+        packet[9] = IPPROTO_UDP;
+        packet[12..16].copy_from_slice(&[192, 168, 1, 10]);
+        packet[16..20].copy_from_slice(&[255, 255, 255, 255]);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn ipv4_header_and_payload() {
+        let packet = ipv4(20, b"data");
+        let (header, payload) = parse_ipv4(&packet).unwrap();
+        assert_eq!(
+            header,
+            IpHeader { protocol: IPPROTO_UDP, src: Ipv4Addr::new(192, 168, 1, 10), dst: Ipv4Addr::BROADCAST, hop_limit: 64 }
+        );
+        assert_eq!(payload, b"data");
+
+        // Options are skipped and Ethernet padding is cut off
+        let mut padded = ipv4(24, b"data");
+        padded.extend_from_slice(&[0; 6]);
+        assert_eq!(parse_ipv4(&padded).unwrap().1, b"data");
+    }
+
+    #[test]
+    fn ipv4_rejects_fragments_and_bad_lengths() {
+        let mut fragment = ipv4(20, b"data");
+        fragment[6..8].copy_from_slice(&0x00b9u16.to_be_bytes());
+        assert!(parse_ipv4(&fragment).is_none());
+        // The first fragment has its upper-layer header
+        let mut first = ipv4(20, b"data");
+        first[6] = 0x20;
+        assert!(parse_ipv4(&first).is_some());
+
+        let packet = ipv4(20, b"data");
+        assert!(parse_ipv4(&packet[..23]).is_none());
+        let mut short_header = packet.clone();
+        short_header[0] = 0x44;
+        assert!(parse_ipv4(&short_header).is_none());
+        let mut version = packet.clone();
+        version[0] = 0x65;
+        assert!(parse_ipv4(&version).is_none());
+        let mut total = packet;
+        total[2..4].copy_from_slice(&19u16.to_be_bytes());
+        assert!(parse_ipv4(&total).is_none());
+    }
+
+    #[test]
+    fn ipv6_header_and_payload() {
+        let frame = neighbor_solicitation_frame(MAC, ipv6("fe80::1"), ipv6("fe80::2"), MAC);
+        let mut packet = frame[ETH_HEADER_LEN..].to_vec();
+        packet.extend_from_slice(&[0; 4]);
+        let (header, payload) = parse_ipv6(&packet).unwrap();
+        assert_eq!(header, IpHeader { protocol: IPPROTO_ICMPV6, src: ipv6("fe80::1"), dst: ipv6("fe80::2"), hop_limit: 255 });
+        assert_eq!(payload.len(), 32);
+
+        assert!(parse_ipv6(&packet[..IPV6_HEADER_LEN + 31]).is_none());
+        assert!(parse_ipv6(&packet[..IPV6_HEADER_LEN - 1]).is_none());
+        assert!(parse_ipv6(&ipv4(40, b"")).is_none());
+    }
+
+    #[test]
+    fn udp_ports_and_payload() {
+        let segment = [0x00, 0x43, 0x00, 0x44, 0x00, 0x0c, 0x00, 0x00, b'd', b'a', b't', b'a', 0, 0];
+        assert_eq!(parse_udp(&segment), Some((67, 68, &b"data"[..])));
+
+        // Lengths are clamped to the segment
+        let mut long = segment;
+        long[4..6].copy_from_slice(&100u16.to_be_bytes());
+        assert_eq!(parse_udp(&long).unwrap().2.len(), 6);
+        let mut short = segment;
+        short[4..6].copy_from_slice(&2u16.to_be_bytes());
+        assert_eq!(parse_udp(&short).unwrap().2, b"");
+        assert_eq!(parse_udp(&segment[..7]), None);
+    }
+}
@@ -0,0 +1,341 @@
+// Parsers for the text output of `arp -a -n` and `ip neigh show`, used when
+// the kernel neighbor table can't be read over netlink
+
+use std::net::IpAddr;
+
+use crate::device::{self, Device, MacAddr, NeighState};
+
+/// Source name of entries read from `arp -a -n`
+pub const ARP_SOURCE: &str = "arp";
+/// Source name of entries read from `ip neigh show`
+pub const IP_NEIGH_SOURCE: &str = "ip-neigh";
+
+/// Lines a parser didn't turn into devices, by reason
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Skipped {
+    /// Unresolved entries without a MAC (`<incomplete>`, INCOMPLETE, FAILED)
+    pub incomplete: usize,
+    /// Proxy entries this host answers for on behalf of others (`PUB`, `proxy`)
+    pub published: usize,
+    /// Entries whose address or MAC can't belong to a device on the link, such
+    /// as multicast addresses and NOARP mappings
+    pub invalid: usize,
+    /// Lines that aren't recognizable neighbor entries
+    pub malformed: usize,
+    /// The invalid and malformed lines themselves, for diagnostics
+    pub lines: Vec<String>,
+}
+
+impl Skipped {
+    pub fn total(&self) -> usize {
+        self.incomplete + self.published + self.invalid + self.malformed
+    }
+
+    /// Add another parse's counts, without its lines
+    pub fn add(&mut self, other: &Skipped) {
+        self.incomplete += other.incomplete;
+        self.published += other.published;
+        self.invalid += other.invalid;
+        self.malformed += other.malformed;
+    }
+
+    /// Add another parse's counts and lines
+    pub fn append(&mut self, other: Skipped) {
+        self.add(&other);
+        self.lines.extend(other.lines);
+    }
+
+    /// The counts labeled by reason, e.g. for metrics
+    pub fn by_reason(&self) -> [(&'static str, usize); 4] {
+        [
+            ("incomplete", self.incomplete),
+            ("published", self.published),
+            ("invalid", self.invalid),
+            ("malformed", self.malformed),
+        ]
+    }
+
+    /// Count an entry skipped for `skip`
+    pub(crate) fn count(&mut self, skip: Skip) {
+        match skip {
+            Skip::Incomplete => self.incomplete += 1,
+            Skip::Published => self.published += 1,
+            Skip::Invalid => self.invalid += 1,
+            Skip::Malformed => self.malformed += 1,
+            Skip::OtherInterface => {}
+        }
+    }
+
+    fn record(&mut self, skip: Skip, line: &str) {
+        self.count(skip);
+        if matches!(skip, Skip::Invalid | Skip::Malformed) {
+            self.lines.push(line.trim().to_string());
+        }
+    }
+}
+
+/// Why an entry didn't become a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Skip {
+    Incomplete,
+    Published,
+    Invalid,
+    Malformed,
+    // Not counted: the entry is for an interface other than the one asked for
+    OtherInterface,
+}
+
+// The address in canonical form, if a neighbor can hold it
+fn neighbor_ip(token: &str) -> Result<IpAddr, Skip> {
+    let ip = token.parse::<IpAddr>().map_err(|_| Skip::Malformed)?;
+    match device::is_neighbor_address(&ip) {
+        true => Ok(ip),
+        false => Err(Skip::Invalid),
+    }
+}
+
+// The MAC, if a device on the link can have it
+fn neighbor_mac(token: &str) -> Result<MacAddr, Skip> {
+    let mac = token.parse::<MacAddr>().map_err(|_| Skip::Invalid)?;
+    match mac.is_unicast() {
+        true => Ok(mac),
+        false => Err(Skip::Invalid),
+    }
+}
+
+pub fn parse_arp_entries(content: &str, devices: &mut Vec<Device>, interface_filter: Option<&str>) -> Result<Skipped, Box<dyn std::error::Error>> {
+    let mut skipped = Skipped::default();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        match parse_arp_line(line, interface_filter) {
+            Ok(device) => devices.push(device),
+            Err(skip) => skipped.record(skip, line),
+        }
+    }
+    Ok(skipped)
+}
+
+// One `arp -a -n` line. Examples:
+//   "? (192.168.1.1) at aa:bb:cc:dd:ee:ff [ether] on wlan0"
+//   "? (192.168.1.5) at <incomplete> on wlan0"
+//   "? (192.168.1.9) at aa:bb:cc:dd:ee:ff [ether] PERM on wlan0"
+//   "? (192.168.1.7) at * PERM PUB on wlan0"
+//   "? (192.168.1.1) at 0:1b:2c:3d:4e:5f on en0 ifscope [ethernet]" (BSD)
+fn parse_arp_line(line: &str, interface_filter: Option<&str>) -> Result<Device, Skip> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let position = |word: &str| parts.iter().position(|part| *part == word);
+    let (Some(at), Some(on)) = (position("at"), position("on")) else {
+        return Err(Skip::Malformed);
+    };
+    let (Some(ip), Some(mac), Some(iface)) = (parts.get(1), parts.get(at + 1), parts.get(on + 1)) else {
+        return Err(Skip::Malformed);
+    };
+    if interface_filter.is_some_and(|filter| *iface != filter) {
+        return Err(Skip::OtherInterface);
+    }
+
+    // Flags follow the MAC: net-tools prints PERM and PUB, BSD permanent and published
+    let flags = &parts[at + 2..];
+    let flagged = |names: [&str; 2]| flags.iter().any(|flag| names.contains(flag));
+
+    let ip = neighbor_ip(ip.trim_start_matches('(').trim_end_matches(')'))?;
+    if flagged(["PUB", "published"]) {
+        return Err(Skip::Published);
+    }
+    if ["<incomplete>", "(incomplete)"].contains(mac) {
+        return Err(Skip::Incomplete);
+    }
+    let mac = neighbor_mac(mac)?;
+    let state = match flagged(["PERM", "permanent"]) {
+        true => NeighState::Permanent,
+        false => NeighState::Unknown,
+    };
+    Ok(Device::new(ip, mac, iface.to_string()).with_state(state).seen_by(ARP_SOURCE))
+}
+
+pub fn parse_ip_neigh_entries(content: &str, devices: &mut Vec<Device>, interface_filter: Option<&str>) -> Result<Skipped, Box<dyn std::error::Error>> {
+    let mut skipped = Skipped::default();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        match parse_ip_neigh_line(line, interface_filter) {
+            Ok(device) => devices.push(device),
+            Err(skip) => skipped.record(skip, line),
+        }
+    }
+    Ok(skipped)
+}
+
+// One `ip neigh show` line. Examples:
+//   "192.168.1.1 dev wlan0 lladdr aa:bb:cc:dd:ee:ff router REACHABLE"
+//   "192.168.1.5 dev wlan0 FAILED"
+//   "2001:db8::5 dev wlan0 proxy"
+fn parse_ip_neigh_line(line: &str, interface_filter: Option<&str>) -> Result<Device, Skip> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+
+    // Look for MAC address after "lladdr", interface after "dev" and the state token
+    let mut mac = None;
+    let mut iface = None;
+    let mut state = NeighState::Unknown;
+    let mut proxy = false;
+
+    let mut i = 1;
+    while i < parts.len() {
+        if parts[i] == "lladdr" && i + 1 < parts.len() {
+            mac = Some(parts[i + 1]);
+            i += 1;
+        } else if parts[i] == "dev" && i + 1 < parts.len() {
+            iface = Some(parts[i + 1]);
+            i += 1;
+        } else if parts[i] == "proxy" {
+            proxy = true;
+        } else if let Some(parsed) = NeighState::from_ip_neigh_token(parts[i]) {
+            state = parsed;
+        }
+        i += 1;
+    }
+
+    let Some(iface) = iface else {
+        return Err(Skip::Malformed);
+    };
+    if interface_filter.is_some_and(|filter| iface != filter) {
+        return Err(Skip::OtherInterface);
+    }
+
+    let ip = neighbor_ip(parts[0])?;
+    if proxy {
+        return Err(Skip::Published);
+    }
+    // NOARP entries are static multicast/broadcast mappings, not real neighbors
+    if state == NeighState::Noarp {
+        return Err(Skip::Invalid);
+    }
+    let Some(mac) = mac else {
+        return Err(Skip::Incomplete);
+    };
+    let mac = neighbor_mac(mac)?;
+    Ok(Device::new(ip, mac, iface.to_string()).with_state(state).seen_by(IP_NEIGH_SOURCE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(ip: &str, mac: &str, interface: &str, state: NeighState, source: &str) -> Device {
+        Device::new(ip.parse().unwrap(), mac.parse().unwrap(), interface.to_string()).with_state(state).seen_by(source)
+    }
+
+    #[test]
+    fn arp_line_linux() {
+        assert_eq!(
+            parse_arp_line("? (192.168.1.1) at aa:bb:cc:dd:ee:ff [ether] on wlan0", None),
+            Ok(device("192.168.1.1", "aa:bb:cc:dd:ee:ff", "wlan0", NeighState::Unknown, ARP_SOURCE))
+        );
+        assert_eq!(
+            parse_arp_line("gw.lan (192.168.1.9) at AA:BB:CC:DD:EE:01 [ether] PERM on wlan0", None),
+            Ok(device("192.168.1.9", "aa:bb:cc:dd:ee:01", "wlan0", NeighState::Permanent, ARP_SOURCE))
+        );
+    }
+
+    #[test]
+    fn arp_line_bsd() {
+        assert_eq!(
+            parse_arp_line("? (192.168.1.1) at 0:1b:2c:3d:4e:5f on en0 ifscope [ethernet]", None),
+            Ok(device("192.168.1.1", "00:1b:2c:3d:4e:5f", "en0", NeighState::Unknown, ARP_SOURCE))
+        );
+        assert_eq!(
+            parse_arp_line("? (192.168.1.2) at 0:1b:2c:3d:4e:60 on en0 ifscope permanent [ethernet]", None).map(|d| d.state),
+            Ok(NeighState::Permanent)
+        );
+        assert_eq!(parse_arp_line("? (192.168.1.3) at (incomplete) on en0 ifscope [ethernet]", None), Err(Skip::Incomplete));
+        assert_eq!(parse_arp_line("? (192.168.1.4) at 0:1b:2c:3d:4e:61 on en0 ifscope published [ethernet]", None), Err(Skip::Published));
+    }
+
+    #[test]
+    fn arp_line_skips() {
+        assert_eq!(parse_arp_line("? (192.168.1.5) at <incomplete> on wlan0", None), Err(Skip::Incomplete));
+        assert_eq!(parse_arp_line("? (192.168.1.7) at * PERM PUB on wlan0", None), Err(Skip::Published));
+        assert_eq!(parse_arp_line("? (224.0.0.251) at 01:00:5e:00:00:fb [ether] on wlan0", None), Err(Skip::Invalid));
+        assert_eq!(parse_arp_line("? (192.168.1.8) at 01:00:5e:00:00:fb [ether] on wlan0", None), Err(Skip::Invalid));
+        assert_eq!(parse_arp_line("? (192.168.1.8) at 00:00:00:00:00:00 [ether] on wlan0", None), Err(Skip::Invalid));
+        assert_eq!(parse_arp_line("? (192.168.1.8) at not-a-mac [ether] on wlan0", None), Err(Skip::Invalid));
+        assert_eq!(parse_arp_line("? (192.168.1.1) at aa:bb:cc:dd:ee:ff [ether] on eth0", Some("wlan0")), Err(Skip::OtherInterface));
+    }
+
+    #[test]
+    fn arp_line_malformed() {
+        for line in [
+            "garbage",
+            "? (192.168.1.1) aa:bb:cc:dd:ee:ff on wlan0",
+            "? (192.168.1.1) at aa:bb:cc:dd:ee:ff [ether]",
+            "? (192.168.1.1) at aa:bb:cc:dd:ee:ff [ether] on",
+            "? (not-an-ip) at aa:bb:cc:dd:ee:ff [ether] on wlan0",
+        ] {
+            assert_eq!(parse_arp_line(line, None), Err(Skip::Malformed), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn ip_neigh_line() {
+        assert_eq!(
+            parse_ip_neigh_line("192.168.1.1 dev wlan0 lladdr aa:bb:cc:dd:ee:ff router REACHABLE", None),
+            Ok(device("192.168.1.1", "aa:bb:cc:dd:ee:ff", "wlan0", NeighState::Reachable, IP_NEIGH_SOURCE))
+        );
+        assert_eq!(
+            parse_ip_neigh_line("fe80::1 dev eth0 lladdr 02:00:00:00:00:01 STALE", None),
+            Ok(device("fe80::1", "02:00:00:00:00:01", "eth0", NeighState::Stale, IP_NEIGH_SOURCE))
+        );
+        // No state token
+        assert_eq!(
+            parse_ip_neigh_line("192.168.1.2 dev eth0 lladdr 02:00:00:00:00:02", None).map(|d| d.state),
+            Ok(NeighState::Unknown)
+        );
+    }
+
+    #[test]
+    fn ip_neigh_line_skips() {
+        assert_eq!(parse_ip_neigh_line("192.168.1.5 dev wlan0 FAILED", None), Err(Skip::Incomplete));
+        assert_eq!(parse_ip_neigh_line("192.168.1.6 dev wlan0  INCOMPLETE", None), Err(Skip::Incomplete));
+        assert_eq!(parse_ip_neigh_line("2001:db8::5 dev wlan0 proxy", None), Err(Skip::Published));
+        assert_eq!(parse_ip_neigh_line("192.168.1.7 dev wlan0 lladdr aa:bb:cc:dd:ee:ff NOARP", None), Err(Skip::Invalid));
+        assert_eq!(parse_ip_neigh_line("ff02::16 dev wlan0 lladdr 33:33:00:00:00:16 NOARP", None), Err(Skip::Invalid));
+        assert_eq!(parse_ip_neigh_line("192.168.1.8 dev wlan0 lladdr ff:ff:ff:ff:ff:ff STALE", None), Err(Skip::Invalid));
+        assert_eq!(parse_ip_neigh_line("192.168.1.1 dev eth0 lladdr aa:bb:cc:dd:ee:ff REACHABLE", Some("wlan0")), Err(Skip::OtherInterface));
+    }
+
+    #[test]
+    fn ip_neigh_line_malformed() {
+        assert_eq!(parse_ip_neigh_line("192.168.1.1 lladdr aa:bb:cc:dd:ee:ff REACHABLE", None), Err(Skip::Malformed));
+        assert_eq!(parse_ip_neigh_line("192.168.1.1 dev", None), Err(Skip::Malformed));
+        assert_eq!(parse_ip_neigh_line("bogus dev eth0 lladdr aa:bb:cc:dd:ee:ff REACHABLE", None), Err(Skip::Malformed));
+    }
+
+    #[test]
+    fn entries_are_counted_by_reason_with_diagnostic_lines() {
+        let content = "\
+192.168.1.1 dev eth0 lladdr aa:bb:cc:dd:ee:ff REACHABLE
+
+192.168.1.5 dev eth0 FAILED
+2001:db8::5 dev eth0 proxy
+192.168.1.8 dev eth0 lladdr 01:00:5e:00:00:01 STALE
+nonsense
+192.168.1.9 dev wlan0 lladdr aa:bb:cc:dd:ee:01 REACHABLE
+";
+        let mut devices = Vec::new();
+        let skipped = parse_ip_neigh_entries(content, &mut devices, Some("eth0")).unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(skipped.by_reason(), [("incomplete", 1), ("published", 1), ("invalid", 1), ("malformed", 1)]);
+        // Other interfaces aren't skipped lines, and only unusable lines are kept
+        assert_eq!(skipped.total(), 4);
+        assert_eq!(skipped.lines, ["192.168.1.8 dev eth0 lladdr 01:00:5e:00:00:01 STALE", "nonsense"]);
+    }
+
+    #[test]
+    fn skipped_append_adds_counts_and_lines() {
+        let mut total = Skipped { incomplete: 1, lines: vec!["a".to_string()], ..Skipped::default() };
+        total.append(Skipped { incomplete: 2, malformed: 1, lines: vec!["b".to_string()], ..Skipped::default() });
+        assert_eq!((total.incomplete, total.malformed, total.total()), (3, 1, 4));
+        assert_eq!(total.lines, ["a", "b"]);
+
+        total.add(&Skipped { invalid: 1, lines: vec!["c".to_string()], ..Skipped::default() });
+        assert_eq!(total.invalid, 1);
+        assert_eq!(total.lines, ["a", "b"]);
+    }
+}
@@ -0,0 +1,518 @@
+// Reader for pcap and pcapng capture files. Frames are returned as Ethernet
+// frames; Linux cooked captures (`tcpdump -i any`) are converted to match.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b23c4d;
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+// Larger records can only come from a corrupt file
+const MAX_RECORD_LEN: usize = 16 * 1024 * 1024;
+
+/// One captured frame
+#[derive(Debug, Clone)]
+pub struct Packet {
+    pub timestamp: DateTime<Local>,
+    /// Interface name recorded in a pcapng file
+    pub interface: Option<String>,
+    pub frame: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+struct Interface {
+    link_type: u32,
+    name: Option<String>,
+    // Timestamp units per second
+    resolution: u64,
+}
+
+enum Format {
+    Pcap { big_endian: bool, nanos: bool, link_type: u32 },
+    Pcapng { big_endian: bool, interfaces: Vec<Interface> },
+}
+
+/// Streams the packets of a capture file in file order
+pub struct CaptureFile {
+    reader: BufReader<File>,
+    format: Format,
+}
+
+impl CaptureFile {
+    /// Open a pcap or pcapng file, telling them apart by their magic number
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 4];
+        read_exact(&mut reader, &mut magic)?;
+
+        let format = if u32::from_le_bytes(magic) == PCAPNG_SECTION_HEADER {
+            let big_endian = read_section_header(&mut reader)?;
+            Format::Pcapng { big_endian, interfaces: Vec::new() }
+        } else {
+            let (big_endian, nanos) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+                (PCAP_MAGIC_MICROS, _) => (false, false),
+                (PCAP_MAGIC_NANOS, _) => (false, true),
+                (_, PCAP_MAGIC_MICROS) => (true, false),
+                (_, PCAP_MAGIC_NANOS) => (true, true),
+                _ => return Err(invalid("not a pcap or pcapng file")),
+            };
+            let mut header = [0u8; 20];
+            read_exact(&mut reader, &mut header)?;
+            let link_type = u32_at(&header, 16, big_endian) & 0xffff;
+            if !is_supported(link_type) {
+                return Err(unsupported_link_type(link_type));
+            }
+            Format::Pcap { big_endian, nanos, link_type }
+        };
+
+        Ok(CaptureFile { reader, format })
+    }
+
+    fn next_pcap(&mut self) -> io::Result<Option<Packet>> {
+        let Format::Pcap { big_endian, nanos, link_type } = self.format else {
+            unreachable!("pcap record in a pcapng file");
+        };
+        let mut header = [0u8; 16];
+        if !read_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+        let seconds = u32_at(&header, 0, big_endian) as i64;
+        let fraction = u32_at(&header, 4, big_endian);
+        let data = read_vec(&mut self.reader, u32_at(&header, 8, big_endian) as usize)?;
+
+        let nanos = if nanos { fraction } else { fraction.saturating_mul(1000) };
+        Ok(Some(Packet {
+            timestamp: timestamp(seconds, nanos)?,
+            interface: None,
+            frame: to_ethernet(link_type, data).unwrap_or_default(),
+        }))
+    }
+
+    fn next_pcapng(&mut self) -> io::Result<Option<Packet>> {
+        loop {
+            let mut header = [0u8; 8];
+            if !read_or_eof(&mut self.reader, &mut header)? {
+                return Ok(None);
+            }
+
+            // A new section may switch byte order and starts over with its interfaces
+            if u32::from_le_bytes(header[..4].try_into().unwrap()) == PCAPNG_SECTION_HEADER {
+                let mut section = io::Cursor::new(header[4..].to_vec()).chain(&mut self.reader);
+                let big_endian = read_section_header(&mut section)?;
+                self.format = Format::Pcapng { big_endian, interfaces: Vec::new() };
+                continue;
+            }
+
+            let Format::Pcapng { big_endian, ref mut interfaces } = self.format else {
+                unreachable!("pcapng block in a pcap file");
+            };
+            let block_type = u32_at(&header, 0, big_endian);
+            let total_len = u32_at(&header, 4, big_endian) as usize;
+            if total_len < 12 || !total_len.is_multiple_of(4) {
+                return Err(invalid("corrupt pcapng block length"));
+            }
+            // Block body followed by the repeated total length
+            let body = read_vec(&mut self.reader, total_len - 8)?;
+            let body = &body[..body.len() - 4];
+
+            match block_type {
+                PCAPNG_INTERFACE_DESCRIPTION => interfaces.push(read_interface(body, big_endian)?),
+                PCAPNG_ENHANCED_PACKET => {
+                    if body.len() < 20 {
+                        return Err(invalid("truncated enhanced packet block"));
+                    }
+                    let interface = interfaces
+                        .get(u32_at(body, 0, big_endian) as usize)
+                        .ok_or_else(|| invalid("packet for an undeclared interface"))?;
+                    let captured_len = u32_at(body, 12, big_endian) as usize;
+                    let data = body.get(20..20 + captured_len).ok_or_else(|| invalid("truncated packet data"))?;
+
+                    // Interfaces of other link types are skipped
+                    let Some(frame) = to_ethernet(interface.link_type, data.to_vec()) else {
+                        continue;
+                    };
+                    let ticks = ((u32_at(body, 4, big_endian) as u64) << 32) | u32_at(body, 8, big_endian) as u64;
+                    let seconds = ticks / interface.resolution;
+                    let nanos = (ticks % interface.resolution) as u128 * 1_000_000_000 / interface.resolution as u128;
+                    return Ok(Some(Packet {
+                        timestamp: timestamp(seconds as i64, nanos as u32)?,
+                        interface: interface.name.clone(),
+                        frame,
+                    }));
+                }
+                // Simple packet blocks have no timestamp to place them on the timeline
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Iterator for CaptureFile {
+    type Item = io::Result<Packet>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let packet = match self.format {
+            Format::Pcap { .. } => self.next_pcap(),
+            Format::Pcapng { .. } => self.next_pcapng(),
+        };
+        packet.transpose()
+    }
+}
+
+// Reads the rest of a section header block after its type, returning whether
+// the section is big-endian
+fn read_section_header(reader: &mut impl Read) -> io::Result<bool> {
+    let mut start = [0u8; 8];
+    read_exact(reader, &mut start)?;
+    let big_endian = match u32::from_le_bytes(start[4..].try_into().unwrap()) {
+        PCAPNG_BYTE_ORDER_MAGIC => false,
+        magic if magic.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => true,
+        _ => return Err(invalid("corrupt pcapng section header")),
+    };
+    let total_len = u32_at(&start, 0, big_endian) as usize;
+    if total_len < 28 || !total_len.is_multiple_of(4) {
+        return Err(invalid("corrupt pcapng section header length"));
+    }
+    // Version, section length and options aren't needed
+    read_vec(reader, total_len - 12)?;
+    Ok(big_endian)
+}
+
+fn read_interface(body: &[u8], big_endian: bool) -> io::Result<Interface> {
+    if body.len() < 8 {
+        return Err(invalid("truncated interface description block"));
+    }
+    let mut interface = Interface {
+        link_type: u16_at(body, 0, big_endian) as u32,
+        name: None,
+        resolution: 1_000_000,
+    };
+
+    let mut options = &body[8..];
+    while options.len() >= 4 {
+        let code = u16_at(options, 0, big_endian);
+        let len = u16_at(options, 2, big_endian) as usize;
+        let Some(value) = options.get(4..4 + len) else {
+            break;
+        };
+        match code {
+            0 => break,
+            2 => interface.name = Some(String::from_utf8_lossy(value).trim_end_matches('\0').to_string()),
+            // Negative powers of ten, or of two with the high bit set
+            9 if len >= 1 => {
+                let exponent = (value[0] & 0x7f) as u32;
+                let base: u64 = if value[0] & 0x80 == 0 { 10 } else { 2 };
+                interface.resolution = base.checked_pow(exponent).filter(|&r| r > 0).unwrap_or(1_000_000);
+            }
+            _ => {}
+        }
+        options = options.get(4 + len.next_multiple_of(4)..).unwrap_or_default();
+    }
+    Ok(interface)
+}
+
+fn is_supported(link_type: u32) -> bool {
+    [LINKTYPE_ETHERNET, LINKTYPE_LINUX_SLL, LINKTYPE_LINUX_SLL2].contains(&link_type)
+}
+
+fn unsupported_link_type(link_type: u32) -> io::Error {
+    invalid(&format!("unsupported link type {} (only Ethernet and Linux cooked captures)", link_type))
+}
+
+// Rebuild an Ethernet header for Linux cooked captures, which keep the sender's
+// address and the protocol but not the destination
+fn to_ethernet(link_type: u32, data: Vec<u8>) -> Option<Vec<u8>> {
+    let (protocol, address, payload) = match link_type {
+        LINKTYPE_ETHERNET => return Some(data),
+        LINKTYPE_LINUX_SLL if data.len() >= 16 => (&data[14..16], &data[6..12], &data[16..]),
+        LINKTYPE_LINUX_SLL2 if data.len() >= 20 => (&data[0..2], &data[12..18], &data[20..]),
+        _ => return None,
+    };
+    let mut frame = Vec::with_capacity(14 + payload.len());
+    frame.extend_from_slice(&[0; 6]);
+    frame.extend_from_slice(address);
+    frame.extend_from_slice(protocol);
+    frame.extend_from_slice(payload);
+    Some(frame)
+}
+
+fn timestamp(seconds: i64, nanos: u32) -> io::Result<DateTime<Local>> {
+    DateTime::from_timestamp(seconds, nanos)
+        .map(|time| time.with_timezone(&Local))
+        .ok_or_else(|| invalid("packet timestamp out of range"))
+}
+
+// Fill `buf`, or return false at a clean end of file
+fn read_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 if filled == 0 => return Ok(false),
+            0 => return Err(truncated()),
+            n => filled += n,
+        }
+    }
+    Ok(true)
+}
+
+fn read_exact(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<()> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => truncated(),
+        _ => e,
+    })
+}
+
+fn read_vec(reader: &mut impl Read, len: usize) -> io::Result<Vec<u8>> {
+    if len > MAX_RECORD_LEN {
+        return Err(invalid("corrupt record length"));
+    }
+    let mut data = vec![0u8; len];
+    read_exact(reader, &mut data)?;
+    Ok(data)
+}
+
+fn u16_at(buf: &[u8], offset: usize, big_endian: bool) -> u16 {
+    let bytes = buf[offset..offset + 2].try_into().unwrap();
+    if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) }
+}
+
+fn u32_at(buf: &[u8], offset: usize, big_endian: bool) -> u32 {
+    let bytes = buf[offset..offset + 4].try_into().unwrap();
+    if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated capture file")
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: [u8; 16] = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 2, 0, 0, 0, 0, 1, 0x08, 0x06, 0xaa, 0xbb];
+
+    // Write `bytes` to a temporary file and open it; the file is gone once opened
+    fn open(name: &str, bytes: &[u8]) -> io::Result<CaptureFile> {
+        let path = std::env::temp_dir().join(format!("netneighbor-{}-{}", std::process::id(), name));
+        std::fs::write(&path, bytes).unwrap();
+        let capture = CaptureFile::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        capture
+    }
+
+    fn read_all(name: &str, bytes: &[u8]) -> io::Result<Vec<Packet>> {
+        open(name, bytes)?.collect()
+    }
+
+    fn u16_bytes(value: u16, big_endian: bool) -> [u8; 2] {
+        if big_endian { value.to_be_bytes() } else { value.to_le_bytes() }
+    }
+
+    fn u32_bytes(value: u32, big_endian: bool) -> [u8; 4] {
+        if big_endian { value.to_be_bytes() } else { value.to_le_bytes() }
+    }
+
+    fn pcap(magic: u32, big_endian: bool, link_type: u32, records: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let mut file = u32_bytes(magic, big_endian).to_vec();
+        file.extend_from_slice(&u16_bytes(2, big_endian));
+        file.extend_from_slice(&u16_bytes(4, big_endian));
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&u32_bytes(65535, big_endian));
+        file.extend_from_slice(&u32_bytes(link_type, big_endian));
+        for (seconds, fraction, data) in records {
+            file.extend_from_slice(&u32_bytes(*seconds, big_endian));
+            file.extend_from_slice(&u32_bytes(*fraction, big_endian));
+            file.extend_from_slice(&u32_bytes(data.len() as u32, big_endian));
+            file.extend_from_slice(&u32_bytes(data.len() as u32, big_endian));
+            file.extend_from_slice(data);
+        }
+        file
+    }
+
+    fn block(block_type: u32, body: &[u8], big_endian: bool) -> Vec<u8> {
+        let padded = body.len().next_multiple_of(4);
+        let total_len = (12 + padded) as u32;
+        let mut block = u32_bytes(block_type, big_endian).to_vec();
+        block.extend_from_slice(&u32_bytes(total_len, big_endian));
+        block.extend_from_slice(body);
+        block.resize(8 + padded, 0);
+        block.extend_from_slice(&u32_bytes(total_len, big_endian));
+        block
+    }
+
+    fn section_header(big_endian: bool) -> Vec<u8> {
+        let mut body = u32_bytes(PCAPNG_BYTE_ORDER_MAGIC, big_endian).to_vec();
+        body.extend_from_slice(&u16_bytes(1, big_endian));
+        body.extend_from_slice(&u16_bytes(0, big_endian));
+        body.extend_from_slice(&[0xff; 8]);
+        // The block type reads the same in either byte order
+        block(PCAPNG_SECTION_HEADER, &body, big_endian)
+    }
+
+    fn interface(link_type: u16, name: Option<&str>, resolution: Option<u8>, big_endian: bool) -> Vec<u8> {
+        let mut body = u16_bytes(link_type, big_endian).to_vec();
+        body.extend_from_slice(&[0, 0]);
+        body.extend_from_slice(&u32_bytes(0, big_endian));
+        let mut option = |code: u16, value: &[u8]| {
+            body.extend_from_slice(&u16_bytes(code, big_endian));
+            body.extend_from_slice(&u16_bytes(value.len() as u16, big_endian));
+            body.extend_from_slice(value);
+            body.resize(body.len().next_multiple_of(4), 0);
+        };
+        if let Some(name) = name {
+            option(2, name.as_bytes());
+        }
+        if let Some(resolution) = resolution {
+            option(9, &[resolution]);
+        }
+        option(0, &[]);
+        block(PCAPNG_INTERFACE_DESCRIPTION, &body, big_endian)
+    }
+
+    fn enhanced_packet(interface: u32, ticks: u64, data: &[u8], big_endian: bool) -> Vec<u8> {
+        let mut body = u32_bytes(interface, big_endian).to_vec();
+        body.extend_from_slice(&u32_bytes((ticks >> 32) as u32, big_endian));
+        body.extend_from_slice(&u32_bytes(ticks as u32, big_endian));
+        body.extend_from_slice(&u32_bytes(data.len() as u32, big_endian));
+        body.extend_from_slice(&u32_bytes(data.len() as u32, big_endian));
+        body.extend_from_slice(data);
+        block(PCAPNG_ENHANCED_PACKET, &body, big_endian)
+    }
+
+    fn at(seconds: i64, nanos: u32) -> DateTime<Local> {
+        timestamp(seconds, nanos).unwrap()
+    }
+
+    #[test]
+    fn pcap_in_both_byte_orders_and_resolutions() {
+        let little = pcap(PCAP_MAGIC_MICROS, false, LINKTYPE_ETHERNET, &[(1_700_000_000, 250_000, &FRAME), (1_700_000_001, 0, &FRAME[..14])]);
+        let packets = read_all("micros.pcap", &little).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].timestamp, at(1_700_000_000, 250_000_000));
+        assert_eq!(packets[0].frame, FRAME);
+        assert_eq!(packets[0].interface, None);
+        assert_eq!(packets[1].frame, FRAME[..14]);
+
+        let big = pcap(PCAP_MAGIC_NANOS, true, LINKTYPE_ETHERNET, &[(1_700_000_000, 123, &FRAME)]);
+        let packets = read_all("nanos.pcap", &big).unwrap();
+        assert_eq!(packets[0].timestamp, at(1_700_000_000, 123));
+        assert_eq!(packets[0].frame, FRAME);
+    }
+
+    #[test]
+    fn linux_cooked_captures_become_ethernet() {
+        let payload = [0xaa, 0xbb];
+        let expected = [0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 1, 0x08, 0x06, 0xaa, 0xbb];
+
+        // Packet type, ARPHRD type, address length, 8 address bytes, protocol
+        let mut sll = vec![0, 0, 0, 1, 0, 6, 2, 0, 0, 0, 0, 1, 0, 0, 0x08, 0x06];
+        sll.extend_from_slice(&payload);
+        let packets = read_all("sll.pcap", &pcap(PCAP_MAGIC_MICROS, false, LINKTYPE_LINUX_SLL, &[(0, 0, &sll)])).unwrap();
+        assert_eq!(packets[0].frame, expected);
+
+        // Protocol, reserved, ifindex, ARPHRD type, packet type, address length, 8 address bytes
+        let mut sll2 = vec![0x08, 0x06, 0, 0, 0, 0, 0, 2, 0, 1, 0, 6, 2, 0, 0, 0, 0, 1, 0, 0];
+        sll2.extend_from_slice(&payload);
+        let packets = read_all("sll2.pcap", &pcap(PCAP_MAGIC_MICROS, false, LINKTYPE_LINUX_SLL2, &[(0, 0, &sll2)])).unwrap();
+        assert_eq!(packets[0].frame, expected);
+
+        // Records too short for their header come out empty rather than failing the file
+        let packets = read_all("short-sll.pcap", &pcap(PCAP_MAGIC_MICROS, false, LINKTYPE_LINUX_SLL2, &[(0, 0, &sll2[..19])])).unwrap();
+        assert!(packets[0].frame.is_empty());
+    }
+
+    #[test]
+    fn pcap_rejects_bad_files() {
+        let error = |name, bytes: &[u8]| read_all(name, bytes).unwrap_err();
+        assert_eq!(error("magic.pcap", b"GIF89a\0\0").kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error("empty.pcap", b"").kind(), io::ErrorKind::UnexpectedEof);
+        assert!(error("wifi.pcap", &pcap(PCAP_MAGIC_MICROS, false, 105, &[])).to_string().contains("unsupported link type 105"));
+
+        let file = pcap(PCAP_MAGIC_MICROS, false, LINKTYPE_ETHERNET, &[(0, 0, &FRAME)]);
+        assert_eq!(error("truncated-data.pcap", &file[..file.len() - 1]).kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(error("truncated-header.pcap", &file[..24 + 8]).kind(), io::ErrorKind::UnexpectedEof);
+        // A file ending between records is complete
+        assert!(read_all("header-only.pcap", &file[..24]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn pcapng_packets_carry_interface_names_and_resolution() {
+        let mut file = section_header(false);
+        file.extend(interface(LINKTYPE_ETHERNET as u16, Some("eth0"), None, false));
+        file.extend(interface(LINKTYPE_ETHERNET as u16, Some("wlan0"), Some(9), false));
+        // 802.11 interfaces are skipped, not rejected
+        file.extend(interface(105, Some("mon0"), None, false));
+        file.extend(enhanced_packet(0, 1_700_000_000_250_000, &FRAME, false));
+        file.extend(enhanced_packet(2, 1_700_000_000_500_000, &FRAME, false));
+        file.extend(block(3, &[0; 8], false));
+        file.extend(enhanced_packet(1, 1_700_000_001_000_000_123, &FRAME[..15], false));
+
+        let packets = read_all("interfaces.pcapng", &file).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].interface.as_deref(), Some("eth0"));
+        assert_eq!(packets[0].timestamp, at(1_700_000_000, 250_000_000));
+        assert_eq!(packets[0].frame, FRAME);
+        assert_eq!(packets[1].interface.as_deref(), Some("wlan0"));
+        assert_eq!(packets[1].timestamp, at(1_700_000_001, 123));
+        assert_eq!(packets[1].frame, FRAME[..15]);
+    }
+
+    #[test]
+    fn pcapng_sections_switch_byte_order_and_interfaces() {
+        let mut file = section_header(true);
+        file.extend(interface(LINKTYPE_ETHERNET as u16, Some("eth0"), None, true));
+        file.extend(enhanced_packet(0, 1_000_000, &FRAME, true));
+        file.extend(section_header(false));
+        file.extend(interface(LINKTYPE_LINUX_SLL2 as u16, Some("any"), Some(0x80 | 10), false));
+        let mut sll2 = vec![0x08, 0x06, 0, 0, 0, 0, 0, 2, 0, 1, 0, 6, 2, 0, 0, 0, 0, 1, 0, 0];
+        sll2.extend_from_slice(&[0xaa, 0xbb]);
+        file.extend(enhanced_packet(0, 3 * 1024 + 512, &sll2, false));
+
+        let packets = read_all("sections.pcapng", &file).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!((packets[0].interface.as_deref(), packets[0].timestamp), (Some("eth0"), at(1, 0)));
+        // Binary resolution: 2^-10 seconds per tick
+        assert_eq!((packets[1].interface.as_deref(), packets[1].timestamp), (Some("any"), at(3, 500_000_000)));
+        assert_eq!(packets[1].frame, [0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 1, 0x08, 0x06, 0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn pcapng_rejects_corrupt_blocks() {
+        let error = |name, bytes: &[u8]| read_all(name, bytes).unwrap_err().to_string();
+
+        let mut undeclared = section_header(false);
+        undeclared.extend(enhanced_packet(0, 0, &FRAME, false));
+        assert!(error("undeclared.pcapng", &undeclared).contains("undeclared interface"));
+
+        let mut bad_length = section_header(false);
+        bad_length.extend(interface(LINKTYPE_ETHERNET as u16, None, None, false));
+        bad_length.extend_from_slice(&6u32.to_le_bytes());
+        bad_length.extend_from_slice(&13u32.to_le_bytes());
+        assert!(error("length.pcapng", &bad_length).contains("block length"));
+
+        let mut bad_magic = section_header(false);
+        bad_magic[8..12].copy_from_slice(&[1, 2, 3, 4]);
+        assert!(error("section.pcapng", &bad_magic).contains("section header"));
+
+        let mut truncated_packet = section_header(false);
+        truncated_packet.extend(interface(LINKTYPE_ETHERNET as u16, None, None, false));
+        let packet = enhanced_packet(0, 0, &FRAME, false);
+        truncated_packet.extend_from_slice(&packet[..packet.len() - 4]);
+        assert!(error("truncated.pcapng", &truncated_packet).contains("truncated"));
+    }
+}
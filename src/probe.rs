@@ -0,0 +1,188 @@
+// Active liveness probing: unicast ARP requests (IPv4) and Neighbor
+// Solicitations (IPv6) sent straight to a device's MAC address to confirm it
+// is still there before the tracker reports it disconnected
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::device::{self, Device, MacAddr, NeighState};
+use crate::diag;
+use crate::netlink::interface_name;
+use crate::packet::{self, ArpPacket, LocalInterface, PacketSocket, ETH_P_ARP, ETH_P_IPV6};
+
+#[derive(Debug, Clone)]
+pub struct ProbeConfig {
+    /// Unanswered probes before an address is given up
+    pub count: u32,
+    /// Spacing between probes, and how long the last one may take to be answered
+    pub interval: Duration,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        ProbeConfig {
+            count: 3,
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl ProbeConfig {
+    /// Time from the first probe until the address counts as gone
+    pub fn window(&self) -> Duration {
+        self.interval * self.count
+    }
+}
+
+/// Source name of probe answers
+pub const SOURCE: &str = "probe";
+
+// Answers arriving later than this are no longer expected
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+struct ProbeState {
+    // Probed (ip, mac, interface) bindings still waiting for an answer, with
+    // when they were last probed
+    pending: HashMap<(IpAddr, MacAddr, String), Instant>,
+    answers: Vec<Device>,
+}
+
+/// Sends probes and collects the answers on background threads
+pub struct Prober {
+    arp: Arc<PacketSocket>,
+    ndp: Arc<PacketSocket>,
+    state: Arc<Mutex<ProbeState>>,
+}
+
+impl Prober {
+    /// Open the packet sockets and start listening for answers
+    pub fn start() -> io::Result<Self> {
+        let arp = Arc::new(PacketSocket::open(ETH_P_ARP)?);
+        let ndp = Arc::new(PacketSocket::open(ETH_P_IPV6)?);
+        ndp.attach_filter(&packet::neighbor_advertisement_filter())?;
+        let state = Arc::new(Mutex::new(ProbeState::default()));
+
+        for socket in [&arp, &ndp] {
+            let (socket, state) = (Arc::clone(socket), Arc::clone(&state));
+            thread::spawn(move || receive_answers(&socket, &state));
+        }
+
+        Ok(Prober { arp, ndp, state })
+    }
+
+    /// Send one probe to each device's address and MAC
+    pub fn probe(&self, devices: &[Device]) {
+        if devices.is_empty() {
+            return;
+        }
+        self.state.lock().unwrap().pending.retain(|_, sent| sent.elapsed() < ANSWER_TIMEOUT);
+        let interfaces = match packet::local_interfaces() {
+            Ok(interfaces) => interfaces,
+            Err(e) => {
+                diag::warn(&format!("Probe: cannot list interfaces: {}", e));
+                return;
+            }
+        };
+
+        for device in devices {
+            let Some(interface) = interfaces.iter().find(|interface| interface.name == device.interface) else {
+                continue;
+            };
+            let ip = device.ip_address;
+            let Some(frame) = probe_frame(interface, ip, device.mac_address.octets()) else {
+                continue;
+            };
+
+            let binding = (ip, device.mac_address, device.interface.clone());
+            self.state.lock().unwrap().pending.insert(binding, Instant::now());
+            let socket = if ip.is_ipv4() { &self.arp } else { &self.ndp };
+            if let Err(e) = socket.send(interface.index, &frame) {
+                diag::warn(&format!("Probe: send to {} on {} failed: {}", ip, interface.name, e));
+            }
+        }
+    }
+
+    /// Devices that answered a probe since the last call, as REACHABLE sightings
+    pub fn answers(&self) -> Vec<Device> {
+        std::mem::take(&mut self.state.lock().unwrap().answers)
+    }
+}
+
+fn probe_frame(interface: &LocalInterface, ip: IpAddr, dst_mac: [u8; 6]) -> Option<Vec<u8>> {
+    let src_mac = interface.mac?;
+    match ip {
+        IpAddr::V4(target) => {
+            // Prefer our address on the target's subnet; an ARP probe from
+            // 0.0.0.0 is still answered (RFC 5227)
+            let src_ip = interface
+                .ipv4
+                .iter()
+                .find(|(local, prefix)| {
+                    let mask = u32::MAX.checked_shl(32 - *prefix as u32).unwrap_or(0);
+                    u32::from(*local) & mask == u32::from(target) & mask
+                })
+                .or(interface.ipv4.first())
+                .map_or(Ipv4Addr::UNSPECIFIED, |(local, _)| *local);
+            Some(ArpPacket::request_frame(src_mac, src_ip, target, dst_mac))
+        }
+        IpAddr::V6(target) => {
+            // Neighbor discovery is sourced from the link-local address when there is one
+            let src_ip = interface
+                .ipv6
+                .iter()
+                .map(|(local, _)| *local)
+                .min_by_key(|local| !device::is_link_local(&IpAddr::V6(*local)))?;
+            Some(packet::neighbor_solicitation_frame(src_mac, src_ip, target, dst_mac))
+        }
+    }
+}
+
+fn receive_answers(socket: &PacketSocket, state: &Mutex<ProbeState>) {
+    let mut buf = [0u8; 2048];
+    let mut interface_names = HashMap::new();
+
+    loop {
+        let (len, received_on) = match socket.recv(&mut buf) {
+            Ok(received) => received,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                diag::warn(&format!("Probe: receive failed: {}", e));
+                return;
+            }
+        };
+        if received_on.outgoing {
+            continue;
+        }
+
+        let frame = &buf[..len];
+        let answer = match packet::parse_ethernet(frame) {
+            Some((ETH_P_ARP, payload)) => {
+                ArpPacket::parse(payload).map(|arp| (IpAddr::V4(arp.sender_ip), arp.sender_mac))
+            }
+            // Advertisements answering a unicast solicitation may leave out the
+            // target link-layer option, so take the MAC from the frame itself
+            Some((ETH_P_IPV6, payload)) => packet::parse_neighbor_advertisement(payload)
+                .map(|target| (IpAddr::V6(target), frame[6..12].try_into().unwrap())),
+            _ => None,
+        };
+        let Some((ip, mac)) = answer else {
+            continue;
+        };
+        let Some(interface) = interface_name(received_on.ifindex as u32, &mut interface_names) else {
+            continue;
+        };
+
+        // Any ARP packet from the probed binding proves it is alive, not just the reply
+        let mut state = state.lock().unwrap();
+        let binding = (ip, MacAddr(mac), interface);
+        if state.pending.remove(&binding).is_some() {
+            let (ip, mac, interface) = binding;
+            state.answers.push(Device::new(ip, mac, interface).with_state(NeighState::Reachable).seen_by(SOURCE));
+        }
+    }
+}
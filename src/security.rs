@@ -0,0 +1,389 @@
+// ARP spoofing / MAC conflict detection. The tracker consults the detector
+// whenever a device claims an address it didn't hold before.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+
+use crate::device::{self, Device, MacAddr};
+use crate::tracker::{Event, EventKind, TrackedDevice};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityAlert {
+    /// An address still held by one device was claimed by a different MAC
+    IpConflict,
+    /// A default gateway's address is now answered by a different MAC
+    GatewayMacChanged,
+    /// One MAC claimed many addresses within a short window
+    ManyAddresses,
+}
+
+impl SecurityAlert {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SecurityAlert::IpConflict => "IP_CONFLICT",
+            SecurityAlert::GatewayMacChanged => "GATEWAY_MAC_CHANGED",
+            SecurityAlert::ManyAddresses => "MANY_ADDRESSES",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            SecurityAlert::IpConflict => "IP address claimed by multiple MACs",
+            SecurityAlert::GatewayMacChanged => "Gateway MAC address changed",
+            SecurityAlert::ManyAddresses => "MAC claimed many addresses in a short window",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "IP_CONFLICT" => Some(SecurityAlert::IpConflict),
+            "GATEWAY_MAC_CHANGED" => Some(SecurityAlert::GatewayMacChanged),
+            "MANY_ADDRESSES" => Some(SecurityAlert::ManyAddresses),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SecurityConfig {
+    pub enabled: bool,
+    /// Alert when one MAC claims at least this many addresses within `claim_window`.
+    /// IPv6 addresses in one /64 count once, so privacy address rotation doesn't alert.
+    pub max_addresses_per_mac: usize,
+    pub claim_window: Duration,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        SecurityConfig {
+            enabled: true,
+            max_addresses_per_mac: 8,
+            claim_window: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A default route next hop
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Gateway {
+    pub ip_address: IpAddr,
+    pub interface: String,
+}
+
+#[derive(Debug, Default)]
+pub struct SecurityDetector {
+    config: SecurityConfig,
+    gateways: HashSet<Gateway>,
+    // MAC each gateway address was last answered by
+    gateway_macs: HashMap<Gateway, MacAddr>,
+    // Recent address claims per device key, IPv6 addresses by their /64
+    claims: HashMap<String, Vec<(IpAddr, Instant)>>,
+    last_many_addresses_alert: HashMap<String, Instant>,
+}
+
+impl SecurityDetector {
+    pub fn new(config: SecurityConfig) -> Self {
+        SecurityDetector { config, ..Default::default() }
+    }
+
+    pub fn config(&self) -> &SecurityConfig {
+        &self.config
+    }
+
+    /// Change the settings, keeping the gateway MACs and address claims seen so far
+    pub fn set_config(&mut self, config: SecurityConfig) {
+        self.config = config;
+    }
+
+    pub fn set_gateways(&mut self, gateways: Vec<Gateway>) {
+        self.gateways = gateways.into_iter().collect();
+    }
+
+    /// Check a present sighting. `new_claim` is true when the device didn't hold
+    /// this address before (including brand new devices).
+    pub fn check(
+        &mut self,
+        device: &Device,
+        new_claim: bool,
+        devices: &HashMap<String, TrackedDevice>,
+        now: Instant,
+        timestamp: DateTime<Local>,
+    ) -> Vec<Event> {
+        if !self.config.enabled {
+            return Vec::new();
+        }
+
+        let mut events = Vec::new();
+        let alert = |alert: SecurityAlert, previous: Option<Device>| Event {
+            previous,
+            alert: Some(alert),
+            ..Event::new(EventKind::Security, device.clone(), timestamp)
+        };
+
+        // Gateway answered by a different MAC than before
+        let gateway = Gateway {
+            ip_address: device.ip_address,
+            interface: device.interface.clone(),
+        };
+        let mut gateway_alerted = false;
+        if self.gateways.contains(&gateway) {
+            match self.gateway_macs.insert(gateway, device.mac_address) {
+                Some(old_mac) if old_mac != device.mac_address => {
+                    let previous = Device { mac_address: old_mac, ..device.clone() };
+                    events.push(alert(SecurityAlert::GatewayMacChanged, Some(previous)));
+                    gateway_alerted = true;
+                }
+                _ => {}
+            }
+        }
+
+        if !new_claim {
+            return events;
+        }
+
+        // Address still held by another device on the same interface
+        if !gateway_alerted {
+            let key = device.key();
+            let holder = devices.iter().find(|(other_key, tracked)| {
+                **other_key != key
+                    && tracked.device.interface == device.interface
+                    && tracked.addresses.contains_key(&device.ip_address)
+            });
+            if let Some((_, tracked)) = holder {
+                let previous = Device { ip_address: device.ip_address, ..tracked.device.clone() };
+                events.push(alert(SecurityAlert::IpConflict, Some(previous)));
+            }
+        }
+
+        // Many addresses claimed by one MAC in a short window
+        let window = self.config.claim_window;
+        // Forget devices whose claims and last alert have all left the window
+        for claims in self.claims.values_mut() {
+            claims.retain(|(_, claimed)| now.duration_since(*claimed) <= window);
+        }
+        self.claims.retain(|_, claims| !claims.is_empty());
+        self.last_many_addresses_alert.retain(|_, last| now.duration_since(*last) <= window);
+        let claims = self.claims.entry(device.key()).or_default();
+        let claim = device::address_group(&device.ip_address);
+        if !claims.iter().any(|(group, _)| *group == claim) {
+            claims.push((claim, now));
+        }
+
+        let recently_alerted = self
+            .last_many_addresses_alert
+            .get(&device.key())
+            .is_some_and(|last| now.duration_since(*last) <= window);
+        if claims.len() >= self.config.max_addresses_per_mac && !recently_alerted {
+            self.last_many_addresses_alert.insert(device.key(), now);
+            events.push(alert(SecurityAlert::ManyAddresses, None));
+        }
+
+        events
+    }
+}
+
+/// Read the IPv4 and IPv6 default gateways from /proc
+pub fn default_gateways() -> Vec<Gateway> {
+    let mut gateways = Vec::new();
+
+    // Iface  Destination  Gateway  Flags ... with addresses as host-order hex
+    if let Ok(content) = fs::read_to_string("/proc/net/route") {
+        for line in content.lines().skip(1) {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 3 || parts[1] != "00000000" {
+                continue;
+            }
+            if let Ok(gateway) = u32::from_str_radix(parts[2], 16)
+                && gateway != 0
+            {
+                gateways.push(Gateway {
+                    ip_address: IpAddr::V4(Ipv4Addr::from(gateway.to_ne_bytes())),
+                    interface: parts[0].to_string(),
+                });
+            }
+        }
+    }
+
+    // dest dest_len src src_len next_hop metric refcnt use flags iface
+    if let Ok(content) = fs::read_to_string("/proc/net/ipv6_route") {
+        for line in content.lines() {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 10 || parts[0].chars().any(|c| c != '0') || parts[1] != "00" {
+                continue;
+            }
+            if let Some(next_hop) = parse_ipv6_hex(parts[4])
+                && !next_hop.is_unspecified()
+            {
+                gateways.push(Gateway {
+                    ip_address: IpAddr::V6(next_hop),
+                    interface: parts[9].to_string(),
+                });
+            }
+        }
+    }
+
+    gateways
+}
+
+fn parse_ipv6_hex(hex: &str) -> Option<Ipv6Addr> {
+    u128::from_str_radix(hex, 16).ok().map(Ipv6Addr::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::NeighState;
+    use crate::tracker::{Tracker, TrackerConfig};
+
+    fn device(ip: &str, mac: &str) -> Device {
+        Device::new(ip.parse().unwrap(), mac.parse().unwrap(), "eth0".to_string()).with_state(NeighState::Reachable)
+    }
+
+    // Tracked devices holding these sightings' addresses
+    fn holding(sightings: Vec<Device>) -> HashMap<String, TrackedDevice> {
+        let mut tracker = Tracker::new(TrackerConfig::default());
+        tracker.restore(sightings, Instant::now());
+        tracker.devices().map(|tracked| (tracked.device.key(), tracked.clone())).collect()
+    }
+
+    fn alerts(events: &[Event]) -> Vec<SecurityAlert> {
+        events.iter().filter_map(|event| event.alert).collect()
+    }
+
+    fn gateway() -> Gateway {
+        Gateway { ip_address: "192.168.1.1".parse().unwrap(), interface: "eth0".to_string() }
+    }
+
+    #[test]
+    fn address_held_by_another_mac_conflicts() {
+        let mut detector = SecurityDetector::new(SecurityConfig::default());
+        let devices = holding(vec![device("192.168.1.10", "02:00:00:00:00:01")]);
+        let intruder = device("192.168.1.10", "02:00:00:00:00:66");
+
+        let events = detector.check(&intruder, true, &devices, Instant::now(), Local::now());
+        assert_eq!(alerts(&events), [SecurityAlert::IpConflict]);
+        assert_eq!(events[0].kind, EventKind::Security);
+        assert_eq!(events[0].device.mac_address, intruder.mac_address);
+        let previous = events[0].previous.as_ref().unwrap();
+        assert_eq!(previous.mac_address.to_string(), "02:00:00:00:00:01");
+        assert_eq!(previous.ip_address, intruder.ip_address);
+
+        // Only new claims are checked
+        assert!(detector.check(&intruder, false, &devices, Instant::now(), Local::now()).is_empty());
+    }
+
+    #[test]
+    fn same_mac_or_other_interface_is_no_conflict() {
+        let mut detector = SecurityDetector::new(SecurityConfig::default());
+        let devices = holding(vec![device("192.168.1.10", "02:00:00:00:00:01")]);
+        let now = Instant::now();
+
+        let same = device("192.168.1.10", "02:00:00:00:00:01");
+        assert!(detector.check(&same, true, &devices, now, Local::now()).is_empty());
+        let other_interface = Device { interface: "wlan0".to_string(), ..device("192.168.1.10", "02:00:00:00:00:66") };
+        assert!(detector.check(&other_interface, true, &devices, now, Local::now()).is_empty());
+    }
+
+    #[test]
+    fn gateway_mac_change_alerts_once() {
+        let mut detector = SecurityDetector::new(SecurityConfig::default());
+        detector.set_gateways(vec![gateway()]);
+        let router = device("192.168.1.1", "02:00:00:00:00:fe");
+        let intruder = device("192.168.1.1", "02:00:00:00:00:66");
+        let now = Instant::now();
+
+        assert!(detector.check(&router, true, &holding(Vec::new()), now, Local::now()).is_empty());
+        assert!(detector.check(&router, false, &holding(vec![router.clone()]), now, Local::now()).is_empty());
+
+        // The conflict with the router's own entry is covered by the gateway alert
+        let events = detector.check(&intruder, true, &holding(vec![router.clone()]), now, Local::now());
+        assert_eq!(alerts(&events), [SecurityAlert::GatewayMacChanged]);
+        assert_eq!(events[0].previous.as_ref().unwrap().mac_address, router.mac_address);
+        assert!(detector.check(&intruder, false, &holding(vec![intruder.clone()]), now, Local::now()).is_empty());
+    }
+
+    #[test]
+    fn gateway_address_on_another_interface_is_not_watched() {
+        let mut detector = SecurityDetector::new(SecurityConfig::default());
+        detector.set_gateways(vec![gateway()]);
+        let now = Instant::now();
+        let elsewhere = |mac: &str| Device { interface: "wlan0".to_string(), ..device("192.168.1.1", mac) };
+        detector.check(&elsewhere("02:00:00:00:00:fe"), false, &HashMap::new(), now, Local::now());
+        assert!(detector.check(&elsewhere("02:00:00:00:00:66"), false, &HashMap::new(), now, Local::now()).is_empty());
+    }
+
+    #[test]
+    fn many_addresses_alert_once_per_window() {
+        let config = SecurityConfig { max_addresses_per_mac: 3, ..SecurityConfig::default() };
+        let mut detector = SecurityDetector::new(config);
+        let start = Instant::now();
+        let mut claim = |ip: &str, secs: u64| {
+            let events = detector.check(&device(ip, "02:00:00:00:00:01"), true, &HashMap::new(), start + Duration::from_secs(secs), Local::now());
+            alerts(&events)
+        };
+
+        assert!(claim("192.168.1.10", 0).is_empty());
+        assert!(claim("192.168.1.11", 1).is_empty());
+        // Claiming an address again doesn't count it twice
+        assert!(claim("192.168.1.10", 2).is_empty());
+        assert_eq!(claim("192.168.1.12", 3), [SecurityAlert::ManyAddresses]);
+        assert!(claim("192.168.1.13", 4).is_empty());
+
+        // The window after the alert has passed, and older claims have expired
+        assert!(claim("192.168.1.14", 63).is_empty());
+        assert_eq!(claim("192.168.1.15", 64), [SecurityAlert::ManyAddresses]);
+    }
+
+    #[test]
+    fn expired_claims_and_alerts_are_forgotten() {
+        let config = SecurityConfig { max_addresses_per_mac: 2, ..SecurityConfig::default() };
+        let mut detector = SecurityDetector::new(config);
+        let start = Instant::now();
+        let claim = |detector: &mut SecurityDetector, ip: &str, mac: &str, secs: u64| {
+            detector.check(&device(ip, mac), true, &HashMap::new(), start + Duration::from_secs(secs), Local::now());
+        };
+        claim(&mut detector, "192.168.1.10", "02:00:00:00:00:01", 0);
+        claim(&mut detector, "192.168.1.11", "02:00:00:00:00:01", 1);
+        claim(&mut detector, "192.168.1.12", "02:00:00:00:00:02", 30);
+        assert_eq!(detector.claims.len(), 2);
+        assert_eq!(detector.last_many_addresses_alert.len(), 1);
+
+        // Only the second device still has a claim within the window
+        claim(&mut detector, "192.168.1.13", "02:00:00:00:00:03", 62);
+        let mut keys: Vec<&String> = detector.claims.keys().collect();
+        keys.sort();
+        assert_eq!(keys, [&device("192.168.1.12", "02:00:00:00:00:02").key(), &device("192.168.1.13", "02:00:00:00:00:03").key()]);
+        assert!(detector.last_many_addresses_alert.is_empty());
+    }
+
+    #[test]
+    fn ipv6_addresses_count_once_per_64() {
+        let config = SecurityConfig { max_addresses_per_mac: 2, ..SecurityConfig::default() };
+        let mut detector = SecurityDetector::new(config);
+        let now = Instant::now();
+        let mut claim = |ip: &str| alerts(&detector.check(&device(ip, "02:00:00:00:00:01"), true, &HashMap::new(), now, Local::now()));
+
+        assert!(claim("2001:db8:1:2::10").is_empty());
+        assert!(claim("2001:db8:1:2:abcd::1").is_empty());
+        assert!(claim("2001:db8:1:2:abcd::2").is_empty());
+        assert_eq!(claim("2001:db8:1:3::10"), [SecurityAlert::ManyAddresses]);
+    }
+
+    #[test]
+    fn disabled_detector_reports_nothing() {
+        let mut detector = SecurityDetector::new(SecurityConfig { enabled: false, max_addresses_per_mac: 1, ..SecurityConfig::default() });
+        detector.set_gateways(vec![gateway()]);
+        let devices = holding(vec![device("192.168.1.10", "02:00:00:00:00:01")]);
+        assert!(detector.check(&device("192.168.1.10", "02:00:00:00:00:66"), true, &devices, Instant::now(), Local::now()).is_empty());
+    }
+
+    #[test]
+    fn ipv6_route_hex_parses() {
+        assert_eq!(parse_ipv6_hex("fe800000000000000000000000000001"), Some("fe80::1".parse().unwrap()));
+        assert_eq!(parse_ipv6_hex("fe80::1"), None);
+    }
+}
@@ -0,0 +1,30 @@
+// Event sinks: destinations that tracker events are delivered to
+
+use crate::output::{format_event_as, OutputFormat};
+use crate::tracker::{Event, Tracker};
+
+pub trait EventSink: Send {
+    /// Deliver a single event
+    fn emit(&mut self, event: &Event);
+
+    /// Called once after every scan or batch of notifications, with the tracker
+    /// state the events were produced from
+    fn flush(&mut self, _tracker: &Tracker) {}
+}
+
+/// Prints events to stdout in the selected format
+pub struct StdoutSink {
+    format: OutputFormat,
+}
+
+impl StdoutSink {
+    pub fn new(format: OutputFormat) -> Self {
+        StdoutSink { format }
+    }
+}
+
+impl EventSink for StdoutSink {
+    fn emit(&mut self, event: &Event) {
+        println!("{}", format_event_as(self.format, event));
+    }
+}
@@ -0,0 +1,550 @@
+// Pluggable neighbor sources. Each scan returns a full snapshot of the devices
+// the source currently sees; a MergedSource combines several of them.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::net::IpAddr;
+use std::process::Command;
+
+use crate::device::{Device, MacAddr};
+use crate::parse::{self, parse_arp_entries, parse_ip_neigh_entries, Skipped};
+use crate::{capture, leases, mdns, netlink, sweep};
+
+// Distinct unusable lines the command source reports before it goes quiet
+const MAX_REPORTED_LINES: usize = 100;
+
+/// Source names from most to least trusted when sources disagree about a
+/// hostname or client identifier. DHCP traffic and mDNS carry what the device
+/// says about itself, lease files what its DHCP server recorded.
+pub const DEFAULT_PRECEDENCE: [&str; 7] = [
+    capture::SOURCE,
+    sweep::SOURCE,
+    mdns::SOURCE,
+    leases::SOURCE,
+    netlink::SOURCE,
+    parse::IP_NEIGH_SOURCE,
+    parse::ARP_SOURCE,
+];
+
+/// Something that can report the current set of neighbors
+pub trait NeighborSource: Send {
+    /// Short name used in diagnostics, and for sightings that don't name their source
+    fn name(&self) -> &str;
+
+    /// Take a full snapshot of the devices this source currently sees
+    fn scan(&mut self) -> Result<Vec<Device>, Box<dyn Error>>;
+
+    /// Add what this source knows to the merged snapshot of every source, e.g.
+    /// details a device announced about itself to its entries from other sources
+    fn annotate(&self, _devices: &mut [Device]) {}
+
+    /// Whether this source's sightings only add details (hostname, client
+    /// identifier, services) to devices other sources see, without vouching
+    /// for the devices themselves
+    fn details_only(&self) -> bool {
+        false
+    }
+
+    /// Scan only this interface from now on, or every interface. Sources that
+    /// can't change it while running keep the one they were started with.
+    fn set_interface(&mut self, _interface: Option<&str>) {}
+
+    /// Devices heard since the last call, for sources that hear devices as they
+    /// talk rather than when scanned, so they can be applied between scans.
+    /// The next scan still includes them.
+    fn take_heard(&mut self) -> Vec<Device> {
+        Vec::new()
+    }
+
+    /// Neighbor entries skipped by the scans since the last call, by reason, with
+    /// the unusable lines not reported before
+    fn take_skipped(&mut self) -> Skipped {
+        Skipped::default()
+    }
+}
+
+/// Sources whose neighbor state is the kernel's own, most direct first. Their
+/// state always wins over what other sources claim, so presence follows the
+/// kernel wherever it has an entry.
+pub const KERNEL_SOURCES: [&str; 3] = [netlink::SOURCE, parse::IP_NEIGH_SOURCE, parse::ARP_SOURCE];
+
+// One merged device, with the precedence rank of the source each contested value came from
+struct Record {
+    device: Device,
+    state_rank: usize,
+    hostname_rank: usize,
+    client_id_rank: usize,
+}
+
+/// Combines sightings from any number of sources into one record per address,
+/// MAC and interface, remembering every source that saw it. The neighbor state
+/// comes from the kernel table when it lists the device (see `KERNEL_SOURCES`).
+/// Where sources disagree about the hostname or client identifier, the source
+/// earliest in the precedence list wins; unlisted sources rank after listed
+/// ones, and ties go to the earlier sighting. Services are combined.
+pub struct SightingMerge {
+    precedence: Vec<String>,
+    records: Vec<Record>,
+    index: HashMap<(IpAddr, MacAddr, String), usize>,
+}
+
+impl SightingMerge {
+    pub fn new(precedence: &[String]) -> Self {
+        SightingMerge { precedence: precedence.to_vec(), records: Vec::new(), index: HashMap::new() }
+    }
+
+    pub fn add(&mut self, sightings: Vec<Device>) {
+        for device in sightings {
+            let rank = source_rank(&device, &self.precedence);
+            let state_rank = source_rank(&device, &KERNEL_SOURCES);
+            let key = (device.ip_address, device.mac_address, device.interface.clone());
+            let Some(&i) = self.index.get(&key) else {
+                self.index.insert(key, self.records.len());
+                self.records.push(Record {
+                    state_rank,
+                    hostname_rank: if device.hostname.is_some() { rank } else { usize::MAX },
+                    client_id_rank: if device.client_id.is_some() { rank } else { usize::MAX },
+                    device,
+                });
+                continue;
+            };
+
+            let record = &mut self.records[i];
+            if state_rank < record.state_rank {
+                record.device.state = device.state;
+                record.state_rank = state_rank;
+            }
+            record.add_details(device, rank);
+        }
+    }
+
+    /// Add the hostname, client identifier, services and source of sightings
+    /// whose device is already merged, leaving its state alone and skipping
+    /// sightings of devices no other source saw
+    pub fn add_details(&mut self, sightings: Vec<Device>) {
+        for device in sightings {
+            let rank = source_rank(&device, &self.precedence);
+            let key = (device.ip_address, device.mac_address, device.interface.clone());
+            if let Some(&i) = self.index.get(&key) {
+                self.records[i].add_details(device, rank);
+            }
+        }
+    }
+
+    /// The merged devices, in the order they were first sighted
+    pub fn into_devices(self) -> Vec<Device> {
+        self.records.into_iter().map(|record| record.device).collect()
+    }
+}
+
+impl Record {
+    // Take a sighting's hostname and client identifier where its source ranks
+    // better, and add its services and sources
+    fn add_details(&mut self, device: Device, rank: usize) {
+        if device.hostname.is_some() && rank < self.hostname_rank {
+            self.device.hostname = device.hostname.clone();
+            self.hostname_rank = rank;
+        }
+        if device.client_id.is_some() && rank < self.client_id_rank {
+            self.device.client_id = device.client_id.clone();
+            self.client_id_rank = rank;
+        }
+        let details = Device { hostname: None, client_id: None, ..device };
+        self.device.inherit_details(&details);
+    }
+}
+
+// Rank of a sighting's best placed source in `order`, lower wins; unlisted
+// sources rank last
+fn source_rank<S: AsRef<str>>(device: &Device, order: &[S]) -> usize {
+    device
+        .sources
+        .iter()
+        .filter_map(|source| order.iter().position(|name| name.as_ref() == source))
+        .min()
+        .unwrap_or(order.len())
+}
+
+/// Scans several sources and merges their snapshots with a `SightingMerge`.
+/// A scan fails if any source fails, so a missing snapshot doesn't look like
+/// its devices left.
+pub struct MergedSource {
+    sources: Vec<Box<dyn NeighborSource>>,
+    precedence: Vec<String>,
+    name: String,
+}
+
+impl MergedSource {
+    pub fn new(sources: Vec<Box<dyn NeighborSource>>, precedence: Vec<String>) -> Self {
+        let name = sources.iter().map(|source| source.name()).collect::<Vec<_>>().join("+");
+        MergedSource { sources, precedence, name }
+    }
+
+    pub fn set_precedence(&mut self, precedence: Vec<String>) {
+        self.precedence = precedence;
+    }
+}
+
+impl NeighborSource for MergedSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn scan(&mut self) -> Result<Vec<Device>, Box<dyn Error>> {
+        let mut merge = SightingMerge::new(&self.precedence);
+        let mut details = Vec::new();
+        for source in self.sources.iter_mut() {
+            let mut devices = source.scan().map_err(|e| format!("{}: {}", source.name(), e))?;
+            for device in devices.iter_mut().filter(|device| device.sources.is_empty()) {
+                device.add_sources(&[source.name().to_string()]);
+            }
+            match source.details_only() {
+                true => details.push(devices),
+                false => merge.add(devices),
+            }
+        }
+        // Only once every device is in, so the order of the sources doesn't matter
+        for devices in details {
+            merge.add_details(devices);
+        }
+
+        let mut devices = merge.into_devices();
+        for source in &self.sources {
+            source.annotate(&mut devices);
+        }
+        Ok(devices)
+    }
+
+    fn annotate(&self, devices: &mut [Device]) {
+        for source in &self.sources {
+            source.annotate(devices);
+        }
+    }
+
+    fn set_interface(&mut self, interface: Option<&str>) {
+        for source in self.sources.iter_mut() {
+            source.set_interface(interface);
+        }
+    }
+
+    fn take_heard(&mut self) -> Vec<Device> {
+        let mut heard = Vec::new();
+        for source in self.sources.iter_mut() {
+            let mut devices = source.take_heard();
+            for device in devices.iter_mut().filter(|device| device.sources.is_empty()) {
+                device.add_sources(&[source.name().to_string()]);
+            }
+            heard.extend(devices);
+        }
+        self.annotate(&mut heard);
+        heard
+    }
+
+    fn take_skipped(&mut self) -> Skipped {
+        let mut skipped = Skipped::default();
+        for source in self.sources.iter_mut() {
+            skipped.append(source.take_skipped());
+        }
+        skipped
+    }
+}
+
+/// Reads the kernel neighbor table over rtnetlink
+pub struct NetlinkSource {
+    interface: Option<String>,
+    skipped: Skipped,
+}
+
+impl NetlinkSource {
+    pub fn new(interface: Option<&str>) -> Self {
+        NetlinkSource { interface: interface.map(str::to_string), skipped: Skipped::default() }
+    }
+}
+
+impl NeighborSource for NetlinkSource {
+    fn name(&self) -> &str {
+        netlink::SOURCE
+    }
+
+    fn scan(&mut self) -> Result<Vec<Device>, Box<dyn Error>> {
+        let (devices, skipped) = netlink::dump_neighbors(self.interface.as_deref())?;
+        self.skipped.add(&skipped);
+        Ok(devices)
+    }
+
+    fn set_interface(&mut self, interface: Option<&str>) {
+        self.interface = interface.map(str::to_string);
+    }
+
+    fn take_skipped(&mut self) -> Skipped {
+        std::mem::take(&mut self.skipped)
+    }
+}
+
+/// Scrapes the output of the `arp` and `ip` commands, merging the two with
+/// `ip neigh`, which reports the neighbor state, taking precedence
+pub struct CommandSource {
+    interface: Option<String>,
+    skipped: Skipped,
+    // Unusable lines already reported, so each is reported once
+    reported: HashSet<String>,
+}
+
+impl CommandSource {
+    pub fn new(interface: Option<&str>) -> Self {
+        CommandSource { interface: interface.map(str::to_string), skipped: Skipped::default(), reported: HashSet::new() }
+    }
+
+    // Count the skipped lines, keeping the unusable ones not reported yet
+    fn record_skipped(&mut self, command: &str, skipped: Skipped) {
+        self.skipped.add(&skipped);
+        for line in skipped.lines {
+            if self.reported.len() < MAX_REPORTED_LINES && self.reported.insert(line.clone()) {
+                self.skipped.lines.push(format!("`{}` line: {}", command, line));
+            }
+        }
+    }
+}
+
+impl NeighborSource for CommandSource {
+    fn name(&self) -> &str {
+        "commands"
+    }
+
+    fn scan(&mut self) -> Result<Vec<Device>, Box<dyn Error>> {
+        let interface = self.interface.clone();
+        let interface = interface.as_deref();
+        let mut neigh_devices = Vec::new();
+        let mut arp_devices = Vec::new();
+
+        // Execute both commands in a single shell to reduce process overhead
+        let script = "arp -a -n; echo '===SPLIT==='; ip neigh show";
+        let output = Command::new("sh")
+            .args(["-c", script])
+            .output()?;
+
+        if output.status.success() {
+            let content = String::from_utf8_lossy(&output.stdout);
+            let parts: Vec<&str> = content.split("===SPLIT===").collect();
+
+            if parts.len() >= 2 {
+                let skipped = parse_ip_neigh_entries(parts[1], &mut neigh_devices, interface)?;
+                self.record_skipped("ip neigh", skipped);
+                let skipped = parse_arp_entries(parts[0], &mut arp_devices, interface)?;
+                self.record_skipped("arp", skipped);
+            }
+        }
+
+        let mut merge = SightingMerge::new(&[parse::IP_NEIGH_SOURCE.to_string(), parse::ARP_SOURCE.to_string()]);
+        merge.add(neigh_devices);
+        merge.add(arp_devices);
+        Ok(merge.into_devices())
+    }
+
+    fn set_interface(&mut self, interface: Option<&str>) {
+        self.interface = interface.map(str::to_string);
+    }
+
+    fn take_skipped(&mut self) -> Skipped {
+        std::mem::take(&mut self.skipped)
+    }
+}
+
+/// Kernel neighbor table via netlink, falling back to the shell tools when
+/// netlink is unavailable (e.g. restricted sandbox)
+pub struct KernelSource {
+    netlink: NetlinkSource,
+    commands: CommandSource,
+}
+
+impl KernelSource {
+    pub fn new(interface: Option<&str>) -> Self {
+        KernelSource {
+            netlink: NetlinkSource::new(interface),
+            commands: CommandSource::new(interface),
+        }
+    }
+}
+
+impl NeighborSource for KernelSource {
+    fn name(&self) -> &str {
+        "kernel"
+    }
+
+    fn scan(&mut self) -> Result<Vec<Device>, Box<dyn Error>> {
+        match self.netlink.scan() {
+            Ok(devices) => Ok(devices),
+            Err(_) => self.commands.scan(),
+        }
+    }
+
+    fn set_interface(&mut self, interface: Option<&str>) {
+        self.netlink.set_interface(interface);
+        self.commands.set_interface(interface);
+    }
+
+    fn take_skipped(&mut self) -> Skipped {
+        let mut skipped = self.netlink.take_skipped();
+        skipped.append(self.commands.take_skipped());
+        skipped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::NeighState;
+
+    fn sighting(source: &str, state: NeighState) -> Device {
+        Device::new("192.168.1.10".parse().unwrap(), "02:00:00:00:00:10".parse().unwrap(), "eth0".to_string())
+            .with_state(state)
+            .seen_by(source)
+    }
+
+    fn named(source: &str, hostname: &str) -> Device {
+        Device { hostname: Some(hostname.to_string()), ..sighting(source, NeighState::Reachable) }
+    }
+
+    fn precedence() -> Vec<String> {
+        DEFAULT_PRECEDENCE.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn merged(batches: Vec<Vec<Device>>) -> Vec<Device> {
+        let mut merge = SightingMerge::new(&precedence());
+        for batch in batches {
+            merge.add(batch);
+        }
+        merge.into_devices()
+    }
+
+    #[test]
+    fn sightings_of_one_binding_merge() {
+        let mut printer = sighting(mdns::SOURCE, NeighState::Reachable);
+        printer.services = vec!["_ipp._tcp".to_string()];
+        let other_interface = Device { interface: "wlan0".to_string(), ..sighting(capture::SOURCE, NeighState::Reachable) };
+
+        let devices = merged(vec![vec![sighting(netlink::SOURCE, NeighState::Stale), other_interface], vec![printer]]);
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].sources, [mdns::SOURCE, netlink::SOURCE]);
+        assert_eq!(devices[0].services, ["_ipp._tcp"]);
+        assert_eq!(devices[1].interface, "wlan0");
+    }
+
+    #[test]
+    fn kernel_state_wins_in_either_order() {
+        for batches in [
+            vec![vec![sighting(capture::SOURCE, NeighState::Reachable)], vec![sighting(netlink::SOURCE, NeighState::Stale)]],
+            vec![vec![sighting(netlink::SOURCE, NeighState::Stale)], vec![sighting(capture::SOURCE, NeighState::Reachable)]],
+        ] {
+            assert_eq!(merged(batches)[0].state, NeighState::Stale);
+        }
+        // Between kernel sources the most direct one wins
+        let devices = merged(vec![vec![sighting(parse::ARP_SOURCE, NeighState::Unknown)], vec![sighting(netlink::SOURCE, NeighState::Delay)]]);
+        assert_eq!(devices[0].state, NeighState::Delay);
+        // Without a kernel source the first sighting's state stays
+        let devices = merged(vec![vec![sighting(mdns::SOURCE, NeighState::Reachable)], vec![sighting(leases::SOURCE, NeighState::Unknown)]]);
+        assert_eq!(devices[0].state, NeighState::Reachable);
+    }
+
+    #[test]
+    fn hostname_follows_precedence_in_either_order() {
+        for batches in [
+            vec![vec![named(leases::SOURCE, "from-lease")], vec![named(capture::SOURCE, "from-dhcp")]],
+            vec![vec![named(capture::SOURCE, "from-dhcp")], vec![named(leases::SOURCE, "from-lease")]],
+        ] {
+            assert_eq!(merged(batches)[0].hostname.as_deref(), Some("from-dhcp"));
+        }
+    }
+
+    #[test]
+    fn hostname_ties_go_to_the_earlier_sighting() {
+        let devices = merged(vec![vec![named(mdns::SOURCE, "first")], vec![named(mdns::SOURCE, "second")]]);
+        assert_eq!(devices[0].hostname.as_deref(), Some("first"));
+
+        // Unlisted sources tie with each other and rank after listed ones
+        let devices = merged(vec![vec![named("plugin-a", "a")], vec![named("plugin-b", "b")]]);
+        assert_eq!(devices[0].hostname.as_deref(), Some("a"));
+        let devices = merged(vec![vec![named("plugin-a", "a")], vec![named(parse::ARP_SOURCE, "arp")]]);
+        assert_eq!(devices[0].hostname.as_deref(), Some("arp"));
+    }
+
+    #[test]
+    fn missing_details_never_replace_known_ones() {
+        let with_id = Device { client_id: Some("01:02".to_string()), ..named(leases::SOURCE, "from-lease") };
+        let devices = merged(vec![vec![with_id], vec![sighting(capture::SOURCE, NeighState::Reachable)]]);
+        assert_eq!(devices[0].hostname.as_deref(), Some("from-lease"));
+        assert_eq!(devices[0].client_id.as_deref(), Some("01:02"));
+    }
+
+    #[test]
+    fn custom_precedence_reorders_sources() {
+        let mut merge = SightingMerge::new(&[leases::SOURCE.to_string(), capture::SOURCE.to_string()]);
+        merge.add(vec![named(capture::SOURCE, "from-dhcp")]);
+        merge.add(vec![named(leases::SOURCE, "from-lease")]);
+        assert_eq!(merge.into_devices()[0].hostname.as_deref(), Some("from-lease"));
+    }
+
+    #[test]
+    fn details_only_annotate_devices_already_seen() {
+        let mut merge = SightingMerge::new(&precedence());
+        merge.add(vec![named(netlink::SOURCE, "from-hosts")]);
+        let elsewhere = Device { ip_address: "192.168.1.99".parse().unwrap(), ..named(leases::SOURCE, "stale-lease") };
+        merge.add_details(vec![Device { state: NeighState::Unknown, ..named(leases::SOURCE, "from-lease") }, elsewhere]);
+
+        let devices = merge.into_devices();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].state, NeighState::Reachable);
+        assert_eq!(devices[0].hostname.as_deref(), Some("from-lease"));
+        assert_eq!(devices[0].sources, [leases::SOURCE, netlink::SOURCE]);
+    }
+
+    struct Stub {
+        name: &'static str,
+        devices: Result<Vec<Device>, &'static str>,
+        details_only: bool,
+    }
+
+    impl NeighborSource for Stub {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn scan(&mut self) -> Result<Vec<Device>, Box<dyn Error>> {
+            self.devices.clone().map_err(Into::into)
+        }
+
+        fn details_only(&self) -> bool {
+            self.details_only
+        }
+    }
+
+    #[test]
+    fn merged_source_names_unnamed_sightings_and_applies_details_last() {
+        let unnamed = Device { sources: Vec::new(), ..sighting("", NeighState::Reachable) };
+        let mut source = MergedSource::new(
+            vec![
+                Box::new(Stub { name: leases::SOURCE, devices: Ok(vec![named(leases::SOURCE, "from-lease")]), details_only: true }),
+                Box::new(Stub { name: "plugin", devices: Ok(vec![unnamed]), details_only: false }),
+            ],
+            precedence(),
+        );
+        assert_eq!(source.name(), "leases+plugin");
+
+        let devices = source.scan().unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].sources, [leases::SOURCE, "plugin"]);
+        assert_eq!(devices[0].hostname.as_deref(), Some("from-lease"));
+    }
+
+    #[test]
+    fn merged_source_fails_with_any_source() {
+        let mut source = MergedSource::new(
+            vec![
+                Box::new(Stub { name: netlink::SOURCE, devices: Ok(vec![sighting(netlink::SOURCE, NeighState::Reachable)]), details_only: false }),
+                Box::new(Stub { name: "plugin", devices: Err("no permission"), details_only: false }),
+            ],
+            precedence(),
+        );
+        assert_eq!(source.scan().unwrap_err().to_string(), "plugin: no permission");
+    }
+}
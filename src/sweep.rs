@@ -0,0 +1,204 @@
+// Active ARP sweep: sends who-has requests across each monitored interface's
+// IPv4 subnets so devices that never talked to this host are discovered too
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::device::{Device, MacAddr, NeighState};
+use crate::diag;
+use crate::netlink::interface_name;
+use crate::packet::{self, ArpPacket, PacketSocket, ARP_REPLY, BROADCAST_MAC, ETH_P_ARP};
+use crate::source::NeighborSource;
+
+/// Source name of devices that answered an ARP sweep
+pub const SOURCE: &str = "arp-sweep";
+
+#[derive(Debug, Clone)]
+pub struct SweepConfig {
+    /// Only sweep this interface; every interface with IPv4 neighbors otherwise
+    pub interface: Option<String>,
+    /// Pause between the end of one sweep and the start of the next
+    pub interval: Duration,
+    /// ARP requests sent per second
+    pub rate: u32,
+    /// Subnets with more host addresses than this are skipped
+    pub max_hosts: u32,
+}
+
+impl Default for SweepConfig {
+    fn default() -> Self {
+        SweepConfig {
+            interface: None,
+            interval: Duration::from_secs(300),
+            rate: 50,
+            max_hosts: 4096,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct SweepState {
+    // Latest reply per (ip, interface) with the sweep it arrived during
+    replies: HashMap<(IpAddr, String), (Device, u64)>,
+    // Number of the sweep in progress, or of the last one started
+    current: u64,
+    finished: u64,
+}
+
+/// Background ARP sweeper. Devices count as present while they answered the
+/// last finished sweep or the one in progress.
+pub struct ArpSweep {
+    state: Arc<Mutex<SweepState>>,
+}
+
+impl ArpSweep {
+    /// Open a packet socket and start sweeping and collecting replies on background threads
+    pub fn start(config: SweepConfig) -> io::Result<Self> {
+        let socket = Arc::new(PacketSocket::open(ETH_P_ARP)?);
+        let state = Arc::new(Mutex::new(SweepState::default()));
+
+        let (receive_socket, receive_state) = (Arc::clone(&socket), Arc::clone(&state));
+        let interface_filter = config.interface.clone();
+        thread::spawn(move || receive_replies(&receive_socket, &receive_state, interface_filter.as_deref()));
+
+        let send_state = Arc::clone(&state);
+        thread::spawn(move || {
+            let mut warned = HashSet::new();
+            loop {
+                send_state.lock().unwrap().current += 1;
+                sweep(&socket, &config, &mut warned);
+                {
+                    let mut state = send_state.lock().unwrap();
+                    state.finished = state.current;
+                }
+                thread::sleep(config.interval);
+            }
+        });
+
+        Ok(ArpSweep { state })
+    }
+
+    /// Devices that answered recently enough to count as present
+    pub fn devices(&self) -> Vec<Device> {
+        let mut state = self.state.lock().unwrap();
+        let finished = state.finished;
+        state.replies.retain(|_, (_, sweep)| *sweep >= finished);
+        state.replies.values().map(|(device, _)| device.clone()).collect()
+    }
+}
+
+// One pass over every host address of every eligible subnet
+fn sweep(socket: &PacketSocket, config: &SweepConfig, warned: &mut HashSet<(String, Ipv4Addr, u8)>) {
+    let interfaces = match packet::local_interfaces() {
+        Ok(interfaces) => interfaces,
+        Err(e) => {
+            diag::warn(&format!("ARP sweep: cannot list interfaces: {}", e));
+            return;
+        }
+    };
+    let spacing = Duration::from_secs(1) / config.rate.max(1);
+
+    for interface in interfaces {
+        if !interface.has_neighbors() || config.interface.as_ref().is_some_and(|name| *name != interface.name) {
+            continue;
+        }
+        let Some(mac) = interface.mac else {
+            continue;
+        };
+
+        for &(local_ip, prefix) in &interface.ipv4 {
+            // Point-to-point /31 and host /32 routes have nobody to sweep
+            if prefix >= 31 {
+                continue;
+            }
+            let hosts = (1u64 << (32 - prefix)) - 2;
+            if hosts > config.max_hosts as u64 {
+                if warned.insert((interface.name.clone(), local_ip, prefix)) {
+                    diag::warn(&format!("ARP sweep: skipping {}/{} on {} ({} hosts is more than {})",
+                                        local_ip, prefix, interface.name, hosts, config.max_hosts));
+                }
+                continue;
+            }
+
+            let network = u32::from(local_ip) & (u32::MAX << (32 - prefix));
+            for host in 1..=hosts as u32 {
+                let target = Ipv4Addr::from(network + host);
+                if target == local_ip {
+                    continue;
+                }
+                let frame = ArpPacket::request_frame(mac, local_ip, target, BROADCAST_MAC);
+                if let Err(e) = socket.send(interface.index, &frame) {
+                    diag::warn(&format!("ARP sweep: send on {} failed: {}", interface.name, e));
+                    break;
+                }
+                thread::sleep(spacing);
+            }
+        }
+    }
+}
+
+fn receive_replies(socket: &PacketSocket, state: &Mutex<SweepState>, interface_filter: Option<&str>) {
+    let mut buf = [0u8; 2048];
+    let mut interface_names = HashMap::new();
+
+    loop {
+        let (len, received_on) = match socket.recv(&mut buf) {
+            Ok(received) => received,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                diag::warn(&format!("ARP sweep: receive failed: {}", e));
+                return;
+            }
+        };
+        if received_on.outgoing {
+            continue;
+        }
+
+        let Some((ETH_P_ARP, payload)) = packet::parse_ethernet(&buf[..len]) else {
+            continue;
+        };
+        let Some(arp) = ArpPacket::parse(payload) else {
+            continue;
+        };
+        if arp.operation != ARP_REPLY || arp.sender_ip.is_unspecified() {
+            continue;
+        }
+        let Some(interface) = interface_name(received_on.ifindex as u32, &mut interface_names) else {
+            continue;
+        };
+        if interface_filter.is_some_and(|filter| filter != interface) {
+            continue;
+        }
+
+        let device = Device::new(IpAddr::V4(arp.sender_ip), MacAddr(arp.sender_mac), interface.clone()).with_state(NeighState::Reachable).seen_by(SOURCE);
+        let mut state = state.lock().unwrap();
+        let current = state.current;
+        state.replies.insert((device.ip_address, interface), (device, current));
+    }
+}
+
+/// Devices that answered the latest ARP sweeps
+pub struct SweepSource {
+    sweep: ArpSweep,
+}
+
+impl SweepSource {
+    pub fn new(sweep: ArpSweep) -> Self {
+        SweepSource { sweep }
+    }
+}
+
+impl NeighborSource for SweepSource {
+    fn name(&self) -> &str {
+        SOURCE
+    }
+
+    fn scan(&mut self) -> Result<Vec<Device>, Box<dyn Error>> {
+        Ok(self.sweep.devices())
+    }
+}
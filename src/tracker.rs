@@ -0,0 +1,915 @@
+// Device tracker: turns neighbor sightings into connect/disconnect events
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+
+use crate::device::{self, AddressFamily, Device, NeighState};
+use crate::hostname::Hostnames;
+use crate::inventory::{Inventory, InventoryEntry};
+use crate::parse::Skipped;
+use crate::probe::ProbeConfig;
+use crate::security::{Gateway, SecurityAlert, SecurityConfig, SecurityDetector};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EventKind {
+    Connected,
+    Disconnected,
+    /// A known device (same MAC on the same interface) moved to a new address
+    IpChanged,
+    /// Suspicious binding such as an IP claimed by several MACs (ARP spoofing)
+    Security,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Connected => "CONNECTED",
+            EventKind::Disconnected => "DISCONNECTED",
+            EventKind::IpChanged => "IP_CHANGED",
+            EventKind::Security => "SECURITY",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "CONNECTED" => Some(EventKind::Connected),
+            "DISCONNECTED" => Some(EventKind::Disconnected),
+            "IP_CHANGED" => Some(EventKind::IpChanged),
+            "SECURITY" => Some(EventKind::Security),
+            _ => None,
+        }
+    }
+}
+
+/// A change in the set of tracked devices
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub kind: EventKind,
+    pub device: Device,
+    /// The binding this event replaces, e.g. the old address for IP_CHANGED
+    /// or the old MAC for a SECURITY alert
+    pub previous: Option<Device>,
+    /// What a SECURITY event is about
+    pub alert: Option<SecurityAlert>,
+    /// The device's entry in the known-device inventory
+    pub inventory: Option<InventoryEntry>,
+    /// CONNECTED event for a MAC missing from the inventory (with `alert_unknown`)
+    pub unknown: bool,
+    /// Name of the device from the hosts file, DHCP leases or reverse DNS
+    pub hostname: Option<String>,
+    pub timestamp: DateTime<Local>,
+}
+
+impl Event {
+    pub fn new(kind: EventKind, device: Device, timestamp: DateTime<Local>) -> Self {
+        Event { kind, device, previous: None, alert: None, inventory: None, unknown: false, hostname: None, timestamp }
+    }
+}
+
+/// A device identified by its MAC address on one interface
+#[derive(Debug, Clone)]
+pub struct TrackedDevice {
+    /// Most recent sighting of the device's primary address
+    pub device: Device,
+    /// Every address the device currently holds, with the time it was last seen present
+    pub addresses: BTreeMap<IpAddr, Instant>,
+    pub first_seen: Instant,
+    pub last_seen: Instant,
+    // Liveness probing of addresses that await confirmation
+    probes: BTreeMap<IpAddr, Probe>,
+}
+
+impl TrackedDevice {
+    fn new(device: Device, now: Instant) -> Self {
+        TrackedDevice {
+            addresses: BTreeMap::from([(device.ip_address, now)]),
+            device,
+            first_seen: now,
+            last_seen: now,
+            probes: BTreeMap::new(),
+        }
+    }
+
+    // Make the most recently seen of the most preferred remaining addresses the primary one
+    fn repick_primary(&mut self) {
+        if let Some((ip, _)) = self.addresses.iter().max_by_key(|(ip, seen)| (Reverse(primary_rank(ip)), **seen)) {
+            self.device.ip_address = *ip;
+        }
+    }
+}
+
+// Probes sent to one address since it was last confirmed present
+#[derive(Debug, Clone, Default)]
+struct Probe {
+    // The neighbor entry left a present state (e.g. went STALE) or was deleted
+    unconfirmed: bool,
+    sent: u32,
+    last_sent: Option<Instant>,
+}
+
+impl Probe {
+    // Every probe was sent and the last one had its interval to be answered
+    fn unanswered(&self, config: &ProbeConfig, now: Instant) -> bool {
+        self.sent >= config.count && self.last_sent.is_some_and(|sent| now.duration_since(sent) >= config.interval)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MonitoringStats {
+    pub total_devices_seen: usize,
+    pub peak_concurrent_devices: usize,
+    pub start_time: Instant,
+    /// Neighbor entries the scans skipped, by reason
+    pub skipped: Skipped,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackerConfig {
+    /// Device considered disconnected after not being seen present for this long
+    pub disconnect_timeout: Duration,
+    /// Neighbor states that count as the device being present
+    pub present_states: Vec<NeighState>,
+    /// How long a STALE entry still counts as present for an address the device
+    /// already holds, from when the entry was first seen STALE. Entries in
+    /// `present_states` count however long they have been STALE.
+    pub stale_timeout: Duration,
+    /// Spoofing and MAC conflict detection
+    pub security: SecurityConfig,
+    /// Flag CONNECTED events for MACs missing from the inventory
+    pub alert_unknown: bool,
+    /// Probe addresses before dropping them instead of going by the timeout
+    /// alone. Only set this if the probes from `due_probes` are actually sent.
+    pub probe: Option<ProbeConfig>,
+    /// Address families whose neighbors are tracked
+    pub family: AddressFamily,
+    /// Track link-local addresses (169.254.0.0/16 and fe80::/10)
+    pub link_local: bool,
+    /// Only track neighbors on this interface; every interface otherwise
+    pub interface: Option<String>,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        TrackerConfig {
+            disconnect_timeout: Duration::from_secs(10),
+            present_states: NeighState::DEFAULT_PRESENT.to_vec(),
+            stale_timeout: Duration::from_secs(60),
+            security: SecurityConfig::default(),
+            alert_unknown: false,
+            probe: None,
+            family: AddressFamily::All,
+            link_local: true,
+            interface: None,
+        }
+    }
+}
+
+pub struct Tracker {
+    config: TrackerConfig,
+    devices: HashMap<String, TrackedDevice>,
+    stats: MonitoringStats,
+    security: SecurityDetector,
+    inventory: Inventory,
+    // Shared so lookups can run without holding the tracker
+    hostnames: Arc<Hostnames>,
+    // (device key, address) pairs whose probes went unanswered, so their
+    // lingering STALE entries don't reconnect them
+    unanswered: HashSet<(String, IpAddr)>,
+    // When the (device key, address) pairs listed as STALE were first seen so
+    stale_since: HashMap<(String, IpAddr), Instant>,
+    // Wall clock time matching `start_time`, used to timestamp events
+    start_wall: DateTime<Local>,
+}
+
+impl Tracker {
+    pub fn new(config: TrackerConfig) -> Self {
+        Tracker::with_origin(config, Instant::now(), Local::now())
+    }
+
+    /// Tracker whose clock starts at `start`, standing for wall clock time
+    /// `start_wall`, e.g. the first packet of a capture being replayed
+    pub fn with_origin(config: TrackerConfig, start: Instant, start_wall: DateTime<Local>) -> Self {
+        Tracker {
+            security: SecurityDetector::new(config.security.clone()),
+            config,
+            devices: HashMap::new(),
+            inventory: Inventory::default(),
+            hostnames: Arc::default(),
+            unanswered: HashSet::new(),
+            stale_since: HashMap::new(),
+            stats: MonitoringStats {
+                total_devices_seen: 0,
+                peak_concurrent_devices: 0,
+                start_time: start,
+                skipped: Skipped::default(),
+            },
+            start_wall,
+        }
+    }
+
+    pub fn config(&self) -> &TrackerConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> &MonitoringStats {
+        &self.stats
+    }
+
+    /// Count neighbor entries a scan skipped
+    pub fn record_skipped(&mut self, skipped: &Skipped) {
+        self.stats.skipped.add(skipped);
+    }
+
+    pub fn devices(&self) -> impl Iterator<Item = &TrackedDevice> {
+        self.devices.values()
+    }
+
+    /// Whether a sighting counts as the device being present. A STALE entry
+    /// for an address whose probes went unanswered doesn't; otherwise one for an
+    /// address the device holds does until it has been STALE for the stale timeout.
+    pub fn is_present(&self, device: &Device, now: Instant) -> bool {
+        let binding = (device.key(), device.ip_address);
+        match device.state {
+            NeighState::Stale if self.unanswered.contains(&binding) => false,
+            state if self.config.present_states.contains(&state) => true,
+            NeighState::Stale => {
+                let held = self
+                    .devices
+                    .get(&binding.0)
+                    .is_some_and(|tracked_device| tracked_device.addresses.contains_key(&device.ip_address));
+                held && self
+                    .stale_since
+                    .get(&binding)
+                    .is_none_or(|since| now.duration_since(*since) <= self.config.stale_timeout)
+            }
+            _ => false,
+        }
+    }
+
+    // Note when the sighting's entry went STALE, or forget it once it isn't
+    fn note_stale(&mut self, device: &Device, now: Instant) {
+        let binding = (device.key(), device.ip_address);
+        match device.state {
+            NeighState::Stale => {
+                self.stale_since.entry(binding).or_insert(now);
+            }
+            _ => {
+                self.stale_since.remove(&binding);
+            }
+        }
+    }
+
+    /// Whether a sighting's address is one the tracker follows, given the
+    /// configured interface, address families and link-local policy
+    pub fn tracks(&self, device: &Device) -> bool {
+        let ip = &device.ip_address;
+        self.config.interface.as_ref().is_none_or(|name| *name == device.interface)
+            && device::is_neighbor_address(ip)
+            && self.config.family.matches(ip)
+            && (self.config.link_local || !device::is_link_local(ip))
+    }
+
+    /// Change the settings of a running tracker, e.g. after the configuration
+    /// was reloaded. Tracked devices keep their state; addresses the new filters
+    /// exclude are forgotten without events, along with devices left without
+    /// any. Returns how many devices were forgotten.
+    pub fn set_config(&mut self, config: TrackerConfig) -> usize {
+        self.security.set_config(config.security.clone());
+        self.config = config;
+
+        let excluded: Vec<(String, IpAddr)> = self
+            .devices
+            .iter()
+            .flat_map(|(key, tracked_device)| {
+                let device = &tracked_device.device;
+                tracked_device
+                    .addresses
+                    .keys()
+                    .filter(|ip| !self.tracks(&Device::new(**ip, device.mac_address, device.interface.clone())))
+                    .map(|ip| (key.clone(), *ip))
+            })
+            .collect();
+
+        let before = self.devices.len();
+        for (key, ip) in excluded {
+            let Some(tracked_device) = self.devices.get_mut(&key) else {
+                continue;
+            };
+            tracked_device.addresses.remove(&ip);
+            tracked_device.probes.remove(&ip);
+            if tracked_device.addresses.is_empty() {
+                self.devices.remove(&key);
+            } else if tracked_device.device.ip_address == ip {
+                tracked_device.repick_primary();
+            }
+        }
+        before - self.devices.len()
+    }
+
+    pub fn inventory(&self) -> &Inventory {
+        &self.inventory
+    }
+
+    /// Known devices whose names, owners and tags are attached to events
+    pub fn set_inventory(&mut self, inventory: Inventory) {
+        self.inventory = inventory;
+    }
+
+    pub fn hostnames(&self) -> &Arc<Hostnames> {
+        &self.hostnames
+    }
+
+    /// Where hostnames attached to events come from
+    pub fn set_hostnames(&mut self, hostnames: Hostnames) {
+        self.hostnames = Arc::new(hostnames);
+    }
+
+    /// Default gateways whose MAC address is watched for changes
+    pub fn set_gateways(&mut self, gateways: Vec<Gateway>) {
+        self.security.set_gateways(gateways);
+    }
+
+    /// Apply a full snapshot of the neighbor table: refresh every listed device,
+    /// then drop addresses and disconnect devices not seen within the timeout period
+    pub fn update(&mut self, current_devices: Vec<Device>, now: Instant) -> Vec<Event> {
+        let mut events = Vec::new();
+        let current_devices: Vec<Device> = current_devices.into_iter().filter(|d| self.tracks(d)).collect();
+
+        // Addresses whose probes went unanswered, or that went STALE, are forgotten once their entry is gone
+        let listed: HashSet<(String, IpAddr)> = current_devices.iter().map(|d| (d.key(), d.ip_address)).collect();
+        self.unanswered.retain(|binding| listed.contains(binding));
+        self.stale_since.retain(|binding, _| listed.contains(binding));
+        for device in &current_devices {
+            self.note_stale(device, now);
+        }
+
+        // Present addresses per device in this snapshot, for O(1) lookup instead of O(n) vector search
+        let mut present_addresses: HashMap<String, HashSet<IpAddr>> = HashMap::new();
+        for device in current_devices.iter().filter(|d| self.is_present(d, now)) {
+            present_addresses.entry(device.key()).or_default().insert(device.ip_address);
+        }
+
+        // Process current devices - update last seen time. The snapshot is complete,
+        // so an address is still in use only if it is present in this snapshot.
+        let no_addresses = HashSet::new();
+        for device in current_devices {
+            let live = present_addresses.get(&device.key()).unwrap_or(&no_addresses);
+            events.extend(self.sight(device, now, |ip, _| live.contains(ip)));
+        }
+
+        // Check for disconnections - addresses not seen within timeout period are
+        // dropped (or, with probing, addresses that didn't answer their probes),
+        // and a device is disconnected once it has no address left
+        let timeout = self.config.disconnect_timeout;
+        let probe_config = self.config.probe.clone();
+        events.extend(self.drop_addresses(now, |key, ip, last_seen, probe| match &probe_config {
+            Some(config) => probe.is_some_and(|probe| probe.unanswered(config, now)),
+            None => {
+                now.duration_since(last_seen) > timeout
+                    && !present_addresses.get(key).is_some_and(|live| live.contains(ip))
+            }
+        }));
+
+        self.annotate(events)
+    }
+
+    /// Addresses that need a liveness probe now: those whose neighbor entry left
+    /// a present state, and those close enough to the disconnect timeout that the
+    /// probes would run out by then. Each returned device carries the address to
+    /// probe; answers are fed back as present sightings through `observe`.
+    pub fn due_probes(&mut self, now: Instant) -> Vec<Device> {
+        let Some(config) = self.config.probe.clone() else {
+            return Vec::new();
+        };
+        let start = self.config.disconnect_timeout.saturating_sub(config.window()).max(config.interval);
+
+        let mut due = Vec::new();
+        for TrackedDevice { device, addresses, probes, .. } in self.devices.values_mut() {
+            for (ip, last_seen) in addresses.iter() {
+                let unconfirmed = probes.get(ip).is_some_and(|probe| probe.unconfirmed);
+                if !unconfirmed && now.duration_since(*last_seen) < start {
+                    continue;
+                }
+
+                let probe = probes.entry(*ip).or_default();
+                if probe.sent >= config.count
+                    || probe.last_sent.is_some_and(|sent| now.duration_since(sent) < config.interval)
+                {
+                    continue;
+                }
+                probe.sent += 1;
+                probe.last_sent = Some(now);
+
+                let mut target = device.clone();
+                target.ip_address = *ip;
+                due.push(target);
+            }
+        }
+        due
+    }
+
+    /// Drop addresses whose probes all went unanswered, disconnecting devices left
+    /// without any. `update` does this for every snapshot; call it in between,
+    /// e.g. when following neighbor notifications.
+    pub fn expire_unanswered(&mut self, now: Instant) -> Vec<Event> {
+        let Some(config) = self.config.probe.clone() else {
+            return Vec::new();
+        };
+        let events = self.drop_addresses(now, |_, _, _, probe| probe.is_some_and(|probe| probe.unanswered(&config, now)));
+        self.annotate(events)
+    }
+
+    // Drop the addresses `expired(key, ip, last_seen, probe)` selects, then
+    // disconnect devices that have no address left
+    fn drop_addresses(
+        &mut self,
+        now: Instant,
+        expired: impl Fn(&str, &IpAddr, Instant, Option<&Probe>) -> bool,
+    ) -> Vec<Event> {
+        let mut keys_to_remove = Vec::new();
+
+        for (key, tracked_device) in self.devices.iter_mut() {
+            let TrackedDevice { addresses, probes, .. } = tracked_device;
+            addresses.retain(|ip, last_seen| {
+                let probe = probes.get(ip);
+                if !expired(key, ip, *last_seen, probe) {
+                    return true;
+                }
+                if probes.remove(ip).is_some_and(|probe| probe.sent > 0) {
+                    self.unanswered.insert((key.clone(), *ip));
+                }
+                false
+            });
+
+            if tracked_device.addresses.is_empty() {
+                keys_to_remove.push(key.clone());
+            } else if !tracked_device.addresses.contains_key(&tracked_device.device.ip_address) {
+                tracked_device.repick_primary();
+            }
+        }
+
+        // Report disconnections and remove from tracking
+        keys_to_remove.iter().filter_map(|key| self.remove_key(key, now)).collect()
+    }
+
+    /// Record a single sighting, e.g. from a neighbor notification. Addresses seen
+    /// within the timeout period count as still in use by the device.
+    pub fn observe(&mut self, device: Device, now: Instant) -> Vec<Event> {
+        if !self.tracks(&device) {
+            return Vec::new();
+        }
+        self.note_stale(&device, now);
+        let timeout = self.config.disconnect_timeout;
+        let events = self.sight(device, now, |_, last_seen| now.duration_since(last_seen) <= timeout);
+        self.annotate(events)
+    }
+
+    // Reports a device as connected if its MAC wasn't tracked yet, or as having
+    // changed address when it shows up with a new address while none of its previous
+    // addresses of the same family are still live. A new IPv6 address in a /64 the
+    // device already holds addresses in (a rotated temporary address) is simply
+    // added. Only sightings in one of the present
+    // states count; others just update the cached state. Present sightings are also
+    // checked for spoofing, with any alerts following the sighting's own event.
+    fn sight(&mut self, device: Device, now: Instant, is_live: impl Fn(&IpAddr, Instant) -> bool) -> Vec<Event> {
+        let mut alerts = Vec::new();
+        if self.is_present(&device, now) {
+            let new_claim = self
+                .devices
+                .get(&device.key())
+                .is_none_or(|tracked_device| !tracked_device.addresses.contains_key(&device.ip_address));
+            let timestamp = self.wall_time(now);
+            alerts = self.security.check(&device, new_claim, &self.devices, now, timestamp);
+        }
+
+        let mut events: Vec<Event> = self.sight_device(device, now, is_live).into_iter().collect();
+        events.extend(alerts);
+        events
+    }
+
+    fn sight_device(&mut self, device: Device, now: Instant, is_live: impl Fn(&IpAddr, Instant) -> bool) -> Option<Event> {
+        let key = device.key();
+        let present = self.is_present(&device, now);
+        let timestamp = self.wall_time(now);
+        let probing = self.config.probe.is_some();
+
+        if present && device.state != NeighState::Stale {
+            self.unanswered.remove(&(key.clone(), device.ip_address));
+        }
+
+        if let Some(tracked_device) = self.devices.get_mut(&key) {
+            if !present {
+                if tracked_device.device.ip_address == device.ip_address {
+                    tracked_device.device.state = device.state;
+                }
+                if probing && tracked_device.addresses.contains_key(&device.ip_address) {
+                    tracked_device.probes.entry(device.ip_address).or_default().unconfirmed = true;
+                }
+                return None;
+            }
+
+            let mut event = None;
+            if !tracked_device.addresses.contains_key(&device.ip_address) {
+                let ip = device.ip_address;
+                let group = device::address_group(&ip);
+                let same_group = tracked_device.addresses.keys().any(|held| device::address_group(held) == group);
+                let same_family: Vec<(IpAddr, Instant)> = tracked_device
+                    .addresses
+                    .iter()
+                    .filter(|(held, _)| held.is_ipv6() == ip.is_ipv6())
+                    .map(|(held, seen)| (*held, *seen))
+                    .collect();
+
+                // Additional addresses alongside live ones (e.g. several IPv6 addresses)
+                // or in a /64 the device already uses are simply added; otherwise the
+                // device moved to a new address
+                let moved = !same_group
+                    && !same_family.is_empty()
+                    && !same_family.iter().any(|(ip, seen)| is_live(ip, *seen));
+                if moved {
+                    let (old_ip, _) = same_family.iter().max_by_key(|(_, seen)| *seen)?;
+                    let mut previous = tracked_device.device.clone();
+                    previous.ip_address = *old_ip;
+                    for (ip, _) in &same_family {
+                        tracked_device.addresses.remove(ip);
+                    }
+
+                    event = Some(Event {
+                        previous: Some(previous),
+                        ..Event::new(EventKind::IpChanged, device.clone(), timestamp)
+                    });
+                }
+            }
+
+            // Keep the primary address stable unless it was replaced, is no longer held
+            // or is less preferred (e.g. IPv6 once the device shows up over IPv4)
+            let replaces_primary = event.is_some()
+                || tracked_device.device.ip_address == device.ip_address
+                || !tracked_device.addresses.contains_key(&tracked_device.device.ip_address)
+                || primary_rank(&device.ip_address) < primary_rank(&tracked_device.device.ip_address);
+
+            tracked_device.addresses.insert(device.ip_address, now);
+            tracked_device.last_seen = now;
+            // A STALE entry counted as present still has to be confirmed by a probe
+            if probing && device.state == NeighState::Stale {
+                tracked_device.probes.entry(device.ip_address).or_default().unconfirmed = true;
+            } else {
+                tracked_device.probes.remove(&device.ip_address);
+            }
+            if replaces_primary {
+                // Neighbor table entries don't erase what leases and mDNS said about the device
+                let mut device = device;
+                device.inherit_details(&tracked_device.device);
+                tracked_device.device = device;
+            } else {
+                tracked_device.device.add_sources(&device.sources);
+            }
+            return event;
+        }
+
+        // A device that isn't tracked yet only connects once it is actually present
+        if !present {
+            return None;
+        }
+
+        let event = Event::new(EventKind::Connected, device.clone(), timestamp);
+
+        // Track the device with current time
+        self.devices.insert(key, TrackedDevice::new(device, now));
+
+        // Update stats for new device and peak concurrent devices count
+        self.stats.total_devices_seen += 1;
+        if self.devices.len() > self.stats.peak_concurrent_devices {
+            self.stats.peak_concurrent_devices = self.devices.len();
+        }
+
+        Some(event)
+    }
+
+    /// Start tracking devices known from a previous session without reporting
+    /// them as connected again; they still disconnect if they don't show up
+    pub fn restore(&mut self, devices: Vec<Device>, now: Instant) {
+        let devices: Vec<Device> = devices.into_iter().filter(|d| self.tracks(d)).collect();
+        for device in devices {
+            match self.devices.get_mut(&device.key()) {
+                Some(tracked_device) => {
+                    tracked_device.addresses.insert(device.ip_address, now);
+                }
+                None => {
+                    self.devices.insert(device.key(), TrackedDevice::new(device, now));
+                }
+            }
+        }
+    }
+
+    /// Drop this address from devices on this interface, e.g. after the kernel
+    /// deleted its neighbor entry. Devices left without any address disconnect.
+    /// With probing, the address is probed first and only dropped if it doesn't answer.
+    pub fn remove(&mut self, ip_address: IpAddr, interface: &str, now: Instant) -> Vec<Event> {
+        if self.config.probe.is_some() {
+            self.mark_unconfirmed(ip_address, interface);
+            return Vec::new();
+        }
+
+        let mut keys_to_remove = Vec::new();
+
+        for (key, tracked_device) in self.devices.iter_mut() {
+            if tracked_device.device.interface != interface
+                || tracked_device.addresses.remove(&ip_address).is_none()
+            {
+                continue;
+            }
+
+            if tracked_device.addresses.is_empty() {
+                keys_to_remove.push(key.clone());
+            } else if tracked_device.device.ip_address == ip_address {
+                tracked_device.repick_primary();
+            }
+        }
+
+        let events = keys_to_remove.iter().filter_map(|key| self.remove_key(key, now)).collect();
+        self.annotate(events)
+    }
+
+    /// Update the cached state of matching devices without refreshing last_seen,
+    /// e.g. when their neighbor entry became INCOMPLETE or FAILED
+    pub fn set_state(&mut self, ip_address: IpAddr, interface: &str, state: NeighState) {
+        for (key, tracked_device) in self.devices.iter_mut() {
+            if tracked_device.device.interface != interface {
+                continue;
+            }
+            if tracked_device.device.ip_address == ip_address {
+                tracked_device.device.state = state;
+            }
+            // The entry isn't STALE anymore, so going STALE again starts a new grace
+            self.stale_since.remove(&(key.clone(), ip_address));
+        }
+        if self.config.probe.is_some() {
+            self.mark_unconfirmed(ip_address, interface);
+        }
+    }
+
+    // Have this address probed at the next opportunity
+    fn mark_unconfirmed(&mut self, ip_address: IpAddr, interface: &str) {
+        for tracked_device in self.devices.values_mut() {
+            if tracked_device.device.interface == interface && tracked_device.addresses.contains_key(&ip_address) {
+                tracked_device.probes.entry(ip_address).or_default().unconfirmed = true;
+            }
+        }
+    }
+
+    fn remove_key(&mut self, key: &str, now: Instant) -> Option<Event> {
+        let tracked_device = self.devices.remove(key)?;
+        Some(Event::new(EventKind::Disconnected, tracked_device.device, self.wall_time(now)))
+    }
+
+    fn annotate(&self, mut events: Vec<Event>) -> Vec<Event> {
+        for event in events.iter_mut() {
+            // Details from earlier lease and mDNS sightings of the device
+            if let Some(tracked) = self.devices.get(&event.device.key()) {
+                event.device.inherit_details(&tracked.device);
+            }
+            self.inventory.annotate(event, self.config.alert_unknown);
+            self.hostnames.annotate(event);
+        }
+        events
+    }
+
+    /// Convert a tracker instant to wall clock time
+    pub fn wall_time(&self, instant: Instant) -> DateTime<Local> {
+        let elapsed = instant.saturating_duration_since(self.stats.start_time);
+        self.start_wall + chrono::Duration::from_std(elapsed).unwrap_or_default()
+    }
+}
+
+// Preference for a device's primary address, lowest first: IPv4, then global
+// IPv6, then link-local IPv6
+fn primary_rank(ip: &IpAddr) -> u8 {
+    match ip {
+        IpAddr::V4(_) => 0,
+        ip if !device::is_link_local(ip) => 1,
+        _ => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(ip: &str, mac: &str, state: NeighState) -> Device {
+        Device::new(ip.parse().unwrap(), mac.parse().unwrap(), "eth0".to_string()).with_state(state)
+    }
+
+    fn laptop(ip: &str) -> Device {
+        device(ip, "02:00:00:00:00:01", NeighState::Reachable)
+    }
+
+    fn tracker() -> (Tracker, Instant) {
+        let start = Instant::now();
+        (Tracker::with_origin(TrackerConfig::default(), start, Local::now()), start)
+    }
+
+    fn at(start: Instant, millis: u64) -> Instant {
+        start + Duration::from_millis(millis)
+    }
+
+    fn kinds(events: &[Event]) -> Vec<EventKind> {
+        events.iter().map(|event| event.kind).collect()
+    }
+
+    #[test]
+    fn present_sightings_connect_once() {
+        let (mut tracker, start) = tracker();
+        let events = tracker.update(vec![laptop("192.168.1.10")], start);
+        assert_eq!(kinds(&events), [EventKind::Connected]);
+        assert_eq!(events[0].device.ip_address.to_string(), "192.168.1.10");
+
+        assert!(tracker.update(vec![laptop("192.168.1.10")], at(start, 1000)).is_empty());
+        assert!(tracker.observe(laptop("192.168.1.10"), at(start, 2000)).is_empty());
+        assert_eq!(tracker.stats().total_devices_seen, 1);
+        assert_eq!(tracker.stats().peak_concurrent_devices, 1);
+    }
+
+    #[test]
+    fn absent_states_never_connect() {
+        let (mut tracker, start) = tracker();
+        let sightings = vec![
+            device("192.168.1.20", "02:00:00:00:00:20", NeighState::Stale),
+            device("192.168.1.21", "02:00:00:00:00:21", NeighState::Failed),
+            device("192.168.1.22", "02:00:00:00:00:22", NeighState::Incomplete),
+        ];
+        assert!(tracker.update(sightings, start).is_empty());
+        assert_eq!(tracker.devices().count(), 0);
+    }
+
+    #[test]
+    fn disconnect_only_after_the_timeout_has_passed() {
+        let (mut tracker, start) = tracker();
+        tracker.update(vec![laptop("192.168.1.10")], start);
+
+        assert!(tracker.update(Vec::new(), at(start, 10_000)).is_empty());
+        let events = tracker.update(Vec::new(), at(start, 10_001));
+        assert_eq!(kinds(&events), [EventKind::Disconnected]);
+        assert_eq!(tracker.devices().count(), 0);
+
+        // A returning device connects again
+        let events = tracker.update(vec![laptop("192.168.1.10")], at(start, 12_000));
+        assert_eq!(kinds(&events), [EventKind::Connected]);
+        assert_eq!(tracker.stats().total_devices_seen, 2);
+    }
+
+    #[test]
+    fn present_sightings_postpone_the_disconnect() {
+        let (mut tracker, start) = tracker();
+        tracker.update(vec![laptop("192.168.1.10")], start);
+        tracker.update(vec![laptop("192.168.1.10")], at(start, 8000));
+        assert!(tracker.update(Vec::new(), at(start, 18_000)).is_empty());
+        assert_eq!(kinds(&tracker.update(Vec::new(), at(start, 18_001))), [EventKind::Disconnected]);
+    }
+
+    #[test]
+    fn new_address_without_the_old_one_is_an_ip_change() {
+        let (mut tracker, start) = tracker();
+        tracker.update(vec![laptop("192.168.1.10")], start);
+
+        let events = tracker.update(vec![laptop("192.168.1.11")], at(start, 1000));
+        assert_eq!(kinds(&events), [EventKind::IpChanged]);
+        assert_eq!(events[0].device.ip_address.to_string(), "192.168.1.11");
+        assert_eq!(events[0].previous.as_ref().unwrap().ip_address.to_string(), "192.168.1.10");
+
+        let tracked = tracker.devices().next().unwrap();
+        assert_eq!(tracked.addresses.keys().map(IpAddr::to_string).collect::<Vec<_>>(), ["192.168.1.11"]);
+    }
+
+    #[test]
+    fn observed_address_is_added_while_the_old_one_is_live() {
+        let (mut tracker, start) = tracker();
+        tracker.observe(laptop("192.168.1.10"), start);
+
+        assert!(tracker.observe(laptop("192.168.1.11"), at(start, 10_000)).is_empty());
+        assert_eq!(tracker.devices().next().unwrap().addresses.len(), 2);
+
+        // Once neither has been seen within the timeout, the next address replaces both
+        let events = tracker.observe(laptop("192.168.1.12"), at(start, 20_001));
+        assert_eq!(kinds(&events), [EventKind::IpChanged]);
+        assert_eq!(events[0].previous.as_ref().unwrap().ip_address.to_string(), "192.168.1.11");
+        assert_eq!(tracker.devices().next().unwrap().addresses.len(), 1);
+    }
+
+    #[test]
+    fn addresses_in_a_held_64_are_added() {
+        let (mut tracker, start) = tracker();
+        tracker.update(vec![laptop("2001:db8:1:2::10")], start);
+
+        // A rotated temporary address in the same /64
+        assert!(tracker.update(vec![laptop("2001:db8:1:2:abcd::1")], at(start, 1000)).is_empty());
+        assert_eq!(tracker.devices().next().unwrap().addresses.len(), 2);
+
+        // Another /64 replaces them
+        let events = tracker.update(vec![laptop("2001:db8:1:3::10")], at(start, 2000));
+        assert_eq!(kinds(&events), [EventKind::IpChanged]);
+        assert_eq!(tracker.devices().next().unwrap().addresses.len(), 1);
+    }
+
+    #[test]
+    fn other_family_is_added_and_preferred_as_primary() {
+        let (mut tracker, start) = tracker();
+        tracker.update(vec![laptop("fe80::1")], start);
+        assert!(tracker.update(vec![laptop("fe80::1"), laptop("192.168.1.10")], at(start, 1000)).is_empty());
+
+        let tracked = tracker.devices().next().unwrap();
+        assert_eq!(tracked.addresses.len(), 2);
+        assert_eq!(tracked.device.ip_address.to_string(), "192.168.1.10");
+
+        // Dropping the primary address makes the remaining one primary
+        assert!(tracker.remove("192.168.1.10".parse().unwrap(), "eth0", at(start, 2000)).is_empty());
+        assert_eq!(tracker.devices().next().unwrap().device.ip_address.to_string(), "fe80::1");
+        let events = tracker.remove("fe80::1".parse().unwrap(), "eth0", at(start, 3000));
+        assert_eq!(kinds(&events), [EventKind::Disconnected]);
+    }
+
+    #[test]
+    fn removal_on_another_interface_is_ignored() {
+        let (mut tracker, start) = tracker();
+        tracker.update(vec![laptop("192.168.1.10")], start);
+        assert!(tracker.remove("192.168.1.10".parse().unwrap(), "wlan0", start).is_empty());
+        assert_eq!(tracker.devices().count(), 1);
+    }
+
+    #[test]
+    fn stale_entries_count_as_present_for_the_stale_timeout() {
+        let (mut tracker, start) = tracker();
+        tracker.update(vec![laptop("192.168.1.10")], start);
+
+        let stale = || device("192.168.1.10", "02:00:00:00:00:01", NeighState::Stale);
+        tracker.update(vec![stale()], at(start, 5000));
+        assert!(tracker.update(vec![stale()], at(start, 65_000)).is_empty());
+        assert!(tracker.is_present(&stale(), at(start, 65_000)));
+        assert!(!tracker.is_present(&stale(), at(start, 65_001)));
+
+        // Last seen present at 65s, so the device disconnects once 10s have passed since
+        assert!(tracker.update(vec![stale()], at(start, 75_000)).is_empty());
+        assert_eq!(kinds(&tracker.update(vec![stale()], at(start, 75_001))), [EventKind::Disconnected]);
+    }
+
+    #[test]
+    fn reachable_again_restarts_the_stale_grace() {
+        let (mut tracker, start) = tracker();
+        let stale = || device("192.168.1.10", "02:00:00:00:00:01", NeighState::Stale);
+        tracker.update(vec![laptop("192.168.1.10")], start);
+        tracker.update(vec![stale()], at(start, 1000));
+        tracker.update(vec![laptop("192.168.1.10")], at(start, 50_000));
+        tracker.update(vec![stale()], at(start, 55_000));
+        assert!(tracker.is_present(&stale(), at(start, 100_000)));
+    }
+
+    #[test]
+    fn filters_decide_which_sightings_are_tracked() {
+        let start = Instant::now();
+        let config = TrackerConfig { link_local: false, interface: Some("eth0".to_string()), ..TrackerConfig::default() };
+        let mut tracker = Tracker::with_origin(config, start, Local::now());
+
+        let elsewhere = Device { interface: "wlan0".to_string(), ..laptop("192.168.1.10") };
+        let sightings = vec![laptop("169.254.3.4"), laptop("fe80::1"), elsewhere, laptop("224.0.0.251")];
+        assert!(tracker.update(sightings, start).is_empty());
+        assert_eq!(kinds(&tracker.update(vec![laptop("192.168.1.10")], start)), [EventKind::Connected]);
+
+        // Addresses a new configuration excludes are forgotten without events
+        let ipv6_only = TrackerConfig { family: AddressFamily::Inet6, ..TrackerConfig::default() };
+        assert_eq!(tracker.set_config(ipv6_only), 1);
+        assert!(tracker.update(Vec::new(), at(start, 60_000)).is_empty());
+    }
+
+    #[test]
+    fn restored_devices_disconnect_without_connecting() {
+        let (mut tracker, start) = tracker();
+        tracker.restore(vec![laptop("192.168.1.10")], start);
+        assert!(tracker.update(vec![laptop("192.168.1.10")], at(start, 1000)).is_empty());
+        assert_eq!(kinds(&tracker.update(Vec::new(), at(start, 11_001))), [EventKind::Disconnected]);
+    }
+
+    #[test]
+    fn spoofed_address_follows_the_connect() {
+        let (mut tracker, start) = tracker();
+        tracker.update(vec![laptop("192.168.1.10")], start);
+        let intruder = device("192.168.1.10", "02:00:00:00:00:66", NeighState::Reachable);
+        let events = tracker.update(vec![laptop("192.168.1.10"), intruder], at(start, 1000));
+        assert_eq!(kinds(&events), [EventKind::Connected, EventKind::Security]);
+        assert_eq!(events[1].alert, Some(SecurityAlert::IpConflict));
+    }
+
+    #[test]
+    fn wall_time_follows_the_origin() {
+        let start = Instant::now();
+        let wall = Local::now();
+        let tracker = Tracker::with_origin(TrackerConfig::default(), start, wall);
+        assert_eq!(tracker.wall_time(at(start, 1500)), wall + chrono::Duration::milliseconds(1500));
+        assert_eq!(tracker.wall_time(start - Duration::from_secs(1)), wall);
+    }
+}
@@ -0,0 +1,416 @@
+// Full-screen dashboard: live device table, event log, interface filter and search
+
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+use ratatui::crossterm::event::{self, Event as TermEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::api::EventBuffer;
+use crate::device::{Device, MacAddr};
+use crate::diag;
+use crate::output::get_vendor_from_mac;
+use crate::tracker::{Event, EventKind, Tracker};
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortColumn {
+    Ip,
+    Mac,
+    Vendor,
+    Interface,
+    State,
+    FirstSeen,
+    LastSeen,
+    Online,
+}
+
+impl SortColumn {
+    const ALL: [SortColumn; 8] = [
+        SortColumn::Ip,
+        SortColumn::Mac,
+        SortColumn::Vendor,
+        SortColumn::Interface,
+        SortColumn::State,
+        SortColumn::FirstSeen,
+        SortColumn::LastSeen,
+        SortColumn::Online,
+    ];
+
+    fn title(&self) -> &'static str {
+        match self {
+            SortColumn::Ip => "IP",
+            SortColumn::Mac => "MAC",
+            SortColumn::Vendor => "Vendor",
+            SortColumn::Interface => "Interface",
+            SortColumn::State => "State",
+            SortColumn::FirstSeen => "First seen",
+            SortColumn::LastSeen => "Last seen",
+            SortColumn::Online => "Online",
+        }
+    }
+
+    fn index(&self) -> usize {
+        SortColumn::ALL.iter().position(|column| column == self).unwrap_or(0)
+    }
+}
+
+// One table row, taken from the tracker on every refresh
+struct DeviceRow {
+    ip: IpAddr,
+    addresses: Vec<IpAddr>,
+    mac: MacAddr,
+    vendor: String,
+    interface: String,
+    state: &'static str,
+    first_seen: DateTime<Local>,
+    last_seen: DateTime<Local>,
+    online: Duration,
+}
+
+struct App {
+    tracker: Arc<Mutex<Tracker>>,
+    events: Arc<EventBuffer>,
+    sort: SortColumn,
+    descending: bool,
+    interface: Option<String>,
+    search: String,
+    editing_search: bool,
+    table: TableState,
+    // Vendor lookups are cached since every row is rebuilt on each refresh
+    vendors: HashMap<MacAddr, Option<String>>,
+}
+
+/// Run the dashboard on the current terminal until the user quits. Devices come
+/// from `tracker` and the event log from `events`, both fed by the monitoring loop.
+pub fn run(tracker: Arc<Mutex<Tracker>>, events: Arc<EventBuffer>) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let mut app = App {
+        tracker,
+        events,
+        sort: SortColumn::LastSeen,
+        descending: true,
+        interface: None,
+        search: String::new(),
+        editing_search: false,
+        table: TableState::default(),
+        vendors: HashMap::new(),
+    };
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl App {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if event::poll(REFRESH_INTERVAL)?
+                && let TermEvent::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !self.handle_key(key)
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    // Returns false when the user asked to quit
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return false;
+        }
+
+        if self.editing_search {
+            match key.code {
+                KeyCode::Char(c) => self.search.push(c),
+                KeyCode::Backspace => {
+                    self.search.pop();
+                }
+                KeyCode::Enter => self.editing_search = false,
+                KeyCode::Esc => {
+                    self.search.clear();
+                    self.editing_search = false;
+                }
+                _ => {}
+            }
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('/') => self.editing_search = true,
+            KeyCode::Char('s') => self.sort = SortColumn::ALL[(self.sort.index() + 1) % SortColumn::ALL.len()],
+            KeyCode::Char('r') => self.descending = !self.descending,
+            KeyCode::Char(c @ '1'..='8') => {
+                let column = SortColumn::ALL[c as usize - '1' as usize];
+                if column == self.sort {
+                    self.descending = !self.descending;
+                } else {
+                    self.sort = column;
+                }
+            }
+            KeyCode::Char('i') => self.next_interface(),
+            KeyCode::Down | KeyCode::Char('j') => self.table.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.table.select_previous(),
+            _ => {}
+        }
+        true
+    }
+
+    // Cycle the interface filter: all interfaces, then each known interface in turn
+    fn next_interface(&mut self) {
+        let interfaces: BTreeSet<String> = self
+            .tracker
+            .lock()
+            .unwrap()
+            .devices()
+            .map(|tracked| tracked.device.interface.clone())
+            .chain(self.interface.clone())
+            .collect();
+
+        self.interface = match &self.interface {
+            None => interfaces.into_iter().next(),
+            Some(current) => interfaces.into_iter().find(|interface| interface > current),
+        };
+    }
+
+    fn matches(&self, device: &Device, vendor: &str, addresses: &[IpAddr]) -> bool {
+        if self.interface.as_ref().is_some_and(|interface| *interface != device.interface) {
+            return false;
+        }
+        if self.search.is_empty() {
+            return true;
+        }
+        let search = self.search.to_lowercase();
+        addresses.iter().any(|ip| ip.to_string().contains(&search))
+            || device.ip_address.to_string().contains(&search)
+            || device.mac_address.to_string().contains(&search)
+            || vendor.to_lowercase().contains(&search)
+            || device.interface.to_lowercase().contains(&search)
+    }
+
+    fn vendor(&mut self, device: &Device, name: Option<&str>) -> String {
+        let vendor = self
+            .vendors
+            .entry(device.mac_address)
+            .or_insert_with(|| get_vendor_from_mac(&device.mac_address))
+            .as_deref()
+            .unwrap_or("Unknown");
+        match name {
+            Some(name) => format!("{} ({})", name, vendor),
+            None => vendor.to_string(),
+        }
+    }
+
+    fn rows(&mut self) -> (Vec<DeviceRow>, usize) {
+        let tracker = Arc::clone(&self.tracker);
+        let tracker = tracker.lock().unwrap();
+        let now = Instant::now();
+        let mut total = 0;
+
+        let mut rows = Vec::new();
+        for tracked in tracker.devices() {
+            total += 1;
+            let device = &tracked.device;
+            let hostname = tracker.hostnames().lookup(device);
+            let name = tracker
+                .inventory()
+                .get(&device.mac_address)
+                .and_then(|entry| entry.name.as_deref())
+                .or(hostname.as_deref());
+            let vendor = self.vendor(device, name);
+            let addresses: Vec<IpAddr> = tracked.addresses.keys().copied().collect();
+            if !self.matches(device, &vendor, &addresses) {
+                continue;
+            }
+
+            rows.push(DeviceRow {
+                ip: device.ip_address,
+                addresses,
+                mac: device.mac_address,
+                vendor,
+                interface: device.interface.clone(),
+                state: device.state.as_str(),
+                first_seen: tracker.wall_time(tracked.first_seen),
+                last_seen: tracker.wall_time(tracked.last_seen),
+                online: now.saturating_duration_since(tracked.first_seen),
+            });
+        }
+
+        rows.sort_by(|a, b| {
+            let ordering = match self.sort {
+                SortColumn::Ip => a.ip.cmp(&b.ip),
+                SortColumn::Mac => a.mac.cmp(&b.mac),
+                SortColumn::Vendor => a.vendor.cmp(&b.vendor),
+                SortColumn::Interface => a.interface.cmp(&b.interface),
+                SortColumn::State => a.state.cmp(b.state),
+                SortColumn::FirstSeen => a.first_seen.cmp(&b.first_seen),
+                SortColumn::LastSeen => a.last_seen.cmp(&b.last_seen),
+                SortColumn::Online => a.online.cmp(&b.online),
+            };
+            if self.descending { ordering.reverse() } else { ordering }
+        });
+
+        (rows, total)
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let diagnostics = diag::recent();
+        let [status_area, table_area, log_area, diag_area, help_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(5),
+            Constraint::Length(12),
+            Constraint::Length(u16::from(!diagnostics.is_empty())),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let (rows, total) = self.rows();
+
+        // Status line: counts, active filters and search box
+        let search = if self.editing_search {
+            format!("/{}_", self.search)
+        } else if self.search.is_empty() {
+            "-".to_string()
+        } else {
+            self.search.clone()
+        };
+        let status = Line::from(vec![
+            Span::styled(" NetNeighbor ", Style::new().fg(Color::Black).bg(Color::Yellow).add_modifier(Modifier::BOLD)),
+            Span::raw(format!(" Devices: {}/{} ", rows.len(), total)),
+            Span::raw(format!("| Interface: {} ", self.interface.as_deref().unwrap_or("all"))),
+            Span::raw(format!("| Sort: {} {} ", self.sort.title(), if self.descending { "↓" } else { "↑" })),
+            Span::raw("| Search: "),
+            Span::styled(search, Style::new().fg(Color::Cyan)),
+        ]);
+        frame.render_widget(Paragraph::new(status), status_area);
+
+        // Device table
+        let header = Row::new(SortColumn::ALL.iter().enumerate().map(|(i, column)| {
+            let title = format!("{} {}", i + 1, column.title());
+            if *column == self.sort {
+                Span::styled(title, Style::new().fg(Color::Yellow).add_modifier(Modifier::BOLD | Modifier::UNDERLINED))
+            } else {
+                Span::styled(title, Style::new().add_modifier(Modifier::BOLD))
+            }
+        }));
+        let table_rows = rows.iter().map(|row| {
+            let ip = match row.addresses.len() {
+                0 | 1 => row.ip.to_string(),
+                n => format!("{} (+{})", row.ip, n - 1),
+            };
+            Row::new(vec![
+                Span::styled(ip, Style::new().fg(Color::Blue)),
+                Span::styled(row.mac.to_string(), Style::new().fg(Color::Yellow)),
+                Span::styled(row.vendor.clone(), Style::new().fg(Color::Cyan)),
+                Span::styled(row.interface.clone(), Style::new().fg(Color::Magenta)),
+                Span::raw(row.state),
+                Span::raw(row.first_seen.format("%m-%d %H:%M:%S").to_string()),
+                Span::raw(row.last_seen.format("%m-%d %H:%M:%S").to_string()),
+                Span::raw(format_duration(row.online)),
+            ])
+        });
+        let widths = [
+            Constraint::Length(24),
+            Constraint::Length(19),
+            Constraint::Fill(1),
+            Constraint::Length(11),
+            Constraint::Length(11),
+            Constraint::Length(16),
+            Constraint::Length(16),
+            Constraint::Length(10),
+        ];
+        let table = Table::new(table_rows, widths)
+            .header(header)
+            .block(Block::bordered().title(" Devices "))
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, table_area, &mut self.table);
+
+        // Event log, newest at the bottom, with the same filters as the table
+        let capacity = log_area.height.saturating_sub(2) as usize;
+        let mut lines = Vec::new();
+        for event in self.events.since(None).iter().rev() {
+            if lines.len() == capacity {
+                break;
+            }
+            let vendor = self.vendor(&event.device, None);
+            if self.matches(&event.device, &vendor, &[]) {
+                lines.push(event_line(event));
+            }
+        }
+        lines.reverse();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Events ")), log_area);
+
+        // The latest diagnostic from background work, which would otherwise garble the screen;
+        // all of them are printed once the dashboard closes
+        if let Some(latest) = diagnostics.last() {
+            let count = match diagnostics.len() {
+                1 => String::new(),
+                count => format!(" ({} warnings, shown on exit)", count),
+            };
+            let line = Line::from(vec![
+                Span::styled(format!(" {} ", latest.timestamp.format("%H:%M:%S")), Style::new().fg(Color::Black).bg(Color::Red)),
+                Span::styled(format!(" {}{}", latest.message, count), Style::new().fg(Color::Red)),
+            ]);
+            frame.render_widget(Paragraph::new(line), diag_area);
+        }
+
+        let help = "q quit  / search  i interface  s/1-8 sort  r reverse  ↑↓ select";
+        frame.render_widget(Paragraph::new(help).style(Style::new().fg(Color::DarkGray)), help_area);
+    }
+}
+
+fn event_line(event: &Event) -> Line<'static> {
+    let (label, color) = match event.kind {
+        EventKind::Connected => ("CONNECTED", Color::Green),
+        EventKind::Disconnected => ("DISCONNECTED", Color::Red),
+        EventKind::IpChanged => ("IP CHANGED", Color::Yellow),
+        EventKind::Security => ("SECURITY", Color::LightRed),
+    };
+    let device = &event.device;
+
+    let mut detail = match (&event.previous, event.kind) {
+        (Some(previous), EventKind::IpChanged) => format!("{} -> {}", previous.ip_address, device.ip_address),
+        (Some(previous), EventKind::Security) => {
+            format!("{} was {}, now {}", device.ip_address, previous.mac_address, device.mac_address)
+        }
+        _ => format!("{} {}", device.ip_address, device.mac_address),
+    };
+    if let Some(alert) = event.alert {
+        detail = format!("{}: {}", alert.description(), detail);
+    }
+    if let Some(name) = event.inventory.as_ref().and_then(|entry| entry.name.as_deref()).or(event.hostname.as_deref()) {
+        detail.push_str(&format!(" ({})", name));
+    }
+    if event.unknown {
+        detail.push_str(" [unknown device]");
+    }
+
+    Line::from(vec![
+        Span::raw(format!("{} ", event.timestamp.format("%H:%M:%S"))),
+        Span::styled(format!("{:<12} ", label), Style::new().fg(color).add_modifier(Modifier::BOLD)),
+        Span::raw(format!("{} on {}", detail, device.interface)),
+    ])
+}
+
+// Order IPv4 numerically and before IPv6
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        3600..86400 => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+        _ => format!("{}d{:02}h", secs / 86400, secs % 86400 / 3600),
+    }
+}
@@ -0,0 +1,324 @@
+// Webhook sink: POSTs events to an HTTP(S) endpoint from a background thread
+
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::diag;
+use crate::output::{format_event_json, get_vendor_from_mac, service_names};
+use crate::sink::EventSink;
+use crate::tracker::{Event, EventKind};
+
+// Client errors that may succeed later: request timeout and rate limiting
+const RETRIED_CLIENT_ERRORS: [u16; 2] = [408, 429];
+// Longest Retry-After wait honored, so one endpoint can't stall deliveries for hours
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Event kinds to deliver; empty delivers every event
+    pub events: Vec<EventKind>,
+    /// Request body with `{{field}}` placeholders; the JSON event record when unset
+    pub template: Option<String>,
+    /// Events waiting for delivery beyond this are dropped
+    pub queue_size: usize,
+    /// Retries after a failed delivery, with exponential backoff
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub timeout: Duration,
+}
+
+impl WebhookConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        WebhookConfig {
+            url: url.into(),
+            events: Vec::new(),
+            template: None,
+            queue_size: 256,
+            max_retries: 3,
+            initial_backoff: Duration::from_secs(1),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Queues events for delivery by a worker thread, so a slow or unreachable
+/// endpoint never blocks the monitoring loop. Events are dropped when the queue is full.
+pub struct WebhookSink {
+    events: Vec<EventKind>,
+    template: Option<String>,
+    sender: SyncSender<String>,
+    dropped: u64,
+}
+
+impl WebhookSink {
+    pub fn new(config: WebhookConfig) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<String>(config.queue_size);
+        let agent = ureq::AgentBuilder::new().timeout(config.timeout).build();
+        let url = config.url.clone();
+        let max_retries = config.max_retries;
+        let initial_backoff = config.initial_backoff;
+
+        thread::spawn(move || {
+            for body in receiver {
+                deliver(&agent, &url, &body, max_retries, initial_backoff);
+            }
+        });
+
+        WebhookSink { events: config.events, template: config.template, sender, dropped: 0 }
+    }
+
+    fn render(&self, event: &Event) -> String {
+        match &self.template {
+            Some(template) => render_template(template, event),
+            None => format_event_json(event),
+        }
+    }
+}
+
+impl EventSink for WebhookSink {
+    fn emit(&mut self, event: &Event) {
+        if !self.events.is_empty() && !self.events.contains(&event.kind) {
+            return;
+        }
+
+        match self.sender.try_send(self.render(event)) {
+            Ok(()) => self.dropped = 0,
+            Err(TrySendError::Full(_)) => {
+                // Only report the start of a run of dropped events
+                if self.dropped == 0 {
+                    diag::warn("Webhook queue full, dropping events until the endpoint catches up");
+                }
+                self.dropped += 1;
+            }
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+}
+
+// POST one body, retrying transport errors, server errors, timeouts and rate
+// limiting with exponential backoff, or after the delay the server asked for
+fn deliver(agent: &ureq::Agent, url: &str, body: &str, max_retries: u32, initial_backoff: Duration) {
+    let mut backoff = initial_backoff;
+    for attempt in 0..=max_retries {
+        let result = agent
+            .post(url)
+            .set("Content-Type", "application/json")
+            .send_string(body);
+
+        let error = match result {
+            Ok(_) => return,
+            // Other client errors won't succeed on retry
+            Err(ureq::Error::Status(status, _)) if status < 500 && !RETRIED_CLIENT_ERRORS.contains(&status) => {
+                diag::warn(&format!("Webhook rejected event: HTTP {}", status));
+                return;
+            }
+            Err(e) => e,
+        };
+
+        if attempt == max_retries {
+            diag::warn(&format!("Webhook delivery failed after {} attempts: {}", attempt + 1, error));
+            return;
+        }
+        let retry_after = match &error {
+            ureq::Error::Status(_, response) => response.header("Retry-After").and_then(|value| retry_after(value, Utc::now())),
+            ureq::Error::Transport(_) => None,
+        };
+        thread::sleep(retry_after.unwrap_or(backoff));
+        backoff *= 2;
+    }
+}
+
+// A Retry-After header, in seconds or as an HTTP date, as the delay from `now`
+fn retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    let delay = match value.parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => {
+            let date = DateTime::parse_from_rfc2822(value).ok()?;
+            (date.with_timezone(&Utc) - now).to_std().unwrap_or_default()
+        }
+    };
+    Some(delay.min(MAX_RETRY_AFTER))
+}
+
+/// Substitute `{{field}}` placeholders with event fields. Values are JSON string
+/// escaped so they can be placed inside quoted strings of a JSON template:
+/// event, timestamp, ip, previous_ip, mac, previous_mac, vendor, hostname,
+/// services (comma separated service names), interface, state, alert, name,
+/// owner, tags and text (a one-line human readable summary).
+pub fn render_template(template: &str, event: &Event) -> String {
+    let device = &event.device;
+    let vendor = get_vendor_from_mac(&device.mac_address);
+    let inventory = event.inventory.as_ref();
+
+    let fields: [(&str, String); 16] = [
+        ("event", event.kind.as_str().to_string()),
+        ("timestamp", event.timestamp.to_rfc3339_opts(SecondsFormat::Secs, false)),
+        ("ip", device.ip_address.to_string()),
+        ("previous_ip", event.previous.as_ref().map(|p| p.ip_address.to_string()).unwrap_or_default()),
+        ("mac", device.mac_address.to_string()),
+        ("previous_mac", event.previous.as_ref().map(|p| p.mac_address.to_string()).unwrap_or_default()),
+        ("vendor", vendor.clone().unwrap_or_else(|| "Unknown".to_string())),
+        ("hostname", event.hostname.clone().unwrap_or_default()),
+        ("services", service_names(&device.services)),
+        ("interface", device.interface.clone()),
+        ("state", device.state.as_str().to_string()),
+        ("alert", event.alert.map(|alert| alert.as_str().to_string()).unwrap_or_default()),
+        ("name", inventory.and_then(|entry| entry.name.clone()).unwrap_or_default()),
+        ("owner", inventory.and_then(|entry| entry.owner.clone()).unwrap_or_default()),
+        ("tags", inventory.map(|entry| entry.tags.join(", ")).unwrap_or_default()),
+        ("text", summary_text(event, vendor.as_deref())),
+    ];
+
+    let mut body = template.to_string();
+    for (name, value) in &fields {
+        body = body.replace(&format!("{{{{{}}}}}", name), &json_escape(value));
+    }
+    body
+}
+
+// e.g. "CONNECTED 192.168.1.40 (c8:a3:62:67:99:b2, Office printer) on wlo1"
+fn summary_text(event: &Event, vendor: Option<&str>) -> String {
+    let device = &event.device;
+    let label = event
+        .inventory
+        .as_ref()
+        .and_then(|entry| entry.name.as_deref())
+        .or(event.hostname.as_deref())
+        .or(vendor)
+        .unwrap_or("Unknown");
+
+    let mut text = format!("{} {} ({}, {}) on {}",
+                           event.kind.as_str(),
+                           device.ip_address,
+                           device.mac_address,
+                           label,
+                           device.interface);
+    if let Some(alert) = event.alert {
+        text = format!("{}: {}", text, alert.description());
+    }
+    if let Some(previous) = &event.previous {
+        if previous.ip_address != device.ip_address {
+            text.push_str(&format!(", was {}", previous.ip_address));
+        }
+        if previous.mac_address != device.mac_address {
+            text.push_str(&format!(", was {}", previous.mac_address));
+        }
+    }
+    if event.unknown {
+        text.push_str(" [unknown device]");
+    }
+    text
+}
+
+// Escape a value for use inside a JSON string literal
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::to_string(value).expect("strings are always serializable");
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Local, TimeZone};
+
+    use crate::device::{Device, NeighState};
+    use crate::inventory::InventoryEntry;
+    use crate::security::SecurityAlert;
+
+    fn event(kind: EventKind) -> Event {
+        let device = Device::new("192.168.1.40".parse().unwrap(), "02:00:00:00:00:40".parse().unwrap(), "wlo1".to_string())
+            .with_state(NeighState::Reachable);
+        Event::new(kind, device, Local.with_ymd_and_hms(2026, 2, 12, 21, 26, 43).unwrap())
+    }
+
+    fn office_printer() -> InventoryEntry {
+        InventoryEntry { name: Some("Office printer".to_string()), owner: Some("IT".to_string()), tags: vec!["printer".to_string(), "2F".to_string()] }
+    }
+
+    #[test]
+    fn json_escape_keeps_values_inside_a_string() {
+        assert_eq!(json_escape("plain"), "plain");
+        assert_eq!(json_escape("say \"hi\"\\n"), "say \\\"hi\\\"\\\\n");
+        assert_eq!(json_escape("line\nbreak\ttab\u{1}"), "line\\nbreak\\ttab\\u0001");
+        assert_eq!(json_escape("café"), "café");
+    }
+
+    #[test]
+    fn template_fields_are_substituted_and_escaped() {
+        let mut event = event(EventKind::Connected);
+        event.hostname = Some("lab \"printer\"".to_string());
+        event.device.services = vec!["_ipp._tcp".to_string(), "_airplay._tcp".to_string()];
+        event.inventory = Some(office_printer());
+
+        let template = r#"{"e":"{{event}}","at":"{{timestamp}}","ip":"{{ip}}","mac":"{{mac}}","host":"{{hostname}}","svc":"{{services}}","if":"{{interface}}","state":"{{state}}","name":"{{name}}","owner":"{{owner}}","tags":"{{tags}}","vendor":"{{vendor}}"}"#;
+        let body: serde_json::Value = serde_json::from_str(&render_template(template, &event)).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "e": "CONNECTED",
+                "at": event.timestamp.to_rfc3339_opts(SecondsFormat::Secs, false),
+                "ip": "192.168.1.40",
+                "mac": "02:00:00:00:00:40",
+                "host": "lab \"printer\"",
+                "svc": "ipp, airplay",
+                "if": "wlo1",
+                "state": "REACHABLE",
+                "name": "Office printer",
+                "owner": "IT",
+                "tags": "printer, 2F",
+                "vendor": "Unknown",
+            })
+        );
+    }
+
+    #[test]
+    fn missing_fields_render_empty_and_unknown_placeholders_stay() {
+        let rendered = render_template("[{{previous_ip}}|{{previous_mac}}|{{alert}}|{{name}}|{{tags}}|{{hostname}}|{{nope}}]", &event(EventKind::Disconnected));
+        assert_eq!(rendered, "[||||||{{nope}}]");
+    }
+
+    #[test]
+    fn summary_text_prefers_the_inventory_name_then_hostname_then_vendor() {
+        let mut event = event(EventKind::Connected);
+        assert_eq!(summary_text(&event, None), "CONNECTED 192.168.1.40 (02:00:00:00:00:40, Unknown) on wlo1");
+        assert_eq!(summary_text(&event, Some("Acme")), "CONNECTED 192.168.1.40 (02:00:00:00:00:40, Acme) on wlo1");
+        event.hostname = Some("printer".to_string());
+        assert_eq!(summary_text(&event, Some("Acme")), "CONNECTED 192.168.1.40 (02:00:00:00:00:40, printer) on wlo1");
+        event.inventory = Some(office_printer());
+        event.unknown = true;
+        assert_eq!(summary_text(&event, Some("Acme")), "CONNECTED 192.168.1.40 (02:00:00:00:00:40, Office printer) on wlo1 [unknown device]");
+    }
+
+    #[test]
+    fn summary_text_names_what_was_replaced() {
+        let mut moved = event(EventKind::IpChanged);
+        moved.previous = Some(Device { ip_address: "192.168.1.39".parse().unwrap(), ..moved.device.clone() });
+        assert_eq!(summary_text(&moved, None), "IP_CHANGED 192.168.1.40 (02:00:00:00:00:40, Unknown) on wlo1, was 192.168.1.39");
+
+        let mut spoofed = event(EventKind::Security);
+        spoofed.alert = Some(SecurityAlert::IpConflict);
+        spoofed.previous = Some(Device { mac_address: "02:00:00:00:00:66".parse().unwrap(), ..spoofed.device.clone() });
+        assert_eq!(
+            summary_text(&spoofed, None),
+            "SECURITY 192.168.1.40 (02:00:00:00:00:40, Unknown) on wlo1: IP address claimed by multiple MACs, was 02:00:00:00:00:66"
+        );
+    }
+
+    #[test]
+    fn retry_after_takes_seconds_or_an_http_date() {
+        let now = Utc.with_ymd_and_hms(2026, 2, 12, 8, 49, 37).unwrap();
+        assert_eq!(retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(retry_after(" 0 ", now), Some(Duration::ZERO));
+        assert_eq!(retry_after("Thu, 12 Feb 2026 08:50:07 GMT", now), Some(Duration::from_secs(30)));
+        // Dates in the past mean now, and long waits are capped
+        assert_eq!(retry_after("Thu, 12 Feb 2026 08:00:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(retry_after("86400", now), Some(MAX_RETRY_AFTER));
+        assert_eq!(retry_after("soon", now), None);
+        assert_eq!(retry_after("-5", now), None);
+    }
+}